@@ -0,0 +1,30 @@
+// Generations per second of the CPU steppers on a dense random board
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use game_of_life_wgpu::simulation::Universe;
+use game_of_life_wgpu::simulation::packed::PackedUniverse;
+use std::hint::black_box;
+
+const SIZE: usize = 1024;
+
+fn random_universe() -> Universe {
+    let mut universe = Universe::new(SIZE, SIZE);
+    universe.fill_random(0, 0, SIZE as i64, SIZE as i64, 1);
+    universe
+}
+
+fn steppers(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group(format!("Life {SIZE}x{SIZE}"));
+    let mut universe = random_universe();
+    group.bench_function("naive", |bencher| {
+        bencher.iter(|| black_box(&mut universe).step())
+    });
+    let mut packed_universe = PackedUniverse::from_universe(&random_universe());
+    group.bench_function("packed", |bencher| {
+        bencher.iter(|| black_box(&mut packed_universe).step())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, steppers);
+criterion_main!(benches);
@@ -1,66 +1,201 @@
 use crate::camera::Camera;
+use crate::cli::{Cli, EngineKind};
+use crate::gpu_simulation::continuous::GpuContinuousSimulation;
+use crate::gpu_simulation::{self, GpuSimulation};
 use crate::graphics_context::GraphicsContext;
+use crate::pattern::{Pattern, PatternFile, create_board, rle};
+use crate::scheduler::{FrameSteps, Scheduler};
+use crate::simulation::continuous::{ContinuousRule, ContinuousUniverse};
+use crate::simulation::hashlife::HashLife;
+use crate::simulation::packed::PackedUniverse;
+use crate::simulation::rule::Neighbourhood;
+use crate::simulation::rule::topology::{Topology, TopologyKind};
+use crate::simulation::sparse::SparseUniverse;
+use crate::simulation::{ALIVE, DEAD, Universe};
+use anyhow::Context;
 use bytemuck::{Pod, Zeroable};
+use nalgebra::{Matrix4, Vector2};
+use std::time::{Instant, SystemTime};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{
-    Buffer, BufferAddress, BufferUsages, ColorTargetState, ColorWrites, FragmentState, FrontFace,
-    PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, PushConstantRange, RenderPipeline,
-    RenderPipelineDescriptor, ShaderStages, VertexAttribute, VertexBufferLayout, VertexFormat,
-    VertexState, VertexStepMode, include_wgsl,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferAddress, BufferBindingType,
+    BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, DeviceType, FragmentState,
+    FrontFace, PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, PushConstantRange,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, TextureSampleType, TextureViewDimension, VertexAttribute, VertexBufferLayout,
+    VertexFormat, VertexState, VertexStepMode, include_wgsl,
 };
+use winit::dpi::LogicalPosition;
+
+// Cell side in world units
+pub const CELL_SIZE: f32 = 8.;
+
+const INITIAL_PATTERN: &str = "#N R-pentomino\nx = 3, y = 3, rule = B3/S23\nb2o$2ob$bo!";
+
+// Universes at least this large are simulated by the compute shader
+const GPU_ENGINE_MIN_CELLS: usize = 4096 * 4096;
+
+// Vertex of the unit quad
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy)]
+struct Vertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+}
+
+// Per-instance data of the cell quad
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy)]
+struct CellInstance {
+    // In cells, relative to the universe center
+    position: [f32; 2],
+    state: u32,
+}
+
+// Vertex of the edge lines of a bounded grid
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy)]
+struct EdgeVertex {
+    // In cells, relative to the universe center
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+// Colors of the edges of a bounded grid
+const PLANE_EDGE_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.];
+const JOINED_EDGE_COLOR: [f32; 4] = [0.2, 0.5, 1., 1.];
+const TWISTED_EDGE_COLOR: [f32; 4] = [1., 0.55, 0., 1.];
+const SPHERE_EDGE_COLOR: [f32; 4] = [0.7, 0.3, 1., 1.];
+
+const GRID_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.];
+// Smaller cells on the screen hide the grid, it would cover them
+const GRID_MIN_CELL_PIXELS: f32 = 4.;
+
+/// Outline of the cells within [min, max), drawn over the universe
+#[derive(Debug, Clone, Copy)]
+pub struct CellRectangle {
+    pub min: (i64, i64),
+    pub max: (i64, i64),
+    pub color: [f32; 4],
+}
+
+/// Push constants of all render pipelines
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy)]
+pub struct PushConstants {
+    pub mvp_matrix: Matrix4<f32>,
+}
+
+// Color of every state, `palette` in palette.wgsl
+type Palette = [[f32; 4]; 256];
+
+pub enum Engine {
+    // Steps `AppContext::universe` directly
+    Cpu,
+    // `AppContext::universe` is only updated by `AppContext::sync_universe`
+    Gpu {
+        simulation: Box<GpuSimulation>,
+        // [i] samples texture i of the simulation
+        board_bind_groups: [BindGroup; 2],
+    },
+    // Steps by 2^n generations, unbounded, only cells around the camera are drawn
+    // `AppContext::universe` is only the frame the cells are placed in
+    HashLife(Box<HashLife>),
+    // 64 cells per word, `AppContext::universe` is only updated by `AppContext::sync_universe`
+    Packed(Box<PackedUniverse>),
+    // Unbounded, only cells around the camera are drawn
+    // `AppContext::universe` is only the frame the cells are placed in
+    Sparse(Box<SparseUniverse>),
+    // Lenia or SmoothLife, `AppContext::universe` only gives the board size
+    Continuous {
+        simulation: Box<GpuContinuousSimulation>,
+        // [i] samples texture i of the simulation
+        board_bind_groups: [BindGroup; 2],
+    },
+}
 
 pub struct AppContext {
     pub camera: Camera,
+    pub universe: Universe,
+    pub engine: Engine,
     pub vertex_buffer: Buffer,
+    pub instance_buffer: Buffer,
+    pub instance_count: u32,
+    // Instance positions are relative to this cell position, near the camera far from the origin
+    instance_origin: Vector2<f64>,
     pub render_pipeline: RenderPipeline,
+    palette_buffer: Buffer,
+    // Palette of the cell render pipeline
+    pub palette_bind_group: BindGroup,
+    pub board_render_pipeline: RenderPipeline,
+    board_bind_group_layout: BindGroupLayout,
+    pub continuous_render_pipeline: RenderPipeline,
+    continuous_bind_group_layout: BindGroupLayout,
+    // Edges of the bounded grid of the rule, empty without one
+    pub edge_render_pipeline: RenderPipeline,
+    pub edge_vertex_buffer: Buffer,
+    pub edge_vertex_count: u32,
+    // Selection and paste preview, drawn with the edge render pipeline
+    pub overlay_vertex_buffer: Buffer,
+    pub overlay_vertex_count: u32,
+    // Lines between the cells in the overlay
+    pub show_grid: bool,
+    pub scheduler: Scheduler,
 }
 
 impl AppContext {
-    pub fn new(graphics_context: &GraphicsContext) -> anyhow::Result<Self> {
+    pub fn new(
+        graphics_context: &GraphicsContext,
+        cli: &Cli,
+        pattern_file: Option<PatternFile>,
+    ) -> anyhow::Result<Self> {
         // Shaders
-        let shader_module = graphics_context
+        let shader_module = create_palette_shader_module(
+            graphics_context,
+            "vs_fs.wgsl",
+            include_str!("shaders/vs_fs.wgsl"),
+        );
+
+        let board_shader_module = create_palette_shader_module(
+            graphics_context,
+            "board_vs_fs.wgsl",
+            include_str!("shaders/board_vs_fs.wgsl"),
+        );
+
+        let continuous_shader_module = graphics_context
             .device
-            .create_shader_module(include_wgsl!("shaders/vs_fs.wgsl"));
+            .create_shader_module(include_wgsl!("shaders/continuous_vs_fs.wgsl"));
 
-        // Vertex buffer
-        #[repr(C)]
-        #[derive(Pod, Zeroable, Clone, Copy)]
-        struct Vertex {
-            position: [f32; 2],
-            uv: [f32; 2],
-            color: [f32; 3],
-        }
+        let edge_shader_module = graphics_context
+            .device
+            .create_shader_module(include_wgsl!("shaders/edge_vs_fs.wgsl"));
 
+        // Vertex buffer
         let vertexes = vec![
             Vertex {
                 position: [-0.5, 0.5],
                 uv: [0.0, 0.0],
-                color: [0.0, 1.0, 0.0],
             },
             Vertex {
                 position: [0.5, 0.5],
                 uv: [1.0, 0.0],
-                color: [0.0, 1.0, 0.0],
             },
             Vertex {
                 position: [-0.5, -0.5],
                 uv: [0.0, 1.0],
-                color: [0.0, 1.0, 0.0],
             },
             Vertex {
                 position: [0.5, 0.5],
                 uv: [1.0, 0.0],
-                color: [0.0, 1.0, 0.0],
             },
             Vertex {
                 position: [0.5, -0.5],
                 uv: [1.0, 1.0],
-                color: [0.0, 1.0, 0.0],
             },
             Vertex {
                 position: [-0.5, -0.5],
                 uv: [0.0, 1.0],
-                color: [0.0, 1.0, 0.0],
             },
         ];
         let vertex_buffer = graphics_context
@@ -71,6 +206,34 @@ impl AppContext {
                 usage: BufferUsages::VERTEX,
             });
 
+        // Instance buffer, grows on demand
+        let instance_buffer = create_instance_buffer(graphics_context, 1024);
+
+        // Palette, written once the rule is known
+        let palette_buffer = graphics_context.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: size_of::<Palette>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let palette_bind_group_layout =
+            graphics_context
+                .device
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[palette_layout_entry()],
+                });
+        let palette_bind_group = graphics_context
+            .device
+            .create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &palette_bind_group_layout,
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: palette_buffer.as_entire_binding(),
+                }],
+            });
+
         // Render Pipeline
         let render_pipeline =
             graphics_context
@@ -79,9 +242,10 @@ impl AppContext {
                     label: None,
                     layout: Some(&graphics_context.device.create_pipeline_layout(
                         &PipelineLayoutDescriptor {
+                            bind_group_layouts: &[&palette_bind_group_layout],
                             push_constant_ranges: &[PushConstantRange {
                                 stages: ShaderStages::VERTEX,
-                                range: 0..64,
+                                range: 0..size_of::<PushConstants>() as u32,
                             }],
                             ..Default::default()
                         },
@@ -90,30 +254,44 @@ impl AppContext {
                         module: &shader_module,
                         entry_point: Some("vs_main"),
                         compilation_options: Default::default(),
-                        buffers: &[VertexBufferLayout {
-                            array_stride: size_of::<Vertex>() as BufferAddress,
-                            step_mode: VertexStepMode::Vertex,
-                            attributes: &[
-                                // Position
-                                VertexAttribute {
-                                    format: VertexFormat::Float32x2,
-                                    offset: 0,
-                                    shader_location: 0,
-                                },
-                                // UV
-                                VertexAttribute {
-                                    format: VertexFormat::Float32x2,
-                                    offset: 8,
-                                    shader_location: 1,
-                                },
-                                // Color
-                                VertexAttribute {
-                                    format: VertexFormat::Float32x3,
-                                    offset: 16,
-                                    shader_location: 2,
-                                },
-                            ],
-                        }],
+                        buffers: &[
+                            VertexBufferLayout {
+                                array_stride: size_of::<Vertex>() as BufferAddress,
+                                step_mode: VertexStepMode::Vertex,
+                                attributes: &[
+                                    // Position
+                                    VertexAttribute {
+                                        format: VertexFormat::Float32x2,
+                                        offset: 0,
+                                        shader_location: 0,
+                                    },
+                                    // UV
+                                    VertexAttribute {
+                                        format: VertexFormat::Float32x2,
+                                        offset: 8,
+                                        shader_location: 1,
+                                    },
+                                ],
+                            },
+                            VertexBufferLayout {
+                                array_stride: size_of::<CellInstance>() as BufferAddress,
+                                step_mode: VertexStepMode::Instance,
+                                attributes: &[
+                                    // Cell position
+                                    VertexAttribute {
+                                        format: VertexFormat::Float32x2,
+                                        offset: 0,
+                                        shader_location: 2,
+                                    },
+                                    // Cell state
+                                    VertexAttribute {
+                                        format: VertexFormat::Uint32,
+                                        offset: 8,
+                                        shader_location: 3,
+                                    },
+                                ],
+                            },
+                        ],
                     },
                     primitive: PrimitiveState {
                         topology: PrimitiveTopology::TriangleList,
@@ -143,18 +321,1014 @@ impl AppContext {
                     cache: None,
                 });
 
+        // Board Render Pipeline, draws the board texture of the GPU engine
+        let board_bind_group_layout =
+            graphics_context
+                .device
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[
+                        palette_layout_entry(),
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::FRAGMENT,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Uint,
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let board_render_pipeline = create_board_render_pipeline(
+            graphics_context,
+            &board_shader_module,
+            &board_bind_group_layout,
+        );
+
+        // Continuous Render Pipeline, draws the f32 board of Lenia and SmoothLife
+        let continuous_bind_group_layout =
+            graphics_context
+                .device
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    }],
+                });
+        let continuous_render_pipeline = create_board_render_pipeline(
+            graphics_context,
+            &continuous_shader_module,
+            &continuous_bind_group_layout,
+        );
+
+        // Edge Render Pipeline, draws the edges of a bounded grid as lines
+        let edge_render_pipeline =
+            create_edge_render_pipeline(graphics_context, &edge_shader_module);
+
         let scale_factor = graphics_context.window.scale_factor();
-        let camera = Camera::new(
+        let mut camera = Camera::new(
             graphics_context
                 .window
                 .inner_size()
                 .to_logical(scale_factor),
         );
+        camera.set_zoom(cli.zoom);
+        if let Some((x, y)) = cli.center {
+            let cell_size = CELL_SIZE as f64;
+            camera.set_position([x as f64 * cell_size, -(y as f64) * cell_size].into());
+        }
 
-        Ok(Self {
+        let has_pattern = pattern_file.is_some();
+        let pattern_file = match pattern_file {
+            Some(pattern_file) => pattern_file,
+            None => PatternFile::Cells(
+                rle::parse(INITIAL_PATTERN).context("Failed to parse initial pattern")?,
+            ),
+        };
+        let mut board = create_board(cli, pattern_file)?;
+
+        // Macrocell patterns run on HashLife unless another engine is asked for
+        // The compute shader has no rule tables
+        // Software adapters are slower than the packed engine, which only runs counting rules
+        let cells = board.width.saturating_mul(board.height);
+        let software_adapter = graphics_context.adapter.get_info().device_type == DeviceType::Cpu;
+        let engine_kind = cli.engine.unwrap_or(if board.is_macrocell() {
+            EngineKind::HashLife
+        } else if cells >= GPU_ENGINE_MIN_CELLS
+            && software_adapter
+            && PackedUniverse::supports(&board.rule)
+        {
+            EngineKind::Packed
+        } else if cells >= GPU_ENGINE_MIN_CELLS && board.rule.rule_table().is_none() {
+            EngineKind::Gpu
+        } else {
+            EngineKind::Cpu
+        });
+        // The unbounded engines are built straight from the pattern, the universe is only the
+        // window they are shown through
+        let unbounded = cli.continuous.is_none()
+            && matches!(engine_kind, EngineKind::HashLife | EngineKind::Sparse);
+        // Nothing is written into it, the cells are drawn straight from the engine
+        let (universe, engine) = if unbounded {
+            board.shrink_to_window();
+            let mut universe = Universe::new(board.width, board.height);
+            universe.set_rule(board.rule.clone());
+            let engine = if engine_kind == EngineKind::HashLife {
+                let mut hashlife = board.hashlife()?;
+                hashlife.set_step_exponent(cli.hashlife_step);
+                Engine::HashLife(Box::new(hashlife))
+            } else {
+                Engine::Sparse(Box::new(board.sparse_universe()?))
+            };
+            (universe, engine)
+        } else {
+            (board.universe()?, Engine::Cpu)
+        };
+
+        // Edge buffer, the rule doesn't change
+        let edge_vertexes = universe
+            .rule()
+            .topology()
+            .map_or_else(Vec::new, edge_vertexes);
+        let edge_vertex_buffer =
+            graphics_context
+                .device
+                .create_buffer_init(&BufferInitDescriptor {
+                    label: None,
+                    contents: bytemuck::cast_slice(&edge_vertexes),
+                    usage: BufferUsages::VERTEX,
+                });
+
+        let overlay_vertex_buffer = create_overlay_vertex_buffer(graphics_context, 64);
+
+        let mut app_context = Self {
             camera,
+            universe,
+            engine,
             vertex_buffer,
+            instance_buffer,
+            instance_count: 0,
+            instance_origin: Vector2::zeros(),
             render_pipeline,
-        })
+            palette_buffer,
+            palette_bind_group,
+            board_render_pipeline,
+            board_bind_group_layout,
+            continuous_render_pipeline,
+            continuous_bind_group_layout,
+            edge_render_pipeline,
+            edge_vertex_buffer,
+            edge_vertex_count: edge_vertexes.len() as u32,
+            overlay_vertex_buffer,
+            overlay_vertex_count: 0,
+            show_grid: false,
+            scheduler: Scheduler::new(cli.paused, cli.gps, Instant::now()),
+        };
+
+        if let Some(rule) = cli.continuous_rule() {
+            app_context.use_continuous_engine(graphics_context, rule, !has_pattern)?;
+            return Ok(app_context);
+        }
+        app_context.update_palette(graphics_context);
+
+        match engine_kind {
+            EngineKind::Gpu => app_context.use_gpu_engine(graphics_context)?,
+            EngineKind::Packed => app_context.use_packed_engine(graphics_context)?,
+            EngineKind::Cpu | EngineKind::HashLife | EngineKind::Sparse => (),
+        }
+
+        Ok(app_context)
+    }
+
+    /// Moves the simulation into the compute shader, starting from the current universe
+    pub fn use_gpu_engine(&mut self, graphics_context: &GraphicsContext) -> anyhow::Result<()> {
+        let rule = self.universe.rule();
+        anyhow::ensure!(
+            rule.rule_table().is_none(),
+            "The GPU engine can't run rule table {rule}"
+        );
+        let device = &graphics_context.device;
+        let (width, height) = (self.universe.width() as u32, self.universe.height() as u32);
+        gpu_simulation::check_board_size(device, width, height)?;
+        let mut simulation = GpuSimulation::new(device, width, height);
+        simulation.upload(device, &graphics_context.queue, &self.universe);
+
+        let create_bind_group = |index: usize| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &self.board_bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.palette_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&simulation.texture_views()[index]),
+                    },
+                ],
+            })
+        };
+        let board_bind_groups = [create_bind_group(0), create_bind_group(1)];
+
+        self.engine = Engine::Gpu {
+            simulation: Box::new(simulation),
+            board_bind_groups,
+        };
+        Ok(())
+    }
+
+    /// Writes the colors of the states of the rule into the palette
+    pub fn update_palette(&self, graphics_context: &GraphicsContext) {
+        let rule = self.universe.rule();
+        let palette: Palette = std::array::from_fn(|state| {
+            let [r, g, b] = rule.color(state as u8);
+            [r as f32 / 255., g as f32 / 255., b as f32 / 255., 1.]
+        });
+        graphics_context
+            .queue
+            .write_buffer(&self.palette_buffer, 0, bytemuck::bytes_of(&palette));
+    }
+
+    /// Runs a continuous automaton on a board of the universe size
+    ///
+    /// Live cells of the universe seed it, the largest state becomes 1. With `noise` the middle
+    /// of the board is filled with random values instead.
+    pub fn use_continuous_engine(
+        &mut self,
+        graphics_context: &GraphicsContext,
+        rule: ContinuousRule,
+        noise: bool,
+    ) -> anyhow::Result<()> {
+        let (width, height) = (self.universe.width(), self.universe.height());
+        let device = &graphics_context.device;
+        gpu_simulation::check_board_size(device, width as u32, height as u32)?;
+        let mut universe = ContinuousUniverse::new(width, height, rule.clone());
+        if noise {
+            let seed = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(1, |duration| duration.as_nanos() as u64);
+            let (width, height) = (width as i64, height as i64);
+            universe.fill_random(width / 4, height / 4, width / 2, height / 2, seed);
+        } else {
+            let max_state = self
+                .universe
+                .live_cells()
+                .map(|(_, _, state)| state)
+                .max()
+                .unwrap_or(ALIVE);
+            for (x, y, state) in self.universe.live_cells() {
+                universe.set_cell(x, y, state as f32 / max_state as f32);
+            }
+        }
+
+        let mut simulation =
+            GpuContinuousSimulation::new(device, width as u32, height as u32, &rule);
+        simulation.upload(&graphics_context.queue, &universe);
+
+        let create_bind_group = |index: usize| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &self.continuous_bind_group_layout,
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&simulation.texture_views()[index]),
+                }],
+            })
+        };
+        let board_bind_groups = [create_bind_group(0), create_bind_group(1)];
+
+        self.engine = Engine::Continuous {
+            simulation: Box::new(simulation),
+            board_bind_groups,
+        };
+        Ok(())
+    }
+
+    /// Switches to the bit-packed CPU stepper, starting from the current universe
+    pub fn use_packed_engine(&mut self, graphics_context: &GraphicsContext) -> anyhow::Result<()> {
+        PackedUniverse::check_rule(self.universe.rule())?;
+        self.sync_universe(graphics_context);
+        let packed_universe = PackedUniverse::from_universe(&self.universe);
+        self.engine = Engine::Packed(Box::new(packed_universe));
+        Ok(())
+    }
+
+    /// Brings `universe` up to date with the engine
+    pub fn sync_universe(&mut self, graphics_context: &GraphicsContext) {
+        match &self.engine {
+            Engine::Gpu { simulation, .. }
+                if simulation.generation() != self.universe.generation() =>
+            {
+                simulation.download(
+                    &graphics_context.device,
+                    &graphics_context.queue,
+                    &mut self.universe,
+                );
+            }
+            Engine::Packed(packed_universe)
+                if packed_universe.generation() != self.universe.generation() =>
+            {
+                packed_universe.write_to(&mut self.universe);
+            }
+            _ => (),
+        }
+    }
+
+    /// State of a universe cell, unbounded engines also have cells outside of the universe
+    ///
+    /// The GPU engine reads its board back first.
+    pub fn get_cell(&mut self, graphics_context: &GraphicsContext, x: i64, y: i64) -> u8 {
+        match &self.engine {
+            Engine::HashLife(hashlife) => hashlife.get_cell(x, y),
+            Engine::Sparse(sparse_universe) => sparse_universe.get_cell(x, y),
+            Engine::Continuous { .. } => DEAD,
+            Engine::Cpu | Engine::Gpu { .. } | Engine::Packed(_) => {
+                self.sync_universe(graphics_context);
+                if self.universe.contains(x, y) {
+                    self.universe.get_cell(x, y)
+                } else {
+                    DEAD
+                }
+            }
+        }
     }
+
+    /// Changes a cell in the engine, cells outside of a bounded universe are ignored
+    ///
+    /// Continuous automata can't be edited.
+    pub fn set_cell(&mut self, graphics_context: &GraphicsContext, x: i64, y: i64, state: u8) {
+        let contains = self.universe.contains(x, y);
+        match &mut self.engine {
+            Engine::HashLife(hashlife) => hashlife.set_cell(x, y, state),
+            Engine::Sparse(sparse_universe) => sparse_universe.set_cell(x, y, state),
+            Engine::Gpu { simulation, .. } if contains => {
+                simulation.set_cell(&graphics_context.queue, x as u32, y as u32, state)
+            }
+            Engine::Packed(packed_universe) if contains => {
+                packed_universe.set_cell(x as usize, y as usize, state == ALIVE)
+            }
+            Engine::Cpu | Engine::Gpu { .. } | Engine::Packed(_) | Engine::Continuous { .. } => {}
+        }
+        // Keeps the universe in step, `sync_universe` only reads engines after steps
+        if let Engine::Cpu | Engine::Gpu { .. } | Engine::Packed(_) = self.engine {
+            self.universe.set_cell(x, y, state);
+        }
+    }
+
+    /// Changes many cells at once, the GPU board is uploaded once instead of per cell
+    pub fn set_cells(&mut self, graphics_context: &GraphicsContext, cells: &[(i64, i64, u8)]) {
+        if let Engine::Gpu { .. } = self.engine {
+            self.sync_universe(graphics_context);
+        }
+        match &mut self.engine {
+            Engine::Gpu { simulation, .. } => {
+                for &(x, y, state) in cells {
+                    self.universe.set_cell(x, y, state);
+                }
+                simulation.upload(
+                    &graphics_context.device,
+                    &graphics_context.queue,
+                    &self.universe,
+                );
+            }
+            _ => {
+                for &(x, y, state) in cells {
+                    self.set_cell(graphics_context, x, y, state);
+                }
+            }
+        }
+    }
+
+    /// Moves the generation counter, e.g. back to where an undone run started
+    pub fn set_generation(&mut self, graphics_context: &GraphicsContext, generation: u64) {
+        // The universe must not look up to date without the cells of the engine
+        self.sync_universe(graphics_context);
+        match &mut self.engine {
+            Engine::Gpu { simulation, .. } => simulation.set_generation(generation),
+            Engine::HashLife(hashlife) => hashlife.set_generation(generation),
+            Engine::Packed(packed_universe) => packed_universe.set_generation(generation),
+            Engine::Sparse(sparse_universe) => sparse_universe.set_generation(generation),
+            Engine::Cpu | Engine::Continuous { .. } => (),
+        }
+        self.universe.set_generation(generation);
+    }
+
+    /// Number of non-dead cells, the GPU engine reads its board back first
+    pub fn population(&mut self, graphics_context: &GraphicsContext) -> u64 {
+        match &self.engine {
+            Engine::HashLife(hashlife) => hashlife.population(),
+            Engine::Packed(packed_universe) => packed_universe.population(),
+            Engine::Sparse(sparse_universe) => sparse_universe.population(),
+            Engine::Continuous { .. } => 0,
+            Engine::Cpu | Engine::Gpu { .. } => {
+                self.sync_universe(graphics_context);
+                self.universe.population() as u64
+            }
+        }
+    }
+
+    /// All non-dead cells, unbounded engines include the ones outside of the universe
+    pub fn live_cells(&mut self, graphics_context: &GraphicsContext) -> Vec<(i64, i64, u8)> {
+        match &self.engine {
+            Engine::HashLife(hashlife) => hashlife.live_cells(),
+            Engine::Sparse(sparse_universe) => sparse_universe.live_cells(),
+            Engine::Continuous { .. } => Vec::new(),
+            Engine::Cpu | Engine::Gpu { .. } | Engine::Packed(_) => {
+                self.sync_universe(graphics_context);
+                self.universe.live_cells().collect()
+            }
+        }
+    }
+
+    /// Cells within [min, max) as a pattern of that size, with the rule of the universe
+    pub fn copy_region(
+        &mut self,
+        graphics_context: &GraphicsContext,
+        min: (i64, i64),
+        max: (i64, i64),
+    ) -> Pattern {
+        let mut cells = Vec::new();
+        let mut push_cell = |x, y, state| cells.push((x - min.0, y - min.1, state));
+        match &self.engine {
+            Engine::HashLife(hashlife) => hashlife.for_each_live_cell_in(min, max, &mut push_cell),
+            Engine::Sparse(sparse_universe) => {
+                sparse_universe.for_each_live_cell_in(min, max, &mut push_cell)
+            }
+            Engine::Continuous { .. } => (),
+            Engine::Cpu | Engine::Gpu { .. } | Engine::Packed(_) => {
+                self.sync_universe(graphics_context);
+                let (width, height) = (self.universe.width() as i64, self.universe.height() as i64);
+                for y in min.1.max(0)..max.1.min(height) {
+                    for x in min.0.max(0)..max.0.min(width) {
+                        let state = self.universe.get_cell(x, y);
+                        if state != DEAD {
+                            push_cell(x, y, state);
+                        }
+                    }
+                }
+            }
+        }
+        Pattern {
+            rule: Some(self.universe.rule().to_string()),
+            width: (max.0 - min.0) as u64,
+            height: (max.1 - min.1) as u64,
+            cells,
+            ..Default::default()
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        match &self.engine {
+            Engine::Cpu => self.universe.generation(),
+            Engine::Gpu { simulation, .. } => simulation.generation(),
+            Engine::HashLife(hashlife) => hashlife.generation(),
+            Engine::Packed(packed_universe) => packed_universe.generation(),
+            Engine::Sparse(sparse_universe) => sparse_universe.generation(),
+            Engine::Continuous { simulation, .. } => simulation.generation(),
+        }
+    }
+
+    /// Advances the simulation by one generation
+    pub fn step(&mut self, graphics_context: &GraphicsContext) {
+        match &mut self.engine {
+            Engine::Cpu => self.universe.step(),
+            Engine::Gpu { simulation, .. } => {
+                simulation.step(&graphics_context.device, &graphics_context.queue)
+            }
+            Engine::HashLife(hashlife) => hashlife.step(),
+            Engine::Packed(packed_universe) => packed_universe.step(),
+            Engine::Sparse(sparse_universe) => sparse_universe.step(),
+            Engine::Continuous { simulation, .. } => {
+                simulation.step(&graphics_context.device, &graphics_context.queue)
+            }
+        }
+    }
+
+    /// Steps as many generations as are due since the last frame
+    pub fn update(&mut self, graphics_context: &GraphicsContext) {
+        match self.scheduler.frame_steps(Instant::now()) {
+            FrameSteps::Generations(generations) => {
+                for _ in 0..generations {
+                    self.step(graphics_context);
+                }
+            }
+            FrameSteps::Until {
+                deadline,
+                max_generations,
+            } => {
+                for _ in 0..max_generations {
+                    self.step(graphics_context);
+                    if Instant::now() >= deadline {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn title(&self) -> String {
+        let title = match &self.engine {
+            Engine::Cpu => format!(
+                "Game of Life {} - generation {}, population {}",
+                self.universe.rule(),
+                self.universe.generation(),
+                self.universe.population()
+            ),
+            Engine::HashLife(hashlife) => format!(
+                "Game of Life {} - generation {}, population {} (HashLife, step 2^{})",
+                hashlife.rule(),
+                hashlife.generation(),
+                hashlife.population(),
+                hashlife.step_exponent()
+            ),
+            Engine::Packed(packed_universe) => format!(
+                "Game of Life {} - generation {}, population {} (packed)",
+                packed_universe.rule(),
+                packed_universe.generation(),
+                packed_universe.population()
+            ),
+            Engine::Sparse(sparse_universe) => format!(
+                "Game of Life {} - generation {}, population {} (sparse, {} tiles)",
+                sparse_universe.rule(),
+                sparse_universe.generation(),
+                sparse_universe.population(),
+                sparse_universe.tile_count()
+            ),
+            // Population would need a readback every frame
+            Engine::Gpu { .. } => format!(
+                "Game of Life {} - generation {} (GPU)",
+                self.universe.rule(),
+                self.generation()
+            ),
+            Engine::Continuous { simulation, .. } => format!(
+                "{} - step {} (GPU)",
+                simulation.rule(),
+                simulation.generation()
+            ),
+        };
+        if self.scheduler.is_paused() {
+            title + " [paused]"
+        } else if let Some(queued_generations) = self.scheduler.queued_generations() {
+            format!("{title} [stepping, {queued_generations} left]")
+        } else if self.scheduler.is_max_speed() {
+            title + " [max speed]"
+        } else if let Some(generations_per_second) = self.scheduler.generations_per_second() {
+            format!("{title} [{generations_per_second} gen/s]")
+        } else {
+            title + " [1 gen/frame]"
+        }
+    }
+
+    /// World position that rendering is relative to, see `Camera::calculate_view_projection_matrix`
+    pub fn render_origin(&self) -> Vector2<f64> {
+        let cell_size = CELL_SIZE as f64;
+        Vector2::new(
+            self.instance_origin.x + self.row_shear() * self.instance_origin.y,
+            self.instance_origin.y,
+        ) * cell_size
+    }
+
+    /// Universe cell at the world position
+    pub fn world_to_cell(&self, world_position: Vector2<f64>) -> (i64, i64) {
+        let cell_size = CELL_SIZE as f64;
+        let y = world_position.y / cell_size;
+        let x = world_position.x / cell_size - self.row_shear() * y;
+        (
+            (x + self.universe.width() as f64 / 2.).floor() as i64,
+            (-y + self.universe.height() as f64 / 2.).floor() as i64,
+        )
+    }
+
+    /// Horizontal shift of a row per row above the middle, in cells
+    ///
+    /// Every row of a hexagonal rule is shifted half a cell right of the one below it, the six
+    /// neighbours of a cell surround it.
+    pub fn row_shear(&self) -> f64 {
+        if self.universe.rule().neighbourhood() == Neighbourhood::Hexagonal {
+            0.5
+        } else {
+            0.
+        }
+    }
+
+    /// Writes live cells of the universe into the instance buffer
+    ///
+    /// The sparse universe only gives the cells around the camera, relative to the cell in the
+    /// middle of the window.
+    pub fn update_instances(&mut self, graphics_context: &GraphicsContext) {
+        let half_width = self.universe.width() as f64 / 2.;
+        let half_height = self.universe.height() as f64 / 2.;
+        // Universe rows go down, world Y goes up
+        let cell_position = |x: i64, y: i64| {
+            Vector2::new(x as f64 - half_width + 0.5, -(y as f64 - half_height + 0.5))
+        };
+        let mut instances = Vec::new();
+        let mut push_instance = |origin: Vector2<f64>, x: i64, y: i64, state: u8| {
+            let position = (cell_position(x, y) - origin).cast::<f32>();
+            instances.push(CellInstance {
+                position: position.into(),
+                state: state as u32,
+            })
+        };
+
+        if let Engine::Sparse(_) | Engine::HashLife(_) = &self.engine {
+            let (center_x, center_y) = self.world_to_cell(self.camera.position());
+            self.instance_origin = cell_position(center_x, center_y);
+            // Visible cells with a margin, rows of hexagonal rules are sheared by half a cell
+            let viewport_size = self.camera.viewport_size();
+            let scale = self.camera.zoom() as f64 * CELL_SIZE as f64;
+            let half_rows = (viewport_size.height as f64 / scale / 2.).ceil() as i64 + 1;
+            let half_columns =
+                (viewport_size.width as f64 / scale / 2.).ceil() as i64 + 1 + half_rows;
+            let min = (
+                center_x.saturating_sub(half_columns),
+                center_y.saturating_sub(half_rows),
+            );
+            let max = (
+                center_x.saturating_add(half_columns + 1),
+                center_y.saturating_add(half_rows + 1),
+            );
+            let mut push_cell = |x, y, state| push_instance(self.instance_origin, x, y, state);
+            match &self.engine {
+                Engine::Sparse(sparse_universe) => {
+                    sparse_universe.for_each_live_cell_in(min, max, &mut push_cell)
+                }
+                Engine::HashLife(hashlife) => {
+                    hashlife.for_each_live_cell_in(min, max, &mut push_cell)
+                }
+                _ => unreachable!(),
+            }
+        } else if let Engine::Packed(packed_universe) = &self.engine {
+            // The set bits of the packed words, the universe is only unpacked when it is read
+            self.instance_origin = Vector2::zeros();
+            for (x, y, state) in packed_universe.live_cells() {
+                push_instance(self.instance_origin, x, y, state);
+            }
+        } else {
+            self.instance_origin = Vector2::zeros();
+            for (x, y, state) in self.universe.live_cells() {
+                push_instance(self.instance_origin, x, y, state);
+            }
+        }
+
+        if instances.len() as u64 * size_of::<CellInstance>() as u64 > self.instance_buffer.size() {
+            self.instance_buffer =
+                create_instance_buffer(graphics_context, instances.len().next_power_of_two());
+        }
+        graphics_context.queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&instances),
+        );
+        self.instance_count = instances.len() as u32;
+    }
+
+    // Cells [min, max) that the grid covers, the visible ones within a bounded universe
+    fn visible_grid(&self) -> Option<((i64, i64), (i64, i64))> {
+        if !self.show_grid || self.camera.zoom() * CELL_SIZE < GRID_MIN_CELL_PIXELS {
+            return None;
+        }
+        let viewport_size = self.camera.viewport_size();
+        let (width, height) = (viewport_size.width as f32, viewport_size.height as f32);
+        let corner_cells = [(0., 0.), (width, 0.), (0., height), (width, height)].map(|(x, y)| {
+            self.world_to_cell(
+                self.camera
+                    .screen_to_world_position(LogicalPosition::new(x, y)),
+            )
+        });
+        let mut min = corner_cells
+            .iter()
+            .fold((i64::MAX, i64::MAX), |min, &(x, y)| {
+                (min.0.min(x), min.1.min(y))
+            });
+        let mut max = corner_cells
+            .iter()
+            .fold((i64::MIN, i64::MIN), |max, &(x, y)| {
+                (max.0.max(x + 1), max.1.max(y + 1))
+            });
+        if !matches!(self.engine, Engine::HashLife(_) | Engine::Sparse(_)) {
+            min = (min.0.max(0), min.1.max(0));
+            max = (
+                max.0.min(self.universe.width() as i64),
+                max.1.min(self.universe.height() as i64),
+            );
+        }
+        (min.0 < max.0 && min.1 < max.1).then_some((min, max))
+    }
+
+    /// Centers the camera on the live cells and zooms until they fit
+    pub fn fit_pattern(&mut self, graphics_context: &GraphicsContext) {
+        let bounding_box = match &self.engine {
+            Engine::HashLife(hashlife) => hashlife.bounding_box(),
+            // The whole board, there are no live cells to find
+            Engine::Continuous { .. } => Some((
+                (0, 0),
+                (self.universe.width() as i64, self.universe.height() as i64),
+            )),
+            _ => self.live_cells(graphics_context).into_iter().fold(
+                None,
+                |bounding_box, (x, y, _)| {
+                    let ((min_x, min_y), (max_x, max_y)) =
+                        bounding_box.unwrap_or(((x, y), (x + 1, y + 1)));
+                    Some((
+                        (min_x.min(x), min_y.min(y)),
+                        (max_x.max(x + 1), max_y.max(y + 1)),
+                    ))
+                },
+            ),
+        };
+        let Some((min, max)) = bounding_box else {
+            return;
+        };
+        // Corners in the world, rows of hexagonal rules are sheared
+        let corners =
+            [min, (max.0, min.1), (min.0, max.1), max].map(|(x, y)| self.cell_corner(x, y));
+        let world_min = corners
+            .iter()
+            .fold(corners[0], |min, corner| min.inf(corner));
+        let world_max = corners
+            .iter()
+            .fold(corners[0], |max, corner| max.sup(corner));
+        self.camera.fit(world_min, world_max);
+    }
+
+    // World position of the top-left corner of a universe cell, inverse of `world_to_cell`
+    fn cell_corner(&self, x: i64, y: i64) -> Vector2<f64> {
+        let cell_y = -(y as f64 - self.universe.height() as f64 / 2.);
+        let cell_x = x as f64 - self.universe.width() as f64 / 2. + self.row_shear() * cell_y;
+        Vector2::new(cell_x, cell_y) * CELL_SIZE as f64
+    }
+
+    /// Replaces the overlay with the outlines of the rectangles
+    ///
+    /// Called after `update_instances`, outlines are placed relative to the same origin.
+    pub fn update_overlay(
+        &mut self,
+        graphics_context: &GraphicsContext,
+        rectangles: &[CellRectangle],
+    ) {
+        let half_width = self.universe.width() as f64 / 2.;
+        let half_height = self.universe.height() as f64 / 2.;
+        // Top-left corner of a cell
+        let corner = |x: i64, y: i64| {
+            let position = Vector2::new(x as f64 - half_width, -(y as f64 - half_height))
+                - self.instance_origin;
+            position.cast::<f32>().into()
+        };
+        let mut vertexes = Vec::with_capacity(rectangles.len() * 8);
+        let mut push_line = |from, to, color| {
+            vertexes.push(EdgeVertex {
+                position: from,
+                color,
+            });
+            vertexes.push(EdgeVertex {
+                position: to,
+                color,
+            });
+        };
+
+        if let Some((min, max)) = self.visible_grid() {
+            for x in min.0..=max.0 {
+                push_line(corner(x, min.1), corner(x, max.1), GRID_COLOR);
+            }
+            for y in min.1..=max.1 {
+                push_line(corner(min.0, y), corner(max.0, y), GRID_COLOR);
+            }
+        }
+        for rectangle in rectangles {
+            let (min, max) = (rectangle.min, rectangle.max);
+            let corners = [
+                corner(min.0, min.1),
+                corner(max.0, min.1),
+                corner(max.0, max.1),
+                corner(min.0, max.1),
+            ];
+            for side in 0..4 {
+                push_line(corners[side], corners[(side + 1) % 4], rectangle.color);
+            }
+        }
+
+        if (vertexes.len() * size_of::<EdgeVertex>()) as u64 > self.overlay_vertex_buffer.size() {
+            self.overlay_vertex_buffer =
+                create_overlay_vertex_buffer(graphics_context, vertexes.len().next_power_of_two());
+        }
+        graphics_context.queue.write_buffer(
+            &self.overlay_vertex_buffer,
+            0,
+            bytemuck::cast_slice(&vertexes),
+        );
+        self.overlay_vertex_count = vertexes.len() as u32;
+    }
+}
+
+fn create_overlay_vertex_buffer(graphics_context: &GraphicsContext, capacity: usize) -> Buffer {
+    graphics_context.device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: (capacity * size_of::<EdgeVertex>()) as BufferAddress,
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Palette uniform buffer, read by the fragment shader
+// `palette` in palette.wgsl
+fn palette_layout_entry() -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+// The palette snippet is shared by the shaders that draw cells
+fn create_palette_shader_module(
+    graphics_context: &GraphicsContext,
+    label: &str,
+    source: &str,
+) -> ShaderModule {
+    let source = format!("{}\n{source}", include_str!("shaders/palette.wgsl"));
+    graphics_context
+        .device
+        .create_shader_module(ShaderModuleDescriptor {
+            label: Some(label),
+            source: ShaderSource::Wgsl(source.into()),
+        })
+}
+
+fn create_instance_buffer(graphics_context: &GraphicsContext, capacity: usize) -> Buffer {
+    graphics_context.device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: (capacity * size_of::<CellInstance>()) as BufferAddress,
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Pipeline of a quad over the whole board that reads the board texture in the fragment shader
+fn create_board_render_pipeline(
+    graphics_context: &GraphicsContext,
+    shader_module: &ShaderModule,
+    bind_group_layout: &BindGroupLayout,
+) -> RenderPipeline {
+    let device = &graphics_context.device;
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::VERTEX,
+                range: 0..size_of::<PushConstants>() as u32,
+            }],
+            ..Default::default()
+        })),
+        vertex: VertexState {
+            module: shader_module,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[VertexBufferLayout {
+                array_stride: size_of::<Vertex>() as BufferAddress,
+                step_mode: VertexStepMode::Vertex,
+                attributes: &[
+                    // Position
+                    VertexAttribute {
+                        format: VertexFormat::Float32x2,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                    // UV
+                    VertexAttribute {
+                        format: VertexFormat::Float32x2,
+                        offset: 8,
+                        shader_location: 1,
+                    },
+                ],
+            }],
+        },
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Cw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: Default::default(),
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: Default::default(),
+        fragment: Some(FragmentState {
+            module: shader_module,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(ColorTargetState {
+                format: graphics_context
+                    .surface_data
+                    .surface_configuration
+                    .view_formats[0],
+                blend: None,
+                write_mask: ColorWrites::all(),
+            })],
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+// Pipeline of the edge lines, `EdgeVertex` pairs
+fn create_edge_render_pipeline(
+    graphics_context: &GraphicsContext,
+    shader_module: &ShaderModule,
+) -> RenderPipeline {
+    let device = &graphics_context.device;
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            bind_group_layouts: &[],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::VERTEX,
+                range: 0..size_of::<PushConstants>() as u32,
+            }],
+            ..Default::default()
+        })),
+        vertex: VertexState {
+            module: shader_module,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[VertexBufferLayout {
+                array_stride: size_of::<EdgeVertex>() as BufferAddress,
+                step_mode: VertexStepMode::Vertex,
+                attributes: &[
+                    // Position
+                    VertexAttribute {
+                        format: VertexFormat::Float32x2,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                    // Color
+                    VertexAttribute {
+                        format: VertexFormat::Float32x4,
+                        offset: 8,
+                        shader_location: 1,
+                    },
+                ],
+            }],
+        },
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::LineList,
+            strip_index_format: None,
+            front_face: FrontFace::Cw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: Default::default(),
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: Default::default(),
+        fragment: Some(FragmentState {
+            module: shader_module,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(ColorTargetState {
+                format: graphics_context
+                    .surface_data
+                    .surface_configuration
+                    .view_formats[0],
+                blend: None,
+                write_mask: ColorWrites::all(),
+            })],
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+// Top, bottom, left and right edges of the grid, colored by how they are joined
+fn edge_vertexes(topology: &Topology) -> Vec<EdgeVertex> {
+    let (right, top) = (topology.width() as f32 / 2., topology.height() as f32 / 2.);
+    let (left, bottom) = (-right, -top);
+    let (top_bottom_color, left_right_color) = match topology.kind() {
+        TopologyKind::Plane => (PLANE_EDGE_COLOR, PLANE_EDGE_COLOR),
+        TopologyKind::Torus => (JOINED_EDGE_COLOR, JOINED_EDGE_COLOR),
+        TopologyKind::KleinBottle {
+            twisted_columns: true,
+        } => (TWISTED_EDGE_COLOR, JOINED_EDGE_COLOR),
+        TopologyKind::KleinBottle {
+            twisted_columns: false,
+        } => (JOINED_EDGE_COLOR, TWISTED_EDGE_COLOR),
+        TopologyKind::CrossSurface => (TWISTED_EDGE_COLOR, TWISTED_EDGE_COLOR),
+        TopologyKind::Sphere => (SPHERE_EDGE_COLOR, SPHERE_EDGE_COLOR),
+    };
+    let line = |from: [f32; 2], to: [f32; 2], color| {
+        [
+            EdgeVertex {
+                position: from,
+                color,
+            },
+            EdgeVertex {
+                position: to,
+                color,
+            },
+        ]
+    };
+    [
+        line([left, top], [right, top], top_bottom_color),
+        line([left, bottom], [right, bottom], top_bottom_color),
+        line([left, bottom], [left, top], left_right_color),
+        line([right, bottom], [right, top], left_right_color),
+    ]
+    .concat()
 }
@@ -1,21 +1,26 @@
 // 2D Camera
+// The position is f64 so that the camera can travel far from the origin of an unbounded universe,
+// rendering happens relative to an origin near the camera where f32 is still precise
 
 use float_cmp::approx_eq;
 use nalgebra::{Matrix4, Vector2, Vector3};
 use winit::dpi::{LogicalPosition, LogicalSize};
 
-const ZOOM_MIN: f32 = 0.1;
-const ZOOM_MAX: f32 = 10.;
+pub const ZOOM_MIN: f32 = 0.1;
+pub const ZOOM_MAX: f32 = 10.;
 
 const ZOOM_DEFAULT_SENSITIVITY: f32 = 0.1;
+// Part of the viewport a fitted rectangle fills
+const FIT_MARGIN: f64 = 0.9;
 
 #[derive(Debug)]
 pub struct Camera {
-    position: Vector2<f32>,
+    // In world units
+    position: Vector2<f64>,
     zoom: f32,
     zoom_sensitivity: f32,
     viewport_size: LogicalSize<u32>,
-    lmb_is_pressed: bool,
+    is_panning: bool,
     cursor_position: LogicalPosition<f32>,
 }
 
@@ -26,16 +31,16 @@ impl Camera {
             zoom: 1.,
             zoom_sensitivity: ZOOM_DEFAULT_SENSITIVITY,
             viewport_size,
-            lmb_is_pressed: false,
+            is_panning: false,
             cursor_position: LogicalPosition::new(0., 0.),
         }
     }
 
-    pub fn position(&self) -> Vector2<f32> {
+    pub fn position(&self) -> Vector2<f64> {
         self.position
     }
 
-    pub fn set_position(&mut self, position: Vector2<f32>) {
+    pub fn set_position(&mut self, position: Vector2<f64>) {
         self.position = position
     }
 
@@ -43,6 +48,10 @@ impl Camera {
         self.zoom
     }
 
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.clamp(ZOOM_MIN, ZOOM_MAX);
+    }
+
     pub fn mouse_scroll(&mut self, scroll: f32) {
         let zoom_delta = scroll * self.zoom_sensitivity;
         let old_zoom = self.zoom;
@@ -61,10 +70,6 @@ impl Camera {
         self.position -= cursor_position_delta;
     }
 
-    pub fn set_zoom_sensitivity(&mut self, zoom_sensitivity: f32) {
-        self.zoom_sensitivity = zoom_sensitivity;
-    }
-
     pub fn viewport_size(&self) -> LogicalSize<u32> {
         self.viewport_size
     }
@@ -73,7 +78,31 @@ impl Camera {
         self.viewport_size = viewport_size
     }
 
-    pub fn calculate_view_projection_matrix(&self) -> Matrix4<f32> {
+    /// Moves the view by a distance in logical pixels, Y goes up
+    pub fn pan(&mut self, offset: Vector2<f32>) {
+        self.position += (offset / self.zoom).cast::<f64>();
+    }
+
+    /// Zooms like `mouse_scroll` but on the middle of the viewport
+    pub fn zoom_on_center(&mut self, scroll: f32) {
+        self.set_zoom(self.zoom + scroll * self.zoom_sensitivity);
+    }
+
+    /// Centers the view on the world rectangle and zooms until it fills most of the viewport
+    pub fn fit(&mut self, min: Vector2<f64>, max: Vector2<f64>) {
+        self.position = (min + max) / 2.;
+        let size = (max - min).map(|length| length.max(f64::EPSILON));
+        let zoom = (self.viewport_size.width as f64 / size.x)
+            .min(self.viewport_size.height as f64 / size.y)
+            * FIT_MARGIN;
+        self.set_zoom(zoom as f32);
+    }
+
+    /// Maps world positions relative to `origin` to clip space
+    ///
+    /// Only the offset of the camera from the origin goes into the matrix, it stays precise in f32
+    /// if the origin is near the camera.
+    pub fn calculate_view_projection_matrix(&self, origin: Vector2<f64>) -> Matrix4<f32> {
         let half_w = (self.viewport_size.width as f32) / (2.0 * self.zoom);
         let half_h = (self.viewport_size.height as f32) / (2.0 * self.zoom);
 
@@ -83,12 +112,13 @@ impl Camera {
         let top = half_h;
 
         let proj = Matrix4::new_orthographic(left, right, bottom, top, -1.0, 1.0);
-        let view = Matrix4::new_translation(&Vector3::new(-self.position.x, -self.position.y, 0.0));
+        let offset = (origin - self.position).cast::<f32>();
+        let view = Matrix4::new_translation(&Vector3::new(offset.x, offset.y, 0.0));
 
         proj * view
     }
 
-    pub fn screen_to_world_position(&self, screen_pos: LogicalPosition<f32>) -> Vector2<f32> {
+    pub fn screen_to_world_position(&self, screen_pos: LogicalPosition<f32>) -> Vector2<f64> {
         let screen_position = Vector2::new(screen_pos.x, screen_pos.y);
         let screen_center = Vector2::new(
             self.viewport_size.width as f32 / 2.0,
@@ -99,23 +129,24 @@ impl Camera {
         let mut world_offset = screen_offset / self.zoom;
         world_offset.y = -world_offset.y;
 
-        self.position + world_offset
+        self.position + world_offset.cast::<f64>()
     }
 
-    pub fn update_lmb_state(&mut self, lmb_is_pressed: bool) {
-        self.lmb_is_pressed = lmb_is_pressed;
+    /// While panning, cursor movement drags the view
+    pub fn set_panning(&mut self, is_panning: bool) {
+        self.is_panning = is_panning;
     }
 
     pub fn update_cursor_position(&mut self, cursor_position: LogicalPosition<f32>) {
         if approx_eq!(f32, self.cursor_position.x, 0.)
             && approx_eq!(f32, self.cursor_position.y, 0.)
-            && self.lmb_is_pressed
+            && self.is_panning
         {
             self.cursor_position = cursor_position;
             return;
         }
 
-        if self.lmb_is_pressed {
+        if self.is_panning {
             let old_x = self.cursor_position.x;
             let old_y = self.cursor_position.y;
 
@@ -125,9 +156,44 @@ impl Camera {
             let delta_x = old_x - new_x;
             let delta_y = old_y - new_y;
 
-            self.position.x += delta_x / self.zoom;
-            self.position.y -= delta_y / self.zoom;
+            self.position.x += (delta_x / self.zoom) as f64;
+            self.position.y -= (delta_y / self.zoom) as f64;
         }
         self.cursor_position = cursor_position;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector4;
+
+    #[test]
+    fn keeps_precision_far_from_the_origin() {
+        let mut camera = Camera::new(LogicalSize::new(800, 600));
+        let far = 8e9;
+        camera.set_position(Vector2::new(far + 0.25, -far));
+
+        // A quarter of a world unit would be lost in f32 at this distance
+        let matrix = camera.calculate_view_projection_matrix(Vector2::new(far, -far));
+        let clip_position = matrix * Vector4::new(0.5, 0., 0., 1.);
+        assert!((clip_position.x - 0.25 / 400.).abs() < 1e-6);
+
+        let world_position = camera.screen_to_world_position(LogicalPosition::new(401., 300.));
+        assert_eq!(world_position, Vector2::new(far + 1.25, -far));
+    }
+
+    #[test]
+    fn fits_rectangles() {
+        let mut camera = Camera::new(LogicalSize::new(800, 600));
+        camera.fit(Vector2::new(-100., 0.), Vector2::new(100., 50.));
+        assert_eq!(camera.position(), Vector2::new(0., 25.));
+        assert!(approx_eq!(f32, camera.zoom(), 3.6));
+
+        // Zoom stays within its limits
+        camera.fit(Vector2::new(0., 0.), Vector2::new(1e6, 1e6));
+        assert_eq!(camera.zoom(), ZOOM_MIN);
+        camera.fit(Vector2::new(3., 3.), Vector2::new(3., 3.));
+        assert_eq!(camera.zoom(), ZOOM_MAX);
+    }
+}
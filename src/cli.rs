@@ -0,0 +1,356 @@
+// Command-line interface
+
+use crate::camera::{ZOOM_MAX, ZOOM_MIN};
+use crate::simulation::continuous::{ContinuousRule, Lenia, MAX_KERNEL_RADIUS, SmoothLife};
+use crate::simulation::rule::{Rule, RuleParseError, table, topology};
+use clap::error::ErrorKind;
+use clap::{ArgGroup, CommandFactory, Parser, ValueEnum};
+use std::path::{Path, PathBuf};
+
+// Larger universes don't fit in memory as a dense grid
+const UNIVERSE_SIZE_MAX: usize = 1 << 16;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Game of Life on wgpu")]
+#[command(group(ArgGroup::new("stop").args(["generations", "until_stable"]).multiple(true)))]
+pub struct Cli {
+    /// Pattern file in RLE, plaintext, Life 1.05/1.06 or macrocell format
+    pub pattern: Option<PathBuf>,
+
+    /// Rule string or a .rule file, overrides the rule of the pattern file
+    #[arg(long, value_parser = parse_rule)]
+    pub rule: Option<Rule>,
+
+    /// Universe size in cells, large enough for the pattern if not set
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_universe_size)]
+    pub universe_size: Option<(usize, usize)>,
+
+    /// Simulation engine, chosen by the universe size if not set
+    #[arg(long, value_enum)]
+    pub engine: Option<EngineKind>,
+
+    /// Every HashLife step advances 2^N generations
+    #[arg(long, value_name = "N", default_value_t = 0,
+          value_parser = clap::value_parser!(u32).range(0..=48))]
+    pub hashlife_step: u32,
+
+    /// Continuous automaton instead of a rule, seeded by the pattern or by noise
+    #[arg(long, value_enum, conflicts_with_all = ["rule", "engine", "hashlife_step", "headless"])]
+    pub continuous: Option<ContinuousKind>,
+
+    /// Kernel radius of the continuous automaton in cells
+    #[arg(long, value_name = "R", requires = "continuous",
+          value_parser = clap::value_parser!(u32).range(1..=MAX_KERNEL_RADIUS as i64))]
+    pub kernel_radius: Option<u32>,
+
+    /// Relative heights of the Lenia kernel rings from the inside out
+    #[arg(long, value_name = "B1,B2,..", requires = "continuous", value_delimiter = ',',
+          value_parser = parse_peak)]
+    pub kernel_peaks: Option<Vec<f32>>,
+
+    /// Center of the Lenia growth function
+    #[arg(long, value_name = "MU", requires = "continuous", value_parser = parse_fraction)]
+    pub growth_mu: Option<f32>,
+
+    /// Width of the Lenia growth function
+    #[arg(long, value_name = "SIGMA", requires = "continuous", value_parser = parse_fraction)]
+    pub growth_sigma: Option<f32>,
+
+    /// Time step of the continuous automaton
+    #[arg(long, value_name = "DT", requires = "continuous", value_parser = parse_fraction)]
+    pub dt: Option<f32>,
+
+    /// Initial camera zoom
+    #[arg(long, default_value_t = 1., value_parser = parse_zoom)]
+    pub zoom: f32,
+
+    /// Cell in the middle of the window, relative to the universe center
+    #[arg(long, value_name = "X,Y", value_parser = parse_cell, allow_hyphen_values = true)]
+    pub center: Option<(i64, i64)>,
+
+    /// Start with the simulation paused
+    #[arg(long)]
+    pub paused: bool,
+
+    /// Generations per second, one per frame if not set, faster than 1920 runs at max speed
+    #[arg(long, value_name = "GPS", value_parser = parse_generations_per_second)]
+    pub gps: Option<f64>,
+
+    /// Window size in physical pixels, 75% of the primary monitor if not set
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_window_size)]
+    pub window_size: Option<(u32, u32)>,
+
+    /// TOML file of key bindings, actions that are not in it keep their default keys
+    #[arg(long, value_name = "FILE")]
+    pub keymap: Option<PathBuf>,
+
+    /// Generations run by the step N key
+    #[arg(long, value_name = "N", default_value_t = 100,
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub step_size: u64,
+
+    /// Memory kept for undo in megabytes, the oldest changes are forgotten past it
+    #[arg(long, value_name = "MB", default_value_t = 64)]
+    pub undo_memory: usize,
+
+    /// Run the pattern without a window and write out the result
+    #[arg(long, requires = "pattern", requires = "stop",
+          conflicts_with_all = ["zoom", "center", "paused", "gps", "window_size", "keymap",
+                                "step_size", "undo_memory"])]
+    pub headless: bool,
+
+    /// Generations to run in headless mode
+    #[arg(long, value_name = "N", requires = "headless")]
+    pub generations: Option<u64>,
+
+    /// Stop headless mode once the pattern dies out, settles or starts oscillating
+    #[arg(long, requires = "headless")]
+    pub until_stable: bool,
+
+    /// Generations --until-stable runs at most if the pattern never repeats
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 100_000,
+        requires = "until_stable"
+    )]
+    pub max_generations: u64,
+
+    /// Headless output file, the format is chosen by the extension, RLE to stdout if not set
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    pub output: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Parses the process arguments, exits with a usage error on invalid combinations
+    pub fn parse_checked() -> Self {
+        let cli = Self::parse();
+        if cli.headless && cli.engine == Some(EngineKind::Gpu) {
+            Self::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "the GPU engine is not available with --headless",
+                )
+                .exit();
+        }
+        let lenia_only =
+            cli.kernel_peaks.is_some() || cli.growth_mu.is_some() || cli.growth_sigma.is_some();
+        if cli.continuous == Some(ContinuousKind::SmoothLife) && lenia_only {
+            Self::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--kernel-peaks, --growth-mu and --growth-sigma only apply to Lenia",
+                )
+                .exit();
+        }
+        cli
+    }
+
+    /// Continuous automaton of `--continuous`, parameters that are not set keep their defaults
+    pub fn continuous_rule(&self) -> Option<ContinuousRule> {
+        Some(match self.continuous? {
+            ContinuousKind::Lenia => {
+                let default = Lenia::default();
+                ContinuousRule::Lenia(Lenia {
+                    radius: self.kernel_radius.unwrap_or(default.radius),
+                    peaks: self.kernel_peaks.clone().unwrap_or(default.peaks),
+                    mu: self.growth_mu.unwrap_or(default.mu),
+                    sigma: self.growth_sigma.unwrap_or(default.sigma),
+                    dt: self.dt.unwrap_or(default.dt),
+                })
+            }
+            ContinuousKind::SmoothLife => {
+                let default = SmoothLife::default();
+                ContinuousRule::SmoothLife(SmoothLife {
+                    radius: self.kernel_radius.unwrap_or(default.radius),
+                    dt: self.dt.unwrap_or(default.dt),
+                    ..default
+                })
+            }
+        })
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineKind {
+    Cpu,
+    Gpu,
+    #[value(name = "hashlife")]
+    HashLife,
+    // 64 cells per word on the CPU
+    Packed,
+    // Unbounded universe of tiles
+    Sparse,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContinuousKind {
+    Lenia,
+    #[value(name = "smoothlife")]
+    SmoothLife,
+}
+
+fn parse_pair<T: std::str::FromStr>(value: &str, separator: char) -> Option<(T, T)> {
+    let (first, second) = value.split_once(separator)?;
+    Some((first.trim().parse().ok()?, second.trim().parse().ok()?))
+}
+
+// Rule tables are loaded from .rule files
+fn parse_rule(value: &str) -> Result<Rule, String> {
+    // A rule table path may end with a topology, `wireworld.rule:T100,50`
+    let (path, topology) = topology::split_suffix(value).unwrap_or((value, None));
+    if path.to_ascii_lowercase().ends_with(".rule") {
+        let rule_table = table::load(Path::new(path)).map_err(|error| format!("{error:#}"))?;
+        let mut rule = Rule::from_table(rule_table);
+        rule.set_topology(topology);
+        return Ok(rule);
+    }
+    value
+        .parse()
+        .map_err(|error: RuleParseError| error.to_string())
+}
+
+fn parse_universe_size(value: &str) -> Result<(usize, usize), String> {
+    let (width, height) = parse_pair::<usize>(value, 'x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, found \"{value}\""))?;
+    if !(1..=UNIVERSE_SIZE_MAX).contains(&width) || !(1..=UNIVERSE_SIZE_MAX).contains(&height) {
+        return Err(format!("both sides must be in 1..={UNIVERSE_SIZE_MAX}"));
+    }
+    Ok((width, height))
+}
+
+fn parse_window_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = parse_pair::<u32>(value, 'x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, found \"{value}\""))?;
+    if width == 0 || height == 0 {
+        return Err("both sides must be positive".to_string());
+    }
+    Ok((width, height))
+}
+
+fn parse_zoom(value: &str) -> Result<f32, String> {
+    let zoom: f32 = value
+        .parse()
+        .map_err(|_| format!("expected a number, found \"{value}\""))?;
+    if !(ZOOM_MIN..=ZOOM_MAX).contains(&zoom) {
+        return Err(format!("must be in {ZOOM_MIN}..={ZOOM_MAX}"));
+    }
+    Ok(zoom)
+}
+
+fn parse_cell(value: &str) -> Result<(i64, i64), String> {
+    parse_pair(value, ',').ok_or_else(|| format!("expected X,Y, found \"{value}\""))
+}
+
+fn parse_fraction(value: &str) -> Result<f32, String> {
+    let fraction: f32 = value
+        .parse()
+        .map_err(|_| format!("expected a number, found \"{value}\""))?;
+    if !(fraction > 0. && fraction <= 1.) {
+        return Err("must be in (0, 1]".to_string());
+    }
+    Ok(fraction)
+}
+
+fn parse_peak(value: &str) -> Result<f32, String> {
+    let peak: f32 = value
+        .parse()
+        .map_err(|_| format!("expected a number, found \"{value}\""))?;
+    if !(0. ..=1.).contains(&peak) {
+        return Err("must be in 0..=1".to_string());
+    }
+    Ok(peak)
+}
+
+fn parse_generations_per_second(value: &str) -> Result<f64, String> {
+    let generations_per_second: f64 = value
+        .parse()
+        .map_err(|_| format!("expected a number, found \"{value}\""))?;
+    if !generations_per_second.is_finite() || generations_per_second <= 0. {
+        return Err("must be positive".to_string());
+    }
+    Ok(generations_per_second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_arguments() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "game_of_life_wgpu",
+            "glider.rle",
+            "--rule",
+            "23/3",
+            "--universe-size",
+            "640x480",
+            "--center=-10,20",
+            "--paused",
+            "--gps",
+            "7.5",
+        ])
+        .unwrap();
+        assert_eq!(cli.pattern, Some(PathBuf::from("glider.rle")));
+        assert_eq!(cli.rule, Some(Rule::LIFE));
+        assert_eq!(cli.universe_size, Some((640, 480)));
+        assert_eq!(cli.center, Some((-10, 20)));
+        assert!(cli.paused);
+        assert_eq!(cli.gps, Some(7.5));
+        assert_eq!(cli.zoom, 1.);
+        assert_eq!(cli.continuous_rule(), None);
+
+        let cli = Cli::try_parse_from([
+            "game_of_life_wgpu",
+            "--continuous",
+            "lenia",
+            "--kernel-radius",
+            "20",
+            "--kernel-peaks",
+            "0.5,1,0.667",
+            "--dt",
+            "0.05",
+        ])
+        .unwrap();
+        let Some(ContinuousRule::Lenia(lenia)) = cli.continuous_rule() else {
+            panic!("expected Lenia");
+        };
+        assert_eq!(lenia.radius, 20);
+        assert_eq!(lenia.peaks, [0.5, 1., 0.667]);
+        assert_eq!(lenia.dt, 0.05);
+        assert_eq!(lenia.mu, Lenia::default().mu);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for arguments in [
+            &["--rule", "B36/S2x"] as &[&str],
+            &["--universe-size", "640"],
+            &["--universe-size", "0x480"],
+            &["--window-size", "800x"],
+            &["--zoom", "100"],
+            &["--gps", "0"],
+            &["--hashlife-step", "64"],
+            &["--engine", "opengl"],
+            &["--headless", "--generations=10"],
+            &["--generations", "10"],
+            &["glider.rle", "--headless"],
+            &[
+                "glider.rle",
+                "--headless",
+                "--generations=10",
+                "--max-generations=10",
+            ],
+            &["--continuous", "lenia", "--rule", "B3/S23"],
+            &["--kernel-radius", "10"],
+            &["--continuous", "lenia", "--kernel-radius", "0"],
+            &["--continuous", "lenia", "--kernel-peaks", "1,2"],
+            &["--continuous", "smoothlife", "--dt", "0"],
+        ] {
+            let result = Cli::try_parse_from(["game_of_life_wgpu"].iter().chain(arguments.iter()));
+            assert!(result.is_err(), "{arguments:?}");
+        }
+    }
+}
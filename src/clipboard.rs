@@ -0,0 +1,80 @@
+// Patterns on the system clipboard, as RLE text
+
+use crate::pattern::{self, Format, Pattern};
+
+/// System clipboard, if there is one
+///
+/// Without a clipboard, e.g. without a display, copying and pasting only go through the
+/// clipboard of the app.
+pub struct SystemClipboard {
+    clipboard: Option<arboard::Clipboard>,
+}
+
+impl SystemClipboard {
+    pub fn new() -> Self {
+        let clipboard = arboard::Clipboard::new()
+            .inspect_err(|err| log::warn!("System clipboard is unavailable: {err}"))
+            .ok();
+        Self { clipboard }
+    }
+
+    /// Puts the pattern on the clipboard as RLE text
+    pub fn set_pattern(&mut self, pattern: &Pattern) {
+        let Some(clipboard) = &mut self.clipboard else {
+            return;
+        };
+        if let Err(err) = clipboard.set_text(pattern::write(pattern, Format::Rle)) {
+            log::warn!("Failed to copy the pattern to the system clipboard: {err}");
+        }
+    }
+
+    /// Pattern of the text on the clipboard, any format that `pattern::parse` reads
+    ///
+    /// None if the clipboard is empty or holds something else.
+    pub fn pattern(&mut self) -> Option<Pattern> {
+        let text = self.clipboard.as_mut()?.get_text().ok()?;
+        parse_pattern(&text)
+    }
+}
+
+// Clipboard text that isn't a pattern is ignored, it's usually not meant for us
+fn parse_pattern(text: &str) -> Option<Pattern> {
+    pattern::parse(text)
+        .inspect_err(|err| log::debug!("The system clipboard doesn't hold a pattern: {err}"))
+        .ok()
+        .filter(|pattern| pattern.width > 0 && pattern.height > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_patterns_from_clipboard_text() {
+        let glider = parse_pattern("x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!").unwrap();
+        assert_eq!(glider.cells.len(), 5);
+        let plaintext = parse_pattern(".O.\n..O\nOOO\n").unwrap();
+        assert_eq!(plaintext.rows(), glider.rows());
+
+        // Copied selections keep their empty margins
+        let selection = Pattern {
+            width: 4,
+            height: 2,
+            cells: vec![(1, 0, 1)],
+            ..Default::default()
+        };
+        let copied = parse_pattern(&pattern::write(&selection, Format::Rle)).unwrap();
+        assert_eq!(copied.rows(), selection.rows());
+
+        assert!(parse_pattern("").is_none());
+        assert!(parse_pattern("Hello, world").is_none());
+    }
+
+    #[test]
+    fn works_without_a_clipboard() {
+        // Built directly, `new` would touch the real clipboard on machines with a display
+        let mut clipboard = SystemClipboard { clipboard: None };
+        clipboard.set_pattern(&Pattern::default());
+        assert!(clipboard.pattern().is_none());
+    }
+}
@@ -0,0 +1,384 @@
+// Drawing and erasing cells with the mouse, selections and pasting
+
+use crate::app_context::{AppContext, CellRectangle};
+use crate::graphics_context::GraphicsContext;
+use crate::history::Change;
+use crate::pattern::Pattern;
+use crate::simulation::{ALIVE, DEAD};
+use std::collections::HashMap;
+
+const SELECTION_COLOR: [f32; 4] = [0.3, 0.9, 0.3, 1.];
+const PASTE_COLOR: [f32; 4] = [1., 0.85, 0.2, 1.];
+// Larger paste previews only show their bounds
+const MAX_PREVIEW_CELLS: usize = 1 << 16;
+
+/// Cells drawn or erased while the left mouse button is held
+///
+/// The first cell decides, a dead one starts drawing and a live one starts erasing.
+#[derive(Debug)]
+pub struct Stroke {
+    state: u8,
+    last_cell: (i64, i64),
+    change: Change,
+}
+
+impl Stroke {
+    pub fn start(
+        app_context: &mut AppContext,
+        graphics_context: &GraphicsContext,
+        cell: (i64, i64),
+    ) -> Self {
+        Self::start_on(&mut (app_context, graphics_context), cell)
+    }
+
+    fn start_on(canvas: &mut impl Canvas, cell: (i64, i64)) -> Self {
+        let state = if canvas.get_cell(cell.0, cell.1) == DEAD {
+            ALIVE
+        } else {
+            DEAD
+        };
+        let mut stroke = Self {
+            state,
+            last_cell: cell,
+            change: Change::default(),
+        };
+        stroke.draw(canvas, cell);
+        stroke
+    }
+
+    /// Continues the stroke to the cell, filling the gap since the last one
+    pub fn extend(
+        &mut self,
+        app_context: &mut AppContext,
+        graphics_context: &GraphicsContext,
+        cell: (i64, i64),
+    ) {
+        self.extend_on(&mut (app_context, graphics_context), cell);
+    }
+
+    fn extend_on(&mut self, canvas: &mut impl Canvas, cell: (i64, i64)) {
+        if cell == self.last_cell {
+            return;
+        }
+        for cell in line(self.last_cell, cell).into_iter().skip(1) {
+            self.draw(canvas, cell);
+        }
+        self.last_cell = cell;
+    }
+
+    fn draw(&mut self, canvas: &mut impl Canvas, (x, y): (i64, i64)) {
+        let state_before = canvas.get_cell(x, y);
+        if state_before != self.state {
+            canvas.set_cell(x, y, self.state);
+            // Cells off a bounded universe and continuous automata stay as they were
+            self.change
+                .push_cell(x, y, state_before, canvas.get_cell(x, y));
+        }
+    }
+
+    /// Cells changed by the whole stroke, undone at once
+    pub fn finish(self) -> Change {
+        self.change
+    }
+}
+
+// Cells a stroke draws on, the engine of the app
+trait Canvas {
+    fn get_cell(&mut self, x: i64, y: i64) -> u8;
+    fn set_cell(&mut self, x: i64, y: i64, state: u8);
+}
+
+impl Canvas for (&mut AppContext, &GraphicsContext) {
+    fn get_cell(&mut self, x: i64, y: i64) -> u8 {
+        self.0.get_cell(self.1, x, y)
+    }
+
+    fn set_cell(&mut self, x: i64, y: i64, state: u8) {
+        self.0.set_cell(self.1, x, y, state);
+    }
+}
+
+/// Rectangle between the cell where dragging started and the cell under the cursor
+#[derive(Debug, Clone, Copy)]
+pub struct Selection {
+    pub anchor: (i64, i64),
+    pub corner: (i64, i64),
+}
+
+impl Selection {
+    pub fn new(cell: (i64, i64)) -> Self {
+        Self {
+            anchor: cell,
+            corner: cell,
+        }
+    }
+
+    /// Selected cells are within [min, max), both corner cells are selected
+    pub fn bounds(&self) -> ((i64, i64), (i64, i64)) {
+        (
+            (
+                self.anchor.0.min(self.corner.0),
+                self.anchor.1.min(self.corner.1),
+            ),
+            (
+                self.anchor.0.max(self.corner.0) + 1,
+                self.anchor.1.max(self.corner.1) + 1,
+            ),
+        )
+    }
+
+    /// Copies the selected cells
+    pub fn copy(
+        &self,
+        app_context: &mut AppContext,
+        graphics_context: &GraphicsContext,
+    ) -> Pattern {
+        let (min, max) = self.bounds();
+        app_context.copy_region(graphics_context, min, max)
+    }
+
+    /// Copies the selected cells and kills them
+    pub fn cut(
+        &self,
+        app_context: &mut AppContext,
+        graphics_context: &GraphicsContext,
+    ) -> (Pattern, Change) {
+        let pattern = self.copy(app_context, graphics_context);
+        let change = kill_cells(app_context, graphics_context, &pattern, self.bounds().0);
+        (pattern, change)
+    }
+
+    /// Kills the selected cells
+    pub fn clear(
+        &self,
+        app_context: &mut AppContext,
+        graphics_context: &GraphicsContext,
+    ) -> Change {
+        let pattern = self.copy(app_context, graphics_context);
+        kill_cells(app_context, graphics_context, &pattern, self.bounds().0)
+    }
+
+    pub fn outline(&self) -> CellRectangle {
+        let (min, max) = self.bounds();
+        CellRectangle {
+            min,
+            max,
+            color: SELECTION_COLOR,
+        }
+    }
+}
+
+// Kills the live cells of the pattern copied at `at`
+fn kill_cells(
+    app_context: &mut AppContext,
+    graphics_context: &GraphicsContext,
+    pattern: &Pattern,
+    at: (i64, i64),
+) -> Change {
+    let mut change = Change::default();
+    let mut dead_cells = Vec::with_capacity(pattern.cells.len());
+    for &(x, y, state) in &pattern.cells {
+        let (x, y) = (at.0 + x, at.1 + y);
+        change.push_cell(x, y, state, DEAD);
+        dead_cells.push((x, y, DEAD));
+    }
+    app_context.set_cells(graphics_context, &dead_cells);
+    change
+}
+
+/// How pasted cells are combined with the cells under them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PasteMode {
+    // Live pasted cells are added
+    #[default]
+    Or,
+    // Live pasted cells toggle the cells under them
+    Xor,
+    // The whole rectangle is replaced, dead cells included
+    Copy,
+}
+
+impl PasteMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Or => Self::Xor,
+            Self::Xor => Self::Copy,
+            Self::Copy => Self::Or,
+        }
+    }
+
+    /// New state of a cell with `state` under a pasted cell with `pasted`
+    pub fn combine(self, state: u8, pasted: u8) -> u8 {
+        match self {
+            Self::Or if pasted == DEAD => state,
+            Self::Xor if pasted == DEAD => state,
+            Self::Xor if state != DEAD => DEAD,
+            Self::Or | Self::Xor | Self::Copy => pasted,
+        }
+    }
+}
+
+/// Pastes the pattern with its top-left at `at`
+pub fn paste(
+    app_context: &mut AppContext,
+    graphics_context: &GraphicsContext,
+    pattern: &Pattern,
+    at: (i64, i64),
+    mode: PasteMode,
+) -> Change {
+    let size = (pattern.width as i64, pattern.height as i64);
+    let cell_states = |cells: &[(i64, i64, u8)]| -> HashMap<_, _> {
+        cells.iter().map(|&(x, y, state)| ((x, y), state)).collect()
+    };
+    let under = app_context.copy_region(graphics_context, at, (at.0 + size.0, at.1 + size.1));
+    let under = cell_states(&under.cells);
+    let pasted = cell_states(&pattern.cells);
+    // Only cells that are alive on either side can change
+    let positions = pasted.keys().chain(
+        under
+            .keys()
+            .filter(|position| !pasted.contains_key(position)),
+    );
+    let mut cells = Vec::new();
+    let mut change = Change::default();
+    for &(x, y) in positions {
+        let state = under.get(&(x, y)).copied().unwrap_or(DEAD);
+        let new_state = mode.combine(state, pasted.get(&(x, y)).copied().unwrap_or(DEAD));
+        if new_state != state {
+            let (x, y) = (at.0 + x, at.1 + y);
+            cells.push((x, y, new_state));
+            change.push_cell(x, y, state, new_state);
+        }
+    }
+    app_context.set_cells(graphics_context, &cells);
+    change
+}
+
+/// Top-left cell of the pattern centered on the cell
+pub fn paste_position(pattern: &Pattern, cell: (i64, i64)) -> (i64, i64) {
+    (
+        cell.0 - pattern.width as i64 / 2,
+        cell.1 - pattern.height as i64 / 2,
+    )
+}
+
+/// Outlines of the live cells of the pattern at `at` and of its bounds
+pub fn paste_preview(pattern: &Pattern, at: (i64, i64)) -> Vec<CellRectangle> {
+    let mut rectangles = Vec::new();
+    if pattern.cells.len() <= MAX_PREVIEW_CELLS {
+        rectangles.extend(pattern.cells.iter().map(|&(x, y, _)| CellRectangle {
+            min: (at.0 + x, at.1 + y),
+            max: (at.0 + x + 1, at.1 + y + 1),
+            color: PASTE_COLOR,
+        }));
+    }
+    rectangles.push(CellRectangle {
+        min: at,
+        max: (at.0 + pattern.width as i64, at.1 + pattern.height as i64),
+        color: PASTE_COLOR,
+    });
+    rectangles
+}
+
+/// Cells of the line from `from` to `to` including both ends, Bresenham's algorithm
+pub fn line(from: (i64, i64), to: (i64, i64)) -> Vec<(i64, i64)> {
+    let (dx, dy) = (
+        to.0.abs_diff(from.0) as i64,
+        -(to.1.abs_diff(from.1) as i64),
+    );
+    let step_x = if from.0 < to.0 { 1 } else { -1 };
+    let step_y = if from.1 < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    let (mut x, mut y) = from;
+    let mut cells = Vec::with_capacity(dx.max(-dy) as usize + 1);
+    loop {
+        cells.push((x, y));
+        if (x, y) == to {
+            return cells;
+        }
+        let doubled_error = 2 * error;
+        if doubled_error >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled_error <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Universe;
+
+    // A bounded universe, like the dense engines
+    impl Canvas for Universe {
+        fn get_cell(&mut self, x: i64, y: i64) -> u8 {
+            Universe::get_cell(self, x, y)
+        }
+
+        fn set_cell(&mut self, x: i64, y: i64, state: u8) {
+            Universe::set_cell(self, x, y, state);
+        }
+    }
+
+    #[test]
+    fn lines_have_no_gaps() {
+        assert_eq!(line((2, 3), (2, 3)), [(2, 3)]);
+        assert_eq!(line((0, 0), (3, 1)), [(0, 0), (1, 0), (2, 1), (3, 1)]);
+        assert_eq!(line((1, 1), (-1, -1)), [(1, 1), (0, 0), (-1, -1)]);
+        assert_eq!(line((0, 0), (0, -2)), [(0, 0), (0, -1), (0, -2)]);
+
+        // Consecutive cells are neighbours and every column is covered once
+        let cells = line((-5, 7), (20, -3));
+        assert_eq!(cells.len(), 26);
+        for pair in cells.windows(2) {
+            assert_eq!(pair[1].0 - pair[0].0, 1);
+            assert!((pair[1].1 - pair[0].1).abs() <= 1);
+        }
+        assert_eq!(cells.last(), Some(&(20, -3)));
+    }
+
+    #[test]
+    fn selections_include_both_corners() {
+        let mut selection = Selection::new((4, -2));
+        assert_eq!(selection.bounds(), ((4, -2), (5, -1)));
+        selection.corner = (1, 3);
+        assert_eq!(selection.bounds(), ((1, -2), (5, 4)));
+    }
+
+    #[test]
+    fn combines_pasted_cells() {
+        let cases = [
+            // (state, pasted, or, xor, copy)
+            (DEAD, DEAD, DEAD, DEAD, DEAD),
+            (DEAD, ALIVE, ALIVE, ALIVE, ALIVE),
+            (ALIVE, DEAD, ALIVE, ALIVE, DEAD),
+            (ALIVE, ALIVE, ALIVE, DEAD, ALIVE),
+            (2, 3, 3, DEAD, 3),
+        ];
+        for (state, pasted, or, xor, copy) in cases {
+            assert_eq!(PasteMode::Or.combine(state, pasted), or);
+            assert_eq!(PasteMode::Xor.combine(state, pasted), xor);
+            assert_eq!(PasteMode::Copy.combine(state, pasted), copy);
+        }
+    }
+
+    #[test]
+    fn strokes_skip_cells_outside_of_bounded_universes() {
+        let mut universe = Universe::new(4, 4);
+        let mut stroke = Stroke::start_on(&mut universe, (-2, 1));
+        assert!(stroke.change.is_empty());
+
+        // Only the cells of the line inside of the universe are drawn and recorded
+        stroke.extend_on(&mut universe, (5, 1));
+        let mut change = Change::default();
+        for x in 0..4 {
+            change.push_cell(x, 1, DEAD, ALIVE);
+        }
+        assert_eq!(stroke.finish(), change);
+        assert_eq!(universe.population(), 4);
+    }
+}
@@ -0,0 +1,619 @@
+// Game of Life simulation in a compute shader
+// The board lives in two storage textures, each generation reads one and writes the other
+// Larger than Life rules take a second compute shader that sums rows first
+
+pub mod continuous;
+
+use crate::simulation::Universe;
+use crate::simulation::rule::larger_than_life::LargerThanLife;
+use crate::simulation::rule::topology::TopologyKind;
+use crate::simulation::rule::{Neighbourhood, Rule};
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer, BufferDescriptor,
+    BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, Extent3d, MapMode, Origin3d, PollType, Queue, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, TexelCopyBufferInfo, TexelCopyBufferLayout,
+    TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+const WORKGROUP_SIZE: u32 = 8;
+const ROW_WORKGROUP_SIZE: u32 = 64;
+
+// `Rule` in life_cs.wgsl
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy)]
+struct RuleUniform {
+    table: [u32; 16],
+    states: u32,
+    topology: u32,
+    twisted_columns: u32,
+    _padding: u32,
+}
+
+impl From<&Rule> for RuleUniform {
+    fn from(rule: &Rule) -> Self {
+        let (topology, twisted_columns) = topology_of(rule);
+        Self {
+            table: *rule.table(),
+            states: rule.states() as u32,
+            topology,
+            twisted_columns,
+            _padding: 0,
+        }
+    }
+}
+
+// `topology` and `twisted_columns` of the shaders, the plane without a topology
+fn topology_of(rule: &Rule) -> (u32, u32) {
+    match rule.topology().map(|topology| topology.kind()) {
+        None | Some(TopologyKind::Plane) => (0, 0),
+        Some(TopologyKind::Torus) => (1, 0),
+        Some(TopologyKind::KleinBottle { twisted_columns }) => (2, twisted_columns as u32),
+        Some(TopologyKind::CrossSurface) => (3, 0),
+        Some(TopologyKind::Sphere) => (4, 0),
+    }
+}
+
+// `Rule` in larger_than_life_cs.wgsl
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy)]
+struct LargerThanLifeUniform {
+    range: i32,
+    middle: u32,
+    von_neumann: u32,
+    states: u32,
+    survival: [u32; 2],
+    birth: [u32; 2],
+    topology: u32,
+    twisted_columns: u32,
+    _padding: [u32; 2],
+}
+
+impl LargerThanLifeUniform {
+    fn new(rule: &Rule, larger_than_life: &LargerThanLife) -> Self {
+        let (survival_min, survival_max) = larger_than_life.survival();
+        let (birth_min, birth_max) = larger_than_life.birth();
+        let (topology, twisted_columns) = topology_of(rule);
+        Self {
+            range: larger_than_life.range() as i32,
+            middle: larger_than_life.includes_middle() as u32,
+            von_neumann: (rule.neighbourhood() == Neighbourhood::VonNeumann) as u32,
+            states: rule.states() as u32,
+            survival: [survival_min as u32, survival_max as u32],
+            birth: [birth_min as u32, birth_max as u32],
+            topology,
+            twisted_columns,
+            _padding: [0; 2],
+        }
+    }
+}
+
+// Pipelines and resources of Larger than Life rules, only created once such a rule is uploaded
+struct LargerThanLifePass {
+    rule_buffer: Buffer,
+    row_sums_pipeline: ComputePipeline,
+    pipeline: ComputePipeline,
+    // [i] reads textures[i], like `GpuSimulation::compute_bind_groups`
+    row_sums_bind_groups: [BindGroup; 2],
+    bind_groups: [BindGroup; 2],
+}
+
+impl LargerThanLifePass {
+    fn new(device: &Device, width: u32, height: u32, texture_views: &[TextureView; 2]) -> Self {
+        let shader_module = create_topology_shader_module(
+            device,
+            "larger_than_life_cs.wgsl",
+            include_str!("shaders/larger_than_life_cs.wgsl"),
+        );
+
+        let row_sums_texture = device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R32Uint,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        });
+        let row_sums_view = row_sums_texture.create_view(&TextureViewDescriptor::default());
+
+        let rule_buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: size_of::<LargerThanLifeUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: None,
+                layout: None,
+                module: &shader_module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let row_sums_pipeline = create_pipeline("row_sums_main");
+        let pipeline = create_pipeline("cs_main");
+
+        let row_sums_bind_group_layout = row_sums_pipeline.get_bind_group_layout(0);
+        let create_row_sums_bind_group = |current: usize| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &row_sums_bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&texture_views[current]),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(&row_sums_view),
+                    },
+                ],
+            })
+        };
+        let bind_group_layout = pipeline.get_bind_group_layout(0);
+        let create_bind_group = |current: usize| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&texture_views[current]),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&texture_views[1 - current]),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: rule_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::TextureView(&row_sums_view),
+                    },
+                ],
+            })
+        };
+
+        Self {
+            row_sums_bind_groups: [create_row_sums_bind_group(0), create_row_sums_bind_group(1)],
+            bind_groups: [create_bind_group(0), create_bind_group(1)],
+            rule_buffer,
+            row_sums_pipeline,
+            pipeline,
+        }
+    }
+}
+
+pub struct GpuSimulation {
+    width: u32,
+    height: u32,
+    textures: [Texture; 2],
+    texture_views: [TextureView; 2],
+    // `RuleUniform` of the current rule
+    rule_buffer: Buffer,
+    compute_pipeline: ComputePipeline,
+    // [i] reads textures[i] and writes textures[1 - i]
+    compute_bind_groups: [BindGroup; 2],
+    // Used instead of `compute_pipeline` while the rule is a Larger than Life rule
+    larger_than_life_pass: Option<LargerThanLifePass>,
+    rule: Rule,
+    current: usize,
+    generation: u64,
+}
+
+impl GpuSimulation {
+    pub fn new(device: &Device, width: u32, height: u32) -> Self {
+        let shader_module = create_topology_shader_module(
+            device,
+            "life_cs.wgsl",
+            include_str!("shaders/life_cs.wgsl"),
+        );
+
+        let create_texture = || {
+            device.create_texture(&TextureDescriptor {
+                label: None,
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R32Uint,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::STORAGE_BINDING
+                    | TextureUsages::COPY_DST
+                    | TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })
+        };
+        let textures = [create_texture(), create_texture()];
+        let texture_views = [
+            textures[0].create_view(&TextureViewDescriptor::default()),
+            textures[1].create_view(&TextureViewDescriptor::default()),
+        ];
+
+        let rule_buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: size_of::<RuleUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: None,
+            layout: None,
+            module: &shader_module,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let bind_group_layout = compute_pipeline.get_bind_group_layout(0);
+        let create_bind_group = |current: usize| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&texture_views[current]),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&texture_views[1 - current]),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: rule_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let compute_bind_groups = [create_bind_group(0), create_bind_group(1)];
+
+        Self {
+            width,
+            height,
+            textures,
+            texture_views,
+            rule_buffer,
+            compute_pipeline,
+            compute_bind_groups,
+            larger_than_life_pass: None,
+            rule: Rule::LIFE,
+            current: 0,
+            generation: 0,
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    /// Both texture views, the current one is [`Self::current_index`]
+    pub fn texture_views(&self) -> &[TextureView; 2] {
+        &self.texture_views
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// Replaces the board and the rule with the universe, sizes must match
+    pub fn upload(&mut self, device: &Device, queue: &Queue, universe: &Universe) {
+        assert_eq!(universe.width(), self.width as usize);
+        assert_eq!(universe.height(), self.height as usize);
+        assert!(
+            universe.rule().rule_table().is_none(),
+            "The GPU engine can't run rule tables"
+        );
+
+        let texels: Vec<u32> = universe.cells().iter().map(|&state| state as u32).collect();
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &self.textures[self.current],
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            bytemuck::cast_slice(&texels),
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width * 4),
+                rows_per_image: Some(self.height),
+            },
+            self.texture_size(),
+        );
+        queue.write_buffer(
+            &self.rule_buffer,
+            0,
+            bytemuck::bytes_of(&RuleUniform::from(universe.rule())),
+        );
+        self.rule = universe.rule().clone();
+        if let Some(larger_than_life) = self.rule.larger_than_life() {
+            let pass = self.larger_than_life_pass.get_or_insert_with(|| {
+                LargerThanLifePass::new(device, self.width, self.height, &self.texture_views)
+            });
+            queue.write_buffer(
+                &pass.rule_buffer,
+                0,
+                bytemuck::bytes_of(&LargerThanLifeUniform::new(&self.rule, larger_than_life)),
+            );
+        }
+        self.generation = universe.generation();
+    }
+
+    /// Writes one cell of the current board, it must be on the board
+    pub fn set_cell(&self, queue: &Queue, x: u32, y: u32, state: u8) {
+        assert!(x < self.width && y < self.height);
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &self.textures[self.current],
+                mip_level: 0,
+                origin: Origin3d { x, y, z: 0 },
+                aspect: TextureAspect::All,
+            },
+            bytemuck::bytes_of(&(state as u32)),
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: None,
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Reads the board back into the universe, sizes must match
+    pub fn download(&self, device: &Device, queue: &Queue, universe: &mut Universe) {
+        assert_eq!(universe.width(), self.width as usize);
+        assert_eq!(universe.height(), self.height as usize);
+
+        let texels = read_texture(device, queue, &self.textures[self.current]);
+        for (index, &state) in texels.iter().enumerate() {
+            let x = index % self.width as usize;
+            let y = index / self.width as usize;
+            universe.set_cell(x as i64, y as i64, state as u8);
+        }
+        universe.set_generation(self.generation);
+    }
+
+    /// Advances the board by one generation
+    pub fn step(&mut self, device: &Device, queue: &Queue) {
+        let mut command_encoder =
+            device.create_command_encoder(&CommandEncoderDescriptor::default());
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            match &self.larger_than_life_pass {
+                Some(pass) if self.rule.larger_than_life().is_some() => {
+                    // One invocation per row
+                    compute_pass.set_pipeline(&pass.row_sums_pipeline);
+                    compute_pass.set_bind_group(0, &pass.row_sums_bind_groups[self.current], &[]);
+                    compute_pass.dispatch_workgroups(
+                        self.height.div_ceil(ROW_WORKGROUP_SIZE),
+                        1,
+                        1,
+                    );
+                    compute_pass.set_pipeline(&pass.pipeline);
+                    compute_pass.set_bind_group(0, &pass.bind_groups[self.current], &[]);
+                }
+                _ => {
+                    compute_pass.set_pipeline(&self.compute_pipeline);
+                    compute_pass.set_bind_group(0, &self.compute_bind_groups[self.current], &[]);
+                }
+            }
+            compute_pass.dispatch_workgroups(
+                self.width.div_ceil(WORKGROUP_SIZE),
+                self.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        queue.submit([command_encoder.finish()]);
+
+        self.current = 1 - self.current;
+        self.generation += 1;
+    }
+
+    fn texture_size(&self) -> Extent3d {
+        Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+// The topology snippet is shared by the compute shaders of counting rules
+fn create_topology_shader_module(device: &Device, label: &str, source: &str) -> ShaderModule {
+    let source = format!("{}\n{source}", include_str!("shaders/topology.wgsl"));
+    device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(label),
+        source: ShaderSource::Wgsl(source.into()),
+    })
+}
+
+/// Checks that the boards of a universe fit in the textures and readback buffers of the device
+pub fn check_board_size(device: &Device, width: u32, height: u32) -> anyhow::Result<()> {
+    let limits = device.limits();
+    let max_size = limits.max_texture_dimension_2d;
+    anyhow::ensure!(
+        width <= max_size && height <= max_size,
+        "The GPU supports universes up to {max_size}x{max_size}, not {width}x{height}"
+    );
+    let readback_size = readback_bytes_per_row(width) as u64 * height as u64;
+    anyhow::ensure!(
+        readback_size <= limits.max_buffer_size,
+        "A {width}x{height} universe takes {readback_size} bytes, the GPU supports buffers up \
+         to {} bytes",
+        limits.max_buffer_size
+    );
+    Ok(())
+}
+
+// Rows of texture copies must be aligned
+fn readback_bytes_per_row(width: u32) -> u32 {
+    (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+}
+
+/// Row-major texels of a 32-bit single channel texture, waits for the copy
+fn read_texture(device: &Device, queue: &Queue, texture: &Texture) -> Vec<u32> {
+    let (width, height) = (texture.width(), texture.height());
+    let bytes_per_row = readback_bytes_per_row(width);
+    let readback_buffer = device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: bytes_per_row as u64 * height as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+    command_encoder.copy_texture_to_buffer(
+        TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        TexelCopyBufferInfo {
+            buffer: &readback_buffer,
+            layout: TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        texture.size(),
+    );
+    queue.submit([command_encoder.finish()]);
+
+    let buffer_slice = readback_buffer.slice(..);
+    buffer_slice.map_async(MapMode::Read, |result| {
+        result.expect("Failed to map readback buffer");
+    });
+    device
+        .poll(PollType::Wait)
+        .expect("Failed to wait for readback");
+
+    let mut texels = Vec::with_capacity(width as usize * height as usize);
+    {
+        let data = buffer_slice.get_mapped_range();
+        for row in data.chunks_exact(bytes_per_row as usize) {
+            texels.extend_from_slice(bytemuck::cast_slice(&row[..width as usize * 4]));
+        }
+    }
+    readback_buffer.unmap();
+    texels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::{DeviceDescriptor, Instance, InstanceDescriptor, Limits, RequestAdapterOptions};
+
+    pub(super) fn fallback_device() -> Option<(Device, Queue)> {
+        let instance = Instance::new(&InstanceDescriptor::from_env_or_default());
+        let adapter =
+            futures::executor::block_on(instance.request_adapter(&RequestAdapterOptions {
+                force_fallback_adapter: true,
+                ..Default::default()
+            }))
+            .ok()?;
+        futures::executor::block_on(adapter.request_device(&DeviceDescriptor {
+            required_limits: Limits::downlevel_defaults(),
+            ..Default::default()
+        }))
+        .ok()
+    }
+
+    #[test]
+    fn rejects_boards_past_the_device_limits() {
+        let Some((device, _)) = fallback_device() else {
+            eprintln!("No fallback adapter available, skipping");
+            return;
+        };
+        let max_size = device.limits().max_texture_dimension_2d;
+        assert!(check_board_size(&device, max_size, 1).is_ok());
+        assert!(check_board_size(&device, max_size + 1, 1).is_err());
+        assert!(check_board_size(&device, 1, max_size + 1).is_err());
+    }
+
+    #[test]
+    fn matches_cpu_stepper() {
+        let Some((device, queue)) = fallback_device() else {
+            eprintln!("No fallback adapter available, skipping");
+            return;
+        };
+
+        let rules = [
+            "B3/S23",
+            "B36/S23",
+            "B3678/S34678",
+            "B2/S",
+            "B0/S8",
+            "B3/S2-i34q",
+            "B2-a/S12",
+            "B2/S/C3",
+            "345/2/4",
+            "B3/S23/C8",
+            "B2/S013V",
+            "B2/S34H",
+            "R5,C0,M1,S34..58,B34..45,NM",
+            "R4,C0,M1,S41..81,B41..81,NM",
+            "R3,C4,M0,S2..9,B3..5,NN",
+            "R10,C0,M1,S123..212,B123..170,NM",
+            "B3/S23:P61,47",
+            "B3/S23:T61,47",
+            "B36/S23:K61*,47",
+            "B3/S23:K61,47*",
+            "B2/S/C3:C61,47",
+            "B3/S23:S53",
+            "R5,C0,M1,S34..58,B34..45,NM:T61,47",
+            "R3,C4,M0,S2..9,B3..5,NN:K61,47*",
+            "R4,C0,M1,S41..81,B41..81,NM:S53",
+        ];
+        for rule in rules {
+            let rule: Rule = rule.parse().unwrap();
+            // Odd sizes so that rows need padding on readback
+            let (width, height) = rule
+                .topology()
+                .map_or((61, 47), |topology| (topology.width(), topology.height()));
+            let mut universe = Universe::new(width as usize, height as usize);
+            universe.set_rule(rule.clone());
+            universe.fill_random(0, 0, width as i64, height as i64, 1);
+
+            let mut gpu_simulation = GpuSimulation::new(&device, width, height);
+            gpu_simulation.upload(&device, &queue, &universe);
+            let mut downloaded = Universe::new(width as usize, height as usize);
+            for _ in 0..32 {
+                universe.step();
+                gpu_simulation.step(&device, &queue);
+                gpu_simulation.download(&device, &queue, &mut downloaded);
+                assert_eq!(downloaded.cells(), universe.cells(), "{rule}");
+                assert_eq!(downloaded.generation(), universe.generation());
+            }
+        }
+    }
+}
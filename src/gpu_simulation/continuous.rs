@@ -0,0 +1,345 @@
+// Lenia and SmoothLife in a compute shader, the board lives in two f32 storage textures
+
+use crate::gpu_simulation::WORKGROUP_SIZE;
+use crate::simulation::continuous::{ContinuousRule, ContinuousUniverse};
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor,
+    Device, Extent3d, Origin3d, PipelineLayoutDescriptor, Queue, ShaderStages,
+    StorageTextureAccess, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension, include_wgsl,
+};
+
+// `Rule` in continuous_cs.wgsl
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy, Default)]
+struct RuleUniform {
+    kind: u32,
+    radius: i32,
+    dt: f32,
+    mu: f32,
+    sigma: f32,
+    alpha_n: f32,
+    birth: [f32; 2],
+    death: [f32; 2],
+    alpha_m: f32,
+    _padding: u32,
+}
+
+impl From<&ContinuousRule> for RuleUniform {
+    fn from(rule: &ContinuousRule) -> Self {
+        match rule {
+            ContinuousRule::Lenia(lenia) => Self {
+                kind: 0,
+                radius: lenia.radius as i32,
+                dt: lenia.dt,
+                mu: lenia.mu,
+                sigma: lenia.sigma,
+                ..Default::default()
+            },
+            ContinuousRule::SmoothLife(smooth_life) => Self {
+                kind: 1,
+                radius: smooth_life.radius as i32,
+                dt: smooth_life.dt,
+                alpha_n: smooth_life.alpha_n,
+                birth: [smooth_life.birth.0, smooth_life.birth.1],
+                death: [smooth_life.death.0, smooth_life.death.1],
+                alpha_m: smooth_life.alpha_m,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+pub struct GpuContinuousSimulation {
+    width: u32,
+    height: u32,
+    textures: [Texture; 2],
+    texture_views: [TextureView; 2],
+    compute_pipeline: ComputePipeline,
+    // [i] reads textures[i] and writes textures[1 - i]
+    compute_bind_groups: [BindGroup; 2],
+    rule: ContinuousRule,
+    current: usize,
+    generation: u64,
+}
+
+impl GpuContinuousSimulation {
+    /// The rule is fixed, its kernel is built once
+    pub fn new(device: &Device, width: u32, height: u32, rule: &ContinuousRule) -> Self {
+        let shader_module =
+            device.create_shader_module(include_wgsl!("../shaders/continuous_cs.wgsl"));
+
+        let create_texture = || {
+            device.create_texture(&TextureDescriptor {
+                label: None,
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R32Float,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::STORAGE_BINDING
+                    | TextureUsages::COPY_DST
+                    | TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })
+        };
+        let textures = [create_texture(), create_texture()];
+        let texture_views = [
+            textures[0].create_view(&TextureViewDescriptor::default()),
+            textures[1].create_view(&TextureViewDescriptor::default()),
+        ];
+
+        let rule_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&RuleUniform::from(rule)),
+            usage: BufferUsages::UNIFORM,
+        });
+        let kernel_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&rule.kernel()),
+            usage: BufferUsages::STORAGE,
+        });
+
+        // R32Float can't be filtered, the derived layout would ask for a filterable texture
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: TextureFormat::R32Float,
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                bind_group_layouts: &[&bind_group_layout],
+                ..Default::default()
+            })),
+            module: &shader_module,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let create_bind_group = |current: usize| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&texture_views[current]),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&texture_views[1 - current]),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: rule_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: kernel_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let compute_bind_groups = [create_bind_group(0), create_bind_group(1)];
+
+        Self {
+            width,
+            height,
+            textures,
+            texture_views,
+            compute_pipeline,
+            compute_bind_groups,
+            rule: rule.clone(),
+            current: 0,
+            generation: 0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn rule(&self) -> &ContinuousRule {
+        &self.rule
+    }
+
+    /// Both texture views, the current one is [`Self::current_index`]
+    pub fn texture_views(&self) -> &[TextureView; 2] {
+        &self.texture_views
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// Replaces the board with the universe, sizes must match
+    pub fn upload(&mut self, queue: &Queue, universe: &ContinuousUniverse) {
+        assert_eq!(universe.width(), self.width as usize);
+        assert_eq!(universe.height(), self.height as usize);
+
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &self.textures[self.current],
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            bytemuck::cast_slice(universe.cells()),
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width * 4),
+                rows_per_image: Some(self.height),
+            },
+            self.textures[self.current].size(),
+        );
+        self.generation = universe.generation();
+    }
+
+    /// Advances the board by one time step
+    pub fn step(&mut self, device: &Device, queue: &Queue) {
+        let mut command_encoder =
+            device.create_command_encoder(&CommandEncoderDescriptor::default());
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            compute_pass.set_pipeline(&self.compute_pipeline);
+            compute_pass.set_bind_group(0, &self.compute_bind_groups[self.current], &[]);
+            compute_pass.dispatch_workgroups(
+                self.width.div_ceil(WORKGROUP_SIZE),
+                self.height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        queue.submit([command_encoder.finish()]);
+
+        self.current = 1 - self.current;
+        self.generation += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu_simulation::read_texture;
+    use crate::gpu_simulation::tests::fallback_device;
+    use crate::simulation::continuous::{Lenia, SmoothLife};
+
+    // Reads the board back into the universe, sizes must match
+    fn download(
+        simulation: &GpuContinuousSimulation,
+        device: &Device,
+        queue: &Queue,
+        universe: &mut ContinuousUniverse,
+    ) {
+        assert_eq!(universe.width(), simulation.width as usize);
+        assert_eq!(universe.height(), simulation.height as usize);
+
+        let texels = read_texture(device, queue, &simulation.textures[simulation.current]);
+        for (index, &bits) in texels.iter().enumerate() {
+            let x = index % simulation.width as usize;
+            let y = index / simulation.width as usize;
+            universe.set_cell(x as i64, y as i64, f32::from_bits(bits));
+        }
+        universe.set_generation(simulation.generation);
+    }
+
+    #[test]
+    fn matches_cpu_reference() {
+        let Some((device, queue)) = fallback_device() else {
+            eprintln!("No fallback adapter available, skipping");
+            return;
+        };
+
+        let rules = [
+            ContinuousRule::Lenia(Lenia::default()),
+            ContinuousRule::Lenia(Lenia {
+                radius: 9,
+                peaks: vec![0.5, 1.],
+                mu: 0.2,
+                sigma: 0.03,
+                dt: 0.2,
+            }),
+            ContinuousRule::SmoothLife(SmoothLife {
+                radius: 8,
+                ..SmoothLife::default()
+            }),
+        ];
+        for rule in rules {
+            // Odd sizes so that rows need padding on readback
+            let mut universe = ContinuousUniverse::new(45, 37, rule.clone());
+            universe.fill_random(8, 6, 24, 20, 11);
+            let mut gpu_simulation = GpuContinuousSimulation::new(&device, 45, 37, &rule);
+            gpu_simulation.upload(&queue, &universe);
+            let mut downloaded = ContinuousUniverse::new(45, 37, rule.clone());
+            for _ in 0..8 {
+                universe.step();
+                gpu_simulation.step(&device, &queue);
+            }
+            download(&gpu_simulation, &device, &queue, &mut downloaded);
+            assert_eq!(downloaded.generation(), universe.generation());
+            for (gpu, cpu) in downloaded.cells().iter().zip(universe.cells()) {
+                assert!((gpu - cpu).abs() < 1e-3, "{rule}: {gpu} != {cpu}");
+            }
+        }
+    }
+}
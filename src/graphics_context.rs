@@ -15,7 +15,6 @@ pub struct GraphicsContext {
     pub window: Arc<Window>,
     #[allow(unused)]
     pub instance: Instance,
-    #[allow(unused)]
     pub adapter: Adapter,
     pub device: Arc<Device>,
     pub queue: Queue,
@@ -23,8 +22,12 @@ pub struct GraphicsContext {
 }
 
 impl GraphicsContext {
-    pub fn new(event_loop: &ActiveEventLoop) -> anyhow::Result<Self> {
-        let window = Arc::new(create_window(event_loop)?);
+    /// Window of `window_size`, 75% of the primary monitor if not set
+    pub fn new(
+        event_loop: &ActiveEventLoop,
+        window_size: Option<PhysicalSize<u32>>,
+    ) -> anyhow::Result<Self> {
+        let window = Arc::new(create_window(event_loop, window_size)?);
 
         let instance = Instance::new(&InstanceDescriptor::default());
         let surface = instance
@@ -41,8 +44,9 @@ impl GraphicsContext {
             futures::executor::block_on(adapter.request_device(&DeviceDescriptor {
                 required_features: Features::default() | Features::PUSH_CONSTANTS,
                 required_limits: Limits {
-                    // 4x4 matrix
                     max_push_constant_size: 64,
+                    // Board textures of the GPU engine can be as large as the adapter allows
+                    max_texture_dimension_2d: adapter.limits().max_texture_dimension_2d,
                     ..Default::default()
                 },
                 ..Default::default()
@@ -65,28 +69,36 @@ impl GraphicsContext {
     }
 }
 
-fn create_window(event_loop: &ActiveEventLoop) -> anyhow::Result<Window> {
-    let primary_monitor = event_loop
-        .primary_monitor()
-        .context("Failed to get primary monitor")?;
+fn create_window(
+    event_loop: &ActiveEventLoop,
+    window_size: Option<PhysicalSize<u32>>,
+) -> anyhow::Result<Window> {
+    let primary_monitor = event_loop.primary_monitor();
+    if primary_monitor.is_none() && window_size.is_none() {
+        anyhow::bail!("Failed to get primary monitor");
+    }
 
-    let monitor_size = primary_monitor.size();
-    let window_size = PhysicalSize::new(
-        (monitor_size.width as f32 * 0.75) as u32,
-        (monitor_size.height as f32 * 0.75) as u32,
-    );
-    let window_position = {
-        let x = (monitor_size.width / 2 - window_size.width / 2) as i32;
-        let y = (monitor_size.height / 2 - window_size.height / 2) as i32;
-        PhysicalPosition::new(x, y)
-    };
+    let window_size = window_size.unwrap_or_else(|| {
+        let monitor_size = primary_monitor.as_ref().unwrap().size();
+        PhysicalSize::new(
+            (monitor_size.width as f32 * 0.75) as u32,
+            (monitor_size.height as f32 * 0.75) as u32,
+        )
+    });
 
-    let window_attributes = WindowAttributes::default()
+    let mut window_attributes = WindowAttributes::default()
         .with_min_inner_size(PhysicalSize::new(1, 1))
         .with_inner_size(window_size)
-        .with_position(window_position)
         .with_visible(false);
 
+    // Centered on the primary monitor if there is one
+    if let Some(primary_monitor) = primary_monitor {
+        let monitor_size = primary_monitor.size();
+        let x = (monitor_size.width as i32 - window_size.width as i32) / 2;
+        let y = (monitor_size.height as i32 - window_size.height as i32) / 2;
+        window_attributes = window_attributes.with_position(PhysicalPosition::new(x, y));
+    }
+
     event_loop
         .create_window(window_attributes)
         .context("Failed to create window")
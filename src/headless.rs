@@ -0,0 +1,224 @@
+// Batch simulation without a window or GPU
+
+use crate::cli::{Cli, EngineKind};
+use crate::pattern::create_board;
+use crate::pattern::{self, Format, Pattern, PatternFile, macrocell};
+use crate::simulation::Universe;
+use crate::simulation::hashlife::HashLife;
+use crate::simulation::packed::PackedUniverse;
+use crate::simulation::sparse::SparseUniverse;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+enum Simulation {
+    Cpu(Universe),
+    HashLife(Box<HashLife>),
+    Packed(Box<PackedUniverse>),
+    Sparse(Box<SparseUniverse>),
+}
+
+impl Simulation {
+    fn generation(&self) -> u64 {
+        match self {
+            Simulation::Cpu(universe) => universe.generation(),
+            Simulation::HashLife(hashlife) => hashlife.generation(),
+            Simulation::Packed(packed_universe) => packed_universe.generation(),
+            Simulation::Sparse(sparse_universe) => sparse_universe.generation(),
+        }
+    }
+
+    fn population(&self) -> u64 {
+        match self {
+            Simulation::Cpu(universe) => universe.population() as u64,
+            Simulation::HashLife(hashlife) => hashlife.population(),
+            Simulation::Packed(packed_universe) => packed_universe.population(),
+            Simulation::Sparse(sparse_universe) => sparse_universe.population(),
+        }
+    }
+
+    // Equal states have equal hashes, the generation is not part of the state
+    fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match self {
+            Simulation::Cpu(universe) => universe.cells().hash(&mut hasher),
+            Simulation::HashLife(hashlife) => hashlife.live_cells().hash(&mut hasher),
+            Simulation::Packed(packed_universe) => packed_universe.cells().hash(&mut hasher),
+            Simulation::Sparse(sparse_universe) => sparse_universe.live_cells().hash(&mut hasher),
+        }
+        hasher.finish()
+    }
+
+    /// Advances by at most `max_generations`, HashLife takes the largest power of two step that fits
+    fn step(&mut self, max_generations: Option<u64>, step_exponent: u32) {
+        match self {
+            Simulation::Cpu(universe) => universe.step(),
+            Simulation::Packed(packed_universe) => packed_universe.step(),
+            Simulation::Sparse(sparse_universe) => sparse_universe.step(),
+            Simulation::HashLife(hashlife) => {
+                let step_exponent = match max_generations {
+                    Some(max_generations) => step_exponent.min(max_generations.ilog2()),
+                    None => step_exponent,
+                };
+                hashlife.set_step_exponent(step_exponent);
+                hashlife.step();
+            }
+        }
+    }
+}
+
+/// How the run ended
+enum Outcome {
+    Finished,
+    // `--max-generations` ran without a repeated state
+    Unsettled,
+    // Generation of the first occurrence of the repeated state and the period
+    Repeats { generation: u64, period: u64 },
+}
+
+/// Runs the pattern for `--generations` or until it repeats, then writes it out
+pub fn run(cli: &Cli, pattern_file: PatternFile) -> anyhow::Result<()> {
+    let board = create_board(cli, pattern_file)?;
+    let engine = match cli.engine {
+        Some(engine) => engine,
+        // Macrocell patterns default to HashLife, like in the window
+        None if board.is_macrocell() => EngineKind::HashLife,
+        None => EngineKind::Cpu,
+    };
+    // Only the bounded engines allocate the whole board
+    let mut simulation = if engine == EngineKind::HashLife {
+        Simulation::HashLife(Box::new(board.hashlife()?))
+    } else if engine == EngineKind::Packed {
+        PackedUniverse::check_rule(&board.rule)?;
+        Simulation::Packed(Box::new(PackedUniverse::from_universe(&board.universe()?)))
+    } else if engine == EngineKind::Sparse {
+        Simulation::Sparse(Box::new(board.sparse_universe()?))
+    } else {
+        Simulation::Cpu(board.universe()?)
+    };
+
+    let mut min_population = simulation.population();
+    let mut max_population = simulation.population();
+    // State hash -> first generation it was seen at
+    let mut seen_states = HashMap::new();
+    let max_generation = cli
+        .until_stable
+        .then(|| simulation.generation().saturating_add(cli.max_generations));
+    let outcome = loop {
+        let generation = simulation.generation();
+        if cli.until_stable {
+            match seen_states.entry(simulation.state_hash()) {
+                Entry::Occupied(entry) => {
+                    break Outcome::Repeats {
+                        generation: *entry.get(),
+                        period: generation - entry.get(),
+                    };
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(generation);
+                }
+            }
+        }
+
+        if cli
+            .generations
+            .is_some_and(|generations| generation >= generations)
+        {
+            break Outcome::Finished;
+        }
+        if max_generation.is_some_and(|max_generation| generation >= max_generation) {
+            break Outcome::Unsettled;
+        }
+        let remaining = cli
+            .generations
+            .into_iter()
+            .chain(max_generation)
+            .map(|stop_generation| stop_generation - generation)
+            .min();
+        simulation.step(remaining, cli.hashlife_step);
+
+        min_population = min_population.min(simulation.population());
+        max_population = max_population.max(simulation.population());
+    };
+
+    let mut summary = vec![
+        format!(
+            "Generation {}, population {}",
+            simulation.generation(),
+            simulation.population()
+        ),
+        format!("Population min {min_population}, max {max_population}"),
+    ];
+    match outcome {
+        Outcome::Finished => (),
+        Outcome::Unsettled => summary.push(format!(
+            "No repeated state within {} generations",
+            cli.max_generations
+        )),
+        Outcome::Repeats { generation, period } => summary.push(if simulation.population() == 0 {
+            format!("Dies out at generation {generation}")
+        } else if period == 1 {
+            format!("Stable from generation {generation}")
+        } else {
+            format!("Repeats with period {period} from generation {generation}")
+        }),
+    }
+
+    let format = cli
+        .output
+        .as_deref()
+        .map(Format::from_extension)
+        .unwrap_or(Format::Rle);
+    let text = match (&simulation, format) {
+        // Written from the quadtree, the cell list can be enormous
+        (Simulation::HashLife(hashlife), Format::Macrocell) => {
+            macrocell::write(hashlife, Some(&hashlife.rule().to_string()), &summary)
+        }
+        (Simulation::HashLife(hashlife), _) => {
+            let pattern = Pattern {
+                rule: Some(hashlife.rule().to_string()),
+                comments: summary.clone(),
+                ..Pattern::from_cells(hashlife.live_cells())
+            };
+            pattern::write(&pattern, format)
+        }
+        (Simulation::Sparse(sparse_universe), _) => {
+            let pattern = Pattern {
+                rule: Some(sparse_universe.rule().to_string()),
+                comments: summary.clone(),
+                ..Pattern::from_cells(sparse_universe.live_cells())
+            };
+            pattern::write(&pattern, format)
+        }
+        (Simulation::Packed(packed_universe), _) => {
+            let mut universe = Universe::new(packed_universe.width(), packed_universe.height());
+            packed_universe.write_to(&mut universe);
+            let pattern = Pattern {
+                comments: summary.clone(),
+                ..Pattern::from_universe(&universe)
+            };
+            pattern::write(&pattern, format)
+        }
+        (Simulation::Cpu(universe), _) => {
+            let pattern = Pattern {
+                comments: summary.clone(),
+                ..Pattern::from_universe(universe)
+            };
+            pattern::write(&pattern, format)
+        }
+    };
+
+    match &cli.output {
+        Some(path) => {
+            std::fs::write(path, text)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            for line in summary {
+                println!("{line}");
+            }
+        }
+        // The summary is already in the pattern comments
+        None => print!("{text}"),
+    }
+    Ok(())
+}
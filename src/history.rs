@@ -0,0 +1,266 @@
+// Undo and redo of edits and runs
+// Changes are stored as the cells that differ before and after them, not as copies of the universe
+// There is no rule to record, it is set once at startup and pastes keep the rule of the universe
+
+use crate::app_context::AppContext;
+use crate::graphics_context::GraphicsContext;
+use crate::simulation::DEAD;
+use std::collections::{HashMap, VecDeque};
+
+// Bytes of a changed cell
+const CELL_MEMORY: usize = size_of::<(i64, i64, u8, u8)>();
+
+/// Undoable change of the universe
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Change {
+    // (x, y, state before, state after)
+    cells: Vec<(i64, i64, u8, u8)>,
+    // Generation before and after a run
+    generations: Option<(u64, u64)>,
+}
+
+impl Change {
+    /// Records a changed cell, unchanged ones are skipped
+    pub fn push_cell(&mut self, x: i64, y: i64, before: u8, after: u8) {
+        if before != after {
+            self.cells.push((x, y, before, after));
+        }
+    }
+
+    /// Change of a run from the live cells before it to the live cells after it
+    pub fn run(
+        before: Vec<(i64, i64, u8)>,
+        after: Vec<(i64, i64, u8)>,
+        generations: (u64, u64),
+    ) -> Self {
+        let mut before: HashMap<_, _> = before
+            .into_iter()
+            .map(|(x, y, state)| ((x, y), state))
+            .collect();
+        let mut change = Self {
+            cells: Vec::new(),
+            generations: Some(generations),
+        };
+        for (x, y, state) in after {
+            let state_before = before.remove(&(x, y)).unwrap_or(DEAD);
+            change.push_cell(x, y, state_before, state);
+        }
+        for ((x, y), state) in before {
+            change.push_cell(x, y, state, DEAD);
+        }
+        change.cells.sort_unstable();
+        change
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty() && self.generations.is_none()
+    }
+
+    // Bytes kept by the history
+    fn memory(&self) -> usize {
+        size_of::<Self>() + self.cells.len() * CELL_MEMORY
+    }
+
+    /// Puts the cells back into the state before the change
+    pub fn revert(&self, app_context: &mut AppContext, graphics_context: &GraphicsContext) {
+        let cells: Vec<_> = self
+            .cells
+            .iter()
+            .map(|&(x, y, before, _)| (x, y, before))
+            .collect();
+        app_context.set_cells(graphics_context, &cells);
+        if let Some((before, _)) = self.generations {
+            app_context.set_generation(graphics_context, before);
+        }
+    }
+
+    /// Makes the change again after it was reverted
+    pub fn apply(&self, app_context: &mut AppContext, graphics_context: &GraphicsContext) {
+        let cells: Vec<_> = self
+            .cells
+            .iter()
+            .map(|&(x, y, _, after)| (x, y, after))
+            .collect();
+        app_context.set_cells(graphics_context, &cells);
+        if let Some((_, after)) = self.generations {
+            app_context.set_generation(graphics_context, after);
+        }
+    }
+}
+
+// Generations that are running, they become one change when they stop
+#[derive(Debug)]
+enum Run {
+    Stopped,
+    // Live cells and generation before the run
+    Recorded {
+        cells: Vec<(i64, i64, u8)>,
+        generation: u64,
+    },
+    // Too many live cells to keep, the run can't be undone
+    Unrecorded,
+}
+
+/// Undo and redo stacks, the oldest changes are forgotten past the memory limit
+#[derive(Debug)]
+pub struct History {
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+    // Bytes of all changes in both stacks
+    memory: usize,
+    max_memory: usize,
+    run: Run,
+}
+
+impl History {
+    pub fn new(max_memory: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            memory: 0,
+            max_memory,
+            run: Run::Stopped,
+        }
+    }
+
+    /// Keeps the live cells before generations are run, see `end_run`
+    pub fn start_run(&mut self, app_context: &mut AppContext, graphics_context: &GraphicsContext) {
+        let population = app_context.population(graphics_context);
+        self.run = if population.saturating_mul(CELL_MEMORY as u64) > self.max_memory as u64 {
+            Run::Unrecorded
+        } else {
+            Run::Recorded {
+                cells: app_context.live_cells(graphics_context),
+                generation: app_context.generation(),
+            }
+        };
+    }
+
+    /// Records the generations run since `start_run` as one change
+    pub fn end_run(&mut self, app_context: &mut AppContext, graphics_context: &GraphicsContext) {
+        match std::mem::replace(&mut self.run, Run::Stopped) {
+            Run::Stopped => (),
+            Run::Recorded { generation, .. } if generation == app_context.generation() => (),
+            Run::Recorded { cells, generation } => self.push(Change::run(
+                cells,
+                app_context.live_cells(graphics_context),
+                (generation, app_context.generation()),
+            )),
+            Run::Unrecorded => {
+                log::warn!("The run is too large to undo, the history is cleared");
+                self.clear();
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.memory = 0;
+    }
+
+    /// Records a change that was just made, the redo stack is dropped
+    pub fn push(&mut self, change: Change) {
+        if change.is_empty() {
+            return;
+        }
+        self.memory -= self
+            .redo
+            .drain(..)
+            .map(|change| change.memory())
+            .sum::<usize>();
+        if change.memory() > self.max_memory {
+            // Older changes can't be undone past this one either
+            log::warn!("Change is too large to undo, the history is cleared");
+            self.clear();
+            return;
+        }
+        self.memory += change.memory();
+        self.undo.push_back(change);
+        while self.memory > self.max_memory {
+            let oldest = self.undo.pop_front().expect("memory without changes");
+            self.memory -= oldest.memory();
+        }
+    }
+
+    /// Last change to revert, it moves to the redo stack
+    pub fn undo(&mut self) -> Option<&Change> {
+        let change = self.undo.pop_back()?;
+        self.redo.push(change);
+        self.redo.last()
+    }
+
+    /// Last undone change to apply again, it moves back to the undo stack
+    pub fn redo(&mut self) -> Option<&Change> {
+        let change = self.redo.pop()?;
+        self.undo.push_back(change);
+        self.undo.back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::ALIVE;
+
+    fn cell_change(x: i64) -> Change {
+        let mut change = Change::default();
+        change.push_cell(x, 0, DEAD, ALIVE);
+        change
+    }
+
+    #[test]
+    fn undoes_and_redoes_in_order() {
+        let mut history = History::new(usize::MAX);
+        assert!(history.undo().is_none());
+        history.push(cell_change(1));
+        history.push(cell_change(2));
+        history.push(Change::default());
+
+        assert_eq!(history.undo(), Some(&cell_change(2)));
+        assert_eq!(history.undo(), Some(&cell_change(1)));
+        assert!(history.undo().is_none());
+        assert_eq!(history.redo(), Some(&cell_change(1)));
+
+        // A new change drops what could be redone
+        history.push(cell_change(3));
+        assert!(history.redo().is_none());
+        assert_eq!(history.undo(), Some(&cell_change(3)));
+        assert_eq!(history.undo(), Some(&cell_change(1)));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn forgets_old_changes_past_the_memory_limit() {
+        let change_memory = cell_change(0).memory();
+        let mut history = History::new(change_memory * 2);
+        for x in 0..5 {
+            history.push(cell_change(x));
+        }
+        assert_eq!(history.memory, change_memory * 2);
+        assert_eq!(history.undo(), Some(&cell_change(4)));
+        assert_eq!(history.undo(), Some(&cell_change(3)));
+        assert!(history.undo().is_none());
+
+        let mut too_large = Change::default();
+        for x in 0..100 {
+            too_large.push_cell(x, 0, DEAD, ALIVE);
+        }
+        history.push(too_large);
+        assert_eq!(history.memory, 0);
+        assert!(history.undo().is_none());
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn runs_store_changed_cells_only() {
+        let before = vec![(0, 0, ALIVE), (1, 0, ALIVE), (5, 5, 2)];
+        let after = vec![(1, 0, ALIVE), (2, 0, ALIVE), (5, 5, 3)];
+        let change = Change::run(before, after, (10, 11));
+        assert_eq!(
+            change.cells,
+            [(0, 0, ALIVE, DEAD), (2, 0, DEAD, ALIVE), (5, 5, 2, 3)]
+        );
+        assert_eq!(change.generations, Some((10, 11)));
+    }
+}
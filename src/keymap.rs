@@ -0,0 +1,425 @@
+// Keyboard actions and the keys bound to them
+// Bindings can be changed in a TOML file, every action listed there replaces its default keys:
+//
+// [keys]
+// pan-up = ["ArrowUp", "W", "K"]
+// undo = ["Ctrl+Z", "Ctrl+U"]
+
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use winit::keyboard::{KeyCode, ModifiersState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    ZoomIn,
+    ZoomOut,
+    // Centers and zooms the camera on the live cells
+    FitPattern,
+    ToggleGrid,
+    PlayPause,
+    Step,
+    // Steps `--step-size` generations
+    StepN,
+    SpeedUp,
+    SlowDown,
+    MaxSpeed,
+    Undo,
+    Redo,
+    Copy,
+    Cut,
+    Paste,
+    // Kills the selected cells
+    Clear,
+    // Stops pasting, or drops the selection
+    Cancel,
+    // Pasting only
+    Rotate,
+    FlipHorizontally,
+    FlipVertically,
+    NextPasteMode,
+}
+
+// Name in the keymap file and default keys of every action
+const ACTIONS: [(Action, &str, &[&str]); 25] = [
+    (Action::PanUp, "pan-up", &["ArrowUp", "W"]),
+    (Action::PanDown, "pan-down", &["ArrowDown", "S"]),
+    (Action::PanLeft, "pan-left", &["ArrowLeft", "A"]),
+    (Action::PanRight, "pan-right", &["ArrowRight", "D"]),
+    // + is Shift and = on most layouts
+    (
+        Action::ZoomIn,
+        "zoom-in",
+        &["Equal", "Shift+Equal", "NumpadAdd"],
+    ),
+    (Action::ZoomOut, "zoom-out", &["Minus", "NumpadSubtract"]),
+    (Action::FitPattern, "fit-pattern", &["F"]),
+    (Action::ToggleGrid, "toggle-grid", &["G"]),
+    (Action::PlayPause, "play-pause", &["Enter"]),
+    (Action::Step, "step", &["Tab"]),
+    (Action::StepN, "step-n", &["Shift+Tab"]),
+    (Action::SpeedUp, "speed-up", &["BracketRight"]),
+    (Action::SlowDown, "slow-down", &["BracketLeft"]),
+    (Action::MaxSpeed, "max-speed", &["Backslash"]),
+    (Action::Undo, "undo", &["Ctrl+Z"]),
+    (Action::Redo, "redo", &["Ctrl+Shift+Z", "Ctrl+Y"]),
+    (Action::Copy, "copy", &["Ctrl+C"]),
+    (Action::Cut, "cut", &["Ctrl+X"]),
+    (Action::Paste, "paste", &["Ctrl+V"]),
+    (Action::Clear, "clear", &["Delete", "Backspace"]),
+    (Action::Cancel, "cancel", &["Escape"]),
+    (Action::Rotate, "rotate", &["R"]),
+    (Action::FlipHorizontally, "flip-horizontally", &["H"]),
+    (Action::FlipVertically, "flip-vertically", &["V"]),
+    (Action::NextPasteMode, "next-paste-mode", &["M"]),
+];
+
+// Names of the keys that aren't letters or digits
+const KEY_NAMES: [(&str, KeyCode); 31] = [
+    ("ArrowUp", KeyCode::ArrowUp),
+    ("ArrowDown", KeyCode::ArrowDown),
+    ("ArrowLeft", KeyCode::ArrowLeft),
+    ("ArrowRight", KeyCode::ArrowRight),
+    ("Space", KeyCode::Space),
+    ("Enter", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("Escape", KeyCode::Escape),
+    ("Delete", KeyCode::Delete),
+    ("Backspace", KeyCode::Backspace),
+    ("Insert", KeyCode::Insert),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Minus", KeyCode::Minus),
+    ("Equal", KeyCode::Equal),
+    ("BracketLeft", KeyCode::BracketLeft),
+    ("BracketRight", KeyCode::BracketRight),
+    ("Backslash", KeyCode::Backslash),
+    ("Semicolon", KeyCode::Semicolon),
+    ("Quote", KeyCode::Quote),
+    ("Backquote", KeyCode::Backquote),
+    ("Comma", KeyCode::Comma),
+    ("Period", KeyCode::Period),
+    ("Slash", KeyCode::Slash),
+    ("NumpadAdd", KeyCode::NumpadAdd),
+    ("NumpadSubtract", KeyCode::NumpadSubtract),
+    ("NumpadMultiply", KeyCode::NumpadMultiply),
+    ("NumpadDivide", KeyCode::NumpadDivide),
+    ("NumpadEnter", KeyCode::NumpadEnter),
+];
+
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+];
+
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+impl Action {
+    /// Held keys keep repeating these
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::PanUp
+                | Action::PanDown
+                | Action::PanLeft
+                | Action::PanRight
+                | Action::ZoomIn
+                | Action::ZoomOut
+                | Action::Step
+                | Action::Undo
+                | Action::Redo
+        )
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        ACTIONS
+            .iter()
+            .find(|(_, action_name, _)| *action_name == name)
+            .map(|&(action, _, _)| action)
+    }
+}
+
+/// Key with the modifiers that must be held, Ctrl also matches Cmd on macOS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl KeyBinding {
+    pub fn new(key: KeyCode, modifiers: ModifiersState) -> Self {
+        Self {
+            key,
+            ctrl: modifiers.control_key() || modifiers.super_key(),
+            shift: modifiers.shift_key(),
+            alt: modifiers.alt_key(),
+        }
+    }
+}
+
+/// Parses bindings like "W", "Ctrl+Shift+Z" or "ArrowUp"
+impl std::str::FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key_name = parts.pop().unwrap_or_default();
+        let mut binding = Self {
+            key: parse_key(key_name).ok_or_else(|| format!("unknown key \"{key_name}\""))?,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        };
+        for modifier in parts {
+            let held = match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "cmd" => &mut binding.ctrl,
+                "shift" => &mut binding.shift,
+                "alt" => &mut binding.alt,
+                _ => return Err(format!("unknown modifier \"{modifier}\"")),
+            };
+            *held = true;
+        }
+        Ok(binding)
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, modifier) in [
+            (self.ctrl, "Ctrl+"),
+            (self.shift, "Shift+"),
+            (self.alt, "Alt+"),
+        ] {
+            if held {
+                write!(f, "{modifier}")?;
+            }
+        }
+        match key_name(self.key) {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "{:?}", self.key),
+        }
+    }
+}
+
+// Name that `parse_key` reads back
+fn key_name(key: KeyCode) -> Option<String> {
+    if let Some(index) = LETTERS.iter().position(|&letter| letter == key) {
+        return Some(char::from(b'A' + index as u8).to_string());
+    }
+    if let Some(index) = DIGITS.iter().position(|&digit| digit == key) {
+        return Some(index.to_string());
+    }
+    KEY_NAMES
+        .iter()
+        .find(|&&(_, named_key)| named_key == key)
+        .map(|(name, _)| name.to_string())
+}
+
+// Letters and digits are named by themselves, other keys by `KEY_NAMES`
+fn parse_key(name: &str) -> Option<KeyCode> {
+    if let [character] = name.as_bytes() {
+        let character = character.to_ascii_uppercase();
+        if character.is_ascii_uppercase() {
+            return Some(LETTERS[(character - b'A') as usize]);
+        }
+        if character.is_ascii_digit() {
+            return Some(DIGITS[(character - b'0') as usize]);
+        }
+    }
+    KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|&(_, key)| key)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeymapFile {
+    #[serde(default)]
+    keys: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    actions: HashMap<KeyBinding, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::parse("").expect("default keymap")
+    }
+}
+
+impl Keymap {
+    /// Reads a keymap file, actions that are not in it keep their default keys
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Keymap of the contents of a keymap file
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let file: KeymapFile = toml::from_str(text)?;
+        let mut bindings: Vec<(Action, Vec<String>)> = ACTIONS
+            .iter()
+            .map(|&(action, _, keys)| (action, keys.iter().map(|key| key.to_string()).collect()))
+            .collect();
+        for (name, keys) in file.keys {
+            let action =
+                Action::from_name(&name).with_context(|| format!("Unknown action \"{name}\""))?;
+            bindings
+                .iter_mut()
+                .find(|(bound_action, _)| *bound_action == action)
+                .expect("action without defaults")
+                .1 = keys;
+        }
+
+        let mut actions = HashMap::new();
+        for (action, keys) in bindings {
+            for key in keys {
+                let binding: KeyBinding = key
+                    .parse()
+                    .map_err(|err| anyhow::anyhow!("{err} in the keys of {action:?}"))?;
+                if let Some(other_action) = actions.insert(binding, action) {
+                    anyhow::ensure!(
+                        other_action == action,
+                        "{binding} is bound to both {other_action:?} and {action:?}"
+                    );
+                }
+            }
+        }
+        Ok(Self { actions })
+    }
+
+    /// Action bound to the key with exactly these modifiers held
+    pub fn action(&self, key: KeyCode, modifiers: ModifiersState) -> Option<Action> {
+        self.actions.get(&KeyBinding::new(key, modifiers)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_key_bindings() {
+        let binding: KeyBinding = "Ctrl+Shift+z".parse().unwrap();
+        assert_eq!(
+            binding,
+            KeyBinding {
+                key: KeyCode::KeyZ,
+                ctrl: true,
+                shift: true,
+                alt: false,
+            }
+        );
+        assert_eq!(binding.to_string(), "Ctrl+Shift+Z");
+        assert_eq!("7".parse::<KeyBinding>().unwrap().key, KeyCode::Digit7);
+        assert_eq!(
+            "alt + arrowleft".parse::<KeyBinding>().unwrap(),
+            KeyBinding::new(KeyCode::ArrowLeft, ModifiersState::ALT)
+        );
+
+        assert!("Hyper+A".parse::<KeyBinding>().is_err());
+        assert!("Ctrl+".parse::<KeyBinding>().is_err());
+        assert!("AB".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn binds_default_keys() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            keymap.action(KeyCode::KeyW, ModifiersState::empty()),
+            Some(Action::PanUp)
+        );
+        assert_eq!(
+            keymap.action(KeyCode::KeyZ, ModifiersState::CONTROL),
+            Some(Action::Undo)
+        );
+        assert_eq!(
+            keymap.action(KeyCode::KeyZ, ModifiersState::SUPER | ModifiersState::SHIFT),
+            Some(Action::Redo)
+        );
+        assert_eq!(
+            keymap.action(KeyCode::Equal, ModifiersState::SHIFT),
+            Some(Action::ZoomIn)
+        );
+        assert_eq!(keymap.action(KeyCode::KeyZ, ModifiersState::empty()), None);
+    }
+
+    #[test]
+    fn keymap_files_replace_default_keys() {
+        let keymap =
+            Keymap::parse("[keys]\npan-up = [\"K\"]\nundo = [\"Ctrl+U\", \"W\"]\n").unwrap();
+        assert_eq!(
+            keymap.action(KeyCode::KeyK, ModifiersState::empty()),
+            Some(Action::PanUp)
+        );
+        assert_eq!(
+            keymap.action(KeyCode::KeyW, ModifiersState::empty()),
+            Some(Action::Undo)
+        );
+        assert_eq!(keymap.action(KeyCode::KeyZ, ModifiersState::CONTROL), None);
+        // Untouched actions keep their defaults
+        assert_eq!(
+            keymap.action(KeyCode::ArrowDown, ModifiersState::empty()),
+            Some(Action::PanDown)
+        );
+
+        for text in [
+            "[keys]\njump = [\"J\"]",
+            "[keys]\nundo = [\"Ctrl+Banana\"]",
+            "[keys]\nundo = [\"S\"]",
+            "[keymap]\nundo = [\"U\"]",
+            "keys = 3",
+        ] {
+            assert!(Keymap::parse(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,3 @@
+// Simulation engines without the window and the GPU, shared with the benchmarks
+
+pub mod simulation;
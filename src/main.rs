@@ -1,33 +1,105 @@
 mod app_context;
 mod camera;
+mod cli;
+mod clipboard;
+mod edit;
+mod gpu_simulation;
 mod graphics_context;
+mod headless;
+mod history;
+mod keymap;
+mod pattern;
+mod scheduler;
 
-use crate::app_context::AppContext;
+use crate::app_context::{AppContext, CELL_SIZE, CellRectangle, Engine, PushConstants};
+use crate::cli::Cli;
+use crate::clipboard::SystemClipboard;
+use crate::edit::{PasteMode, Selection, Stroke};
+use crate::history::{Change, History};
+use crate::keymap::{Action, Keymap};
+use crate::pattern::{Pattern, PatternFile};
+use anyhow::Context;
 use bytemuck::bytes_of;
+use game_of_life_wgpu::simulation;
 use graphics_context::GraphicsContext;
-use nalgebra::{Matrix4, Vector3};
+use nalgebra::{Matrix4, Vector2, Vector3};
+use std::time::Instant;
 use wgpu::{
     Color, CommandEncoderDescriptor, LoadOp, Operations, RenderPassColorAttachment,
     RenderPassDescriptor, ShaderStages, StoreOp,
 };
 use winit::application::ApplicationHandler;
-use winit::dpi::PhysicalPosition;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
 use winit::event::{MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
 use winit::window::WindowId;
 
-#[derive(Default)]
+// Logical pixels a pan key moves the view
+const PAN_STEP: f32 = 64.;
+
 struct App {
+    cli: Cli,
+    // Taken when the app context is created
+    pattern_file: Option<PatternFile>,
+    keymap: Keymap,
     graphics_context: Option<GraphicsContext>,
     app_context: Option<AppContext>,
     input_state: Option<InputState>,
+    system_clipboard: Option<SystemClipboard>,
+    history: Option<History>,
 }
 
 #[derive(Default, Debug)]
 struct InputState {
     cursor_in_window: bool,
-    lmb_is_pressed: bool,
     cursor_position: PhysicalPosition<f64>,
+    space_is_pressed: bool,
+    // Left mouse pans if space was held when it was pressed, otherwise it draws
+    lmb_pans: bool,
+    mmb_is_pressed: bool,
+    rmb_is_pressed: bool,
+    stroke: Option<Stroke>,
+    // The simulation was running when the stroke started, it resumes once the stroke is recorded
+    stroke_paused_run: bool,
+    modifiers: ModifiersState,
+    // Shift and left mouse drag a selection
+    selection: Option<Selection>,
+    is_selecting: bool,
+    // Mirrored to the system clipboard if there is one
+    clipboard: Option<Pattern>,
+    // The clipboard follows the cursor until it is placed with left mouse
+    is_pasting: bool,
+    paste_mode: PasteMode,
+}
+
+impl InputState {
+    fn is_panning(&self) -> bool {
+        self.lmb_pans || self.mmb_is_pressed || self.rmb_is_pressed
+    }
+
+    fn cursor_cell(&self, app_context: &AppContext, scale_factor: f64) -> (i64, i64) {
+        app_context.world_to_cell(
+            app_context
+                .camera
+                .screen_to_world_position(self.cursor_position.to_logical(scale_factor)),
+        )
+    }
+
+    // Selection outline and paste preview
+    fn overlay(&self, cursor_cell: (i64, i64)) -> Vec<CellRectangle> {
+        let mut rectangles = Vec::new();
+        if let Some(selection) = &self.selection {
+            rectangles.push(selection.outline());
+        }
+        if let Some(clipboard) = self.clipboard.as_ref().filter(|_| self.is_pasting) {
+            rectangles.extend(edit::paste_preview(
+                clipboard,
+                edit::paste_position(clipboard, cursor_cell),
+            ));
+        }
+        rectangles
+    }
 }
 
 impl ApplicationHandler for App {
@@ -36,7 +108,11 @@ impl ApplicationHandler for App {
             return;
         }
 
-        let graphics_context = match GraphicsContext::new(event_loop) {
+        let window_size = self
+            .cli
+            .window_size
+            .map(|(width, height)| PhysicalSize::new(width, height));
+        let graphics_context = match GraphicsContext::new(event_loop, window_size) {
             Ok(graphics_context) => graphics_context,
             Err(err) => {
                 log::error!("Failed to create graphics context: {err:#}");
@@ -45,20 +121,40 @@ impl ApplicationHandler for App {
             }
         };
 
-        let app_context = match AppContext::new(&graphics_context) {
-            Ok(app_context) => app_context,
-            Err(err) => {
-                log::error!("Failed to create app context: {err:#}");
-                event_loop.exit();
-                return;
-            }
-        };
+        let mut app_context =
+            match AppContext::new(&graphics_context, &self.cli, self.pattern_file.take()) {
+                Ok(app_context) => app_context,
+                Err(err) => {
+                    log::error!("Failed to create app context: {err:#}");
+                    event_loop.exit();
+                    return;
+                }
+            };
 
         graphics_context.window.set_visible(true);
 
+        let mut history = History::new(self.cli.undo_memory << 20);
+        if !app_context.scheduler.is_paused() {
+            history.start_run(&mut app_context, &graphics_context);
+        }
+
         self.graphics_context = Some(graphics_context);
         self.app_context = Some(app_context);
         self.input_state = Some(InputState::default());
+        self.system_clipboard = Some(SystemClipboard::new());
+        self.history = Some(history);
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(app_context) = &self.app_context else {
+            return;
+        };
+        // Sleep until the next event while paused
+        event_loop.set_control_flow(if app_context.scheduler.is_paused() {
+            ControlFlow::Wait
+        } else {
+            ControlFlow::Poll
+        });
     }
 
     fn window_event(
@@ -73,8 +169,21 @@ impl ApplicationHandler for App {
         let graphics_context = self.graphics_context.as_mut().unwrap();
         let app_context = self.app_context.as_mut().unwrap();
         let input_state = self.input_state.as_mut().unwrap();
+        let system_clipboard = self.system_clipboard.as_mut().unwrap();
+        let history = self.history.as_mut().unwrap();
+        // Nothing is drawn while paused unless something happens
+        if !matches!(event, WindowEvent::RedrawRequested) {
+            graphics_context.window.request_redraw();
+        }
         match event {
             WindowEvent::RedrawRequested => {
+                let was_paused = app_context.scheduler.is_paused();
+                app_context.update(graphics_context);
+                // Queued generations pause once they have all run
+                if !was_paused && app_context.scheduler.is_paused() {
+                    history.end_run(app_context, graphics_context);
+                }
+                graphics_context.window.set_title(&app_context.title());
                 self.render();
             }
             WindowEvent::Resized(new_size) => {
@@ -94,21 +203,182 @@ impl ApplicationHandler for App {
             WindowEvent::CursorLeft { .. } => {
                 input_state.cursor_in_window = false;
             }
-            WindowEvent::MouseInput { button, state, .. } => {
-                if button == MouseButton::Left && state.is_pressed() {
-                    input_state.lmb_is_pressed = true;
-                    app_context.camera.update_lmb_state(true);
+            WindowEvent::ModifiersChanged(modifiers) => {
+                input_state.modifiers = modifiers.state();
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                let PhysicalKey::Code(key_code) = event.physical_key else {
+                    return;
+                };
+                if key_code == KeyCode::Space {
+                    input_state.space_is_pressed = event.state.is_pressed();
                 }
-                if button == MouseButton::Left && !state.is_pressed() {
-                    input_state.lmb_is_pressed = false;
-                    app_context.camera.update_lmb_state(false);
+                let Some(action) = self.keymap.action(key_code, input_state.modifiers) else {
+                    return;
+                };
+                if !event.state.is_pressed() || (event.repeat && !action.repeats()) {
+                    return;
+                }
+                let clipboard = input_state
+                    .clipboard
+                    .as_mut()
+                    .filter(|_| input_state.is_pasting);
+                match (action, clipboard) {
+                    (Action::PanUp, _) => app_context.camera.pan(Vector2::new(0., PAN_STEP)),
+                    (Action::PanDown, _) => app_context.camera.pan(Vector2::new(0., -PAN_STEP)),
+                    (Action::PanLeft, _) => app_context.camera.pan(Vector2::new(-PAN_STEP, 0.)),
+                    (Action::PanRight, _) => app_context.camera.pan(Vector2::new(PAN_STEP, 0.)),
+                    (Action::ZoomIn, _) => app_context.camera.zoom_on_center(1.),
+                    (Action::ZoomOut, _) => app_context.camera.zoom_on_center(-1.),
+                    (Action::FitPattern, _) => app_context.fit_pattern(graphics_context),
+                    (Action::ToggleGrid, _) => app_context.show_grid = !app_context.show_grid,
+                    (Action::PlayPause, _) => {
+                        if app_context.scheduler.is_paused() {
+                            play(app_context, graphics_context, history);
+                        } else {
+                            pause(app_context, graphics_context, history);
+                        }
+                    }
+                    (Action::Step | Action::StepN, _) => {
+                        let generations = if action == Action::StepN {
+                            self.cli.step_size
+                        } else {
+                            1
+                        };
+                        // The generations run over the next frames, the run ends when they pause
+                        pause(app_context, graphics_context, history);
+                        history.start_run(app_context, graphics_context);
+                        app_context
+                            .scheduler
+                            .queue_generations(generations, Instant::now());
+                    }
+                    (Action::SpeedUp, _) => app_context.scheduler.speed_up(),
+                    (Action::SlowDown, _) => app_context.scheduler.slow_down(),
+                    (Action::MaxSpeed, _) => app_context.scheduler.toggle_max_speed(),
+                    (Action::Undo, _) => {
+                        pause(app_context, graphics_context, history);
+                        if let Some(change) = history.undo() {
+                            change.revert(app_context, graphics_context);
+                        }
+                    }
+                    (Action::Redo, _) => {
+                        pause(app_context, graphics_context, history);
+                        if let Some(change) = history.redo() {
+                            change.apply(app_context, graphics_context);
+                        }
+                    }
+                    (Action::Copy, _) => {
+                        if let Some(selection) = input_state.selection {
+                            let pattern = selection.copy(app_context, graphics_context);
+                            system_clipboard.set_pattern(&pattern);
+                            input_state.clipboard = Some(pattern);
+                        }
+                    }
+                    (Action::Cut, _) => {
+                        if let Some(selection) = input_state.selection {
+                            let running = start_edit(app_context, graphics_context, history);
+                            let (pattern, change) = selection.cut(app_context, graphics_context);
+                            finish_edit(app_context, graphics_context, history, change, running);
+                            system_clipboard.set_pattern(&pattern);
+                            input_state.clipboard = Some(pattern);
+                        }
+                    }
+                    (Action::Paste, _) => {
+                        // Patterns copied in other programs win over our own clipboard
+                        if let Some(pattern) = system_clipboard.pattern() {
+                            input_state.clipboard = Some(pattern);
+                        }
+                        input_state.is_pasting = input_state.clipboard.is_some();
+                    }
+                    (Action::Clear, _) => {
+                        if let Some(selection) = input_state.selection {
+                            let running = start_edit(app_context, graphics_context, history);
+                            let change = selection.clear(app_context, graphics_context);
+                            finish_edit(app_context, graphics_context, history, change, running);
+                        }
+                    }
+                    (Action::Cancel, Some(_)) => input_state.is_pasting = false,
+                    (Action::Cancel, None) => input_state.selection = None,
+                    (Action::Rotate, Some(clipboard)) => clipboard.rotate_clockwise(),
+                    (Action::FlipHorizontally, Some(clipboard)) => clipboard.flip_horizontally(),
+                    (Action::FlipVertically, Some(clipboard)) => clipboard.flip_vertically(),
+                    (Action::NextPasteMode, Some(_)) => {
+                        input_state.paste_mode = input_state.paste_mode.next();
+                        log::info!("Paste mode {:?}", input_state.paste_mode);
+                    }
+                    (
+                        Action::Rotate
+                        | Action::FlipHorizontally
+                        | Action::FlipVertically
+                        | Action::NextPasteMode,
+                        None,
+                    ) => (),
                 }
             }
+            WindowEvent::MouseInput { button, state, .. } => {
+                let is_pressed = state.is_pressed();
+                let cursor_cell =
+                    input_state.cursor_cell(app_context, graphics_context.window.scale_factor());
+                match button {
+                    MouseButton::Left if is_pressed && input_state.space_is_pressed => {
+                        input_state.lmb_pans = true;
+                    }
+                    MouseButton::Left if is_pressed && input_state.is_pasting => {
+                        let clipboard = input_state.clipboard.as_ref().unwrap();
+                        let running = start_edit(app_context, graphics_context, history);
+                        let change = edit::paste(
+                            app_context,
+                            graphics_context,
+                            clipboard,
+                            edit::paste_position(clipboard, cursor_cell),
+                            input_state.paste_mode,
+                        );
+                        finish_edit(app_context, graphics_context, history, change, running);
+                        input_state.is_pasting = false;
+                    }
+                    MouseButton::Left if is_pressed && input_state.modifiers.shift_key() => {
+                        input_state.selection = Some(Selection::new(cursor_cell));
+                        input_state.is_selecting = true;
+                    }
+                    MouseButton::Left if is_pressed => {
+                        input_state.stroke_paused_run =
+                            start_edit(app_context, graphics_context, history);
+                        input_state.stroke =
+                            Some(Stroke::start(app_context, graphics_context, cursor_cell));
+                    }
+                    MouseButton::Left => {
+                        input_state.lmb_pans = false;
+                        if let Some(stroke) = input_state.stroke.take() {
+                            finish_edit(
+                                app_context,
+                                graphics_context,
+                                history,
+                                stroke.finish(),
+                                input_state.stroke_paused_run,
+                            );
+                        }
+                        input_state.is_selecting = false;
+                    }
+                    MouseButton::Middle => input_state.mmb_is_pressed = is_pressed,
+                    MouseButton::Right => input_state.rmb_is_pressed = is_pressed,
+                    _ => (),
+                }
+                app_context.camera.set_panning(input_state.is_panning());
+            }
             WindowEvent::CursorMoved { position, .. } => {
                 input_state.cursor_position = position;
-                app_context.camera.update_cursor_position(
-                    position.to_logical(graphics_context.window.scale_factor()),
-                );
+                let logical_position = position.to_logical(graphics_context.window.scale_factor());
+                app_context.camera.update_cursor_position(logical_position);
+                let cursor_cell =
+                    input_state.cursor_cell(app_context, graphics_context.window.scale_factor());
+                if let Some(stroke) = &mut input_state.stroke {
+                    stroke.extend(app_context, graphics_context, cursor_cell);
+                }
+                if let Some(selection) = &mut input_state.selection
+                    && input_state.is_selecting
+                {
+                    selection.corner = cursor_cell;
+                }
             }
             WindowEvent::MouseWheel { delta, .. } => {
                 if let MouseScrollDelta::LineDelta(_, delta_y) = delta {
@@ -122,10 +392,73 @@ impl ApplicationHandler for App {
     }
 }
 
+// Pauses the simulation, the generations run since it was started become one change
+fn pause(app_context: &mut AppContext, graphics_context: &GraphicsContext, history: &mut History) {
+    if !app_context.scheduler.is_paused() {
+        app_context.scheduler.set_paused(true, Instant::now());
+        history.end_run(app_context, graphics_context);
+    }
+}
+
+// Starts the simulation, the generations it runs become one change once it is paused
+fn play(app_context: &mut AppContext, graphics_context: &GraphicsContext, history: &mut History) {
+    history.start_run(app_context, graphics_context);
+    app_context.scheduler.set_paused(false, Instant::now());
+}
+
+// Edits pause a running simulation, so the run is recorded before any cell is edited
+// Returns whether it was playing, see `finish_edit`, queued generations are dropped
+fn start_edit(
+    app_context: &mut AppContext,
+    graphics_context: &GraphicsContext,
+    history: &mut History,
+) -> bool {
+    let running =
+        !app_context.scheduler.is_paused() && app_context.scheduler.queued_generations().is_none();
+    pause(app_context, graphics_context, history);
+    running
+}
+
+// Records the edit on its own and resumes the simulation if the edit paused it
+fn finish_edit(
+    app_context: &mut AppContext,
+    graphics_context: &GraphicsContext,
+    history: &mut History,
+    change: Change,
+    resume: bool,
+) {
+    history.push(change);
+    if resume {
+        play(app_context, graphics_context, history);
+    }
+}
+
 impl App {
+    fn new(cli: Cli, pattern_file: Option<PatternFile>, keymap: Keymap) -> Self {
+        Self {
+            cli,
+            pattern_file,
+            keymap,
+            graphics_context: None,
+            app_context: None,
+            input_state: None,
+            system_clipboard: None,
+            history: None,
+        }
+    }
+
     pub fn render(&mut self) {
         let graphics_context = self.graphics_context.as_mut().unwrap();
         let app_context = self.app_context.as_mut().unwrap();
+        let input_state = self.input_state.as_ref().unwrap();
+        if let Engine::Cpu | Engine::HashLife(_) | Engine::Packed(_) | Engine::Sparse(_) =
+            app_context.engine
+        {
+            app_context.update_instances(graphics_context);
+        }
+        let cursor_cell =
+            input_state.cursor_cell(app_context, graphics_context.window.scale_factor());
+        app_context.update_overlay(graphics_context, &input_state.overlay(cursor_cell));
         let (surface_texture, surface_texture_view) = graphics_context.surface_data.acquire();
 
         let mut command_encoder = graphics_context
@@ -145,44 +478,134 @@ impl App {
                 ..Default::default()
             });
 
-            let view_projection_matrix = app_context.camera.calculate_view_projection_matrix();
+            let view_projection_matrix = app_context
+                .camera
+                .calculate_view_projection_matrix(app_context.render_origin());
+            let mut model_matrix = Matrix4::<f32>::identity();
+            model_matrix[(0, 1)] = app_context.row_shear() as f32;
             let model_matrix =
-                Matrix4::<f32>::identity().append_nonuniform_scaling(&Vector3::new(128., 128., 1.));
+                model_matrix.append_nonuniform_scaling(&Vector3::new(CELL_SIZE, CELL_SIZE, 1.));
             let mvp_matrix = view_projection_matrix * model_matrix;
 
             render_pass.set_vertex_buffer(0, app_context.vertex_buffer.slice(..));
-            render_pass.set_pipeline(&app_context.render_pipeline);
-            render_pass.set_push_constants(ShaderStages::VERTEX, 0, bytes_of(&mvp_matrix));
-            render_pass.draw(0..6, 0..1);
-
-            // Square under cursor
-            /*
-            let cursor_position = input_state.cursor_position.to_logical(graphics_context.window.scale_factor());
-            let cursor_world_position = app_context.camera.screen_to_world_position(cursor_position);
-            let scale = Matrix4::<f32>::identity().append_nonuniform_scaling(&Vector3::new(32., 32., 1.));
-            let translation = Matrix4::<f32>::identity().append_translation(&cursor_world_position.push(0.));
-            let model_matrix = translation * scale;
-            let mvp_matrix = view_projection_matrix * model_matrix;
-            render_pass.set_push_constants(ShaderStages::VERTEX, 0, bytes_of(&mvp_matrix));
-            render_pass.draw(0..6, 0..1);
-             */
+            match &app_context.engine {
+                Engine::Cpu | Engine::HashLife(_) | Engine::Packed(_) | Engine::Sparse(_) => {
+                    // All live cells in one instanced draw
+                    render_pass.set_vertex_buffer(1, app_context.instance_buffer.slice(..));
+                    render_pass.set_pipeline(&app_context.render_pipeline);
+                    render_pass.set_bind_group(0, &app_context.palette_bind_group, &[]);
+                    let push_constants = PushConstants { mvp_matrix };
+                    render_pass.set_push_constants(
+                        ShaderStages::VERTEX,
+                        0,
+                        bytes_of(&push_constants),
+                    );
+                    render_pass.draw(0..6, 0..app_context.instance_count);
+                }
+                Engine::Gpu {
+                    simulation,
+                    board_bind_groups,
+                } => {
+                    // One quad over the whole board, sampling the current texture
+                    let board_matrix = mvp_matrix.prepend_nonuniform_scaling(&Vector3::new(
+                        app_context.universe.width() as f32,
+                        app_context.universe.height() as f32,
+                        1.,
+                    ));
+                    render_pass.set_pipeline(&app_context.board_render_pipeline);
+                    render_pass.set_bind_group(
+                        0,
+                        &board_bind_groups[simulation.current_index()],
+                        &[],
+                    );
+                    let push_constants = PushConstants {
+                        mvp_matrix: board_matrix,
+                    };
+                    render_pass.set_push_constants(
+                        ShaderStages::VERTEX,
+                        0,
+                        bytes_of(&push_constants),
+                    );
+                    render_pass.draw(0..6, 0..1);
+                }
+                Engine::Continuous {
+                    simulation,
+                    board_bind_groups,
+                } => {
+                    let board_matrix = mvp_matrix.prepend_nonuniform_scaling(&Vector3::new(
+                        simulation.width() as f32,
+                        simulation.height() as f32,
+                        1.,
+                    ));
+                    render_pass.set_pipeline(&app_context.continuous_render_pipeline);
+                    render_pass.set_bind_group(
+                        0,
+                        &board_bind_groups[simulation.current_index()],
+                        &[],
+                    );
+                    let push_constants = PushConstants {
+                        mvp_matrix: board_matrix,
+                    };
+                    render_pass.set_push_constants(
+                        ShaderStages::VERTEX,
+                        0,
+                        bytes_of(&push_constants),
+                    );
+                    render_pass.draw(0..6, 0..1);
+                }
+            }
+
+            // Edges of a bounded grid over the cells
+            if app_context.edge_vertex_count > 0 {
+                render_pass.set_vertex_buffer(0, app_context.edge_vertex_buffer.slice(..));
+                render_pass.set_pipeline(&app_context.edge_render_pipeline);
+                let push_constants = PushConstants { mvp_matrix };
+                render_pass.set_push_constants(ShaderStages::VERTEX, 0, bytes_of(&push_constants));
+                render_pass.draw(0..app_context.edge_vertex_count, 0..1);
+            }
+
+            // Selection outline and paste preview over everything
+            if app_context.overlay_vertex_count > 0 {
+                render_pass.set_vertex_buffer(0, app_context.overlay_vertex_buffer.slice(..));
+                render_pass.set_pipeline(&app_context.edge_render_pipeline);
+                let push_constants = PushConstants { mvp_matrix };
+                render_pass.set_push_constants(ShaderStages::VERTEX, 0, bytes_of(&push_constants));
+                render_pass.draw(0..app_context.overlay_vertex_count, 0..1);
+            }
         }
         let command_buffer = command_encoder.finish();
         graphics_context.queue.submit([command_buffer]);
         graphics_context.window.pre_present_notify();
         surface_texture.present();
-        graphics_context.window.request_redraw();
+        if !app_context.scheduler.is_paused() {
+            graphics_context.window.request_redraw();
+        }
     }
 }
 
-fn main() {
+fn main() -> anyhow::Result<()> {
     env_logger::init();
+    let cli = Cli::parse_checked();
+
+    // Loaded before the window is created, a bad file fails right away
+    let pattern_file = cli.pattern.as_deref().map(pattern::load).transpose()?;
+
+    if cli.headless {
+        // Required by the command line parser
+        let pattern_file = pattern_file.expect("--headless without a pattern");
+        return headless::run(&cli, pattern_file);
+    }
+
+    let keymap = match &cli.keymap {
+        Some(path) => Keymap::load(path)?,
+        None => Keymap::default(),
+    };
 
-    let event_loop = EventLoop::new().expect("Failed to create EventLoop");
+    let event_loop = EventLoop::new().context("Failed to create EventLoop")?;
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let mut app = App::default();
+    let mut app = App::new(cli, pattern_file, keymap);
     event_loop
         .run_app(&mut app)
-        .expect("Failed to run app in EventLoop");
+        .context("Failed to run app in EventLoop")
 }
@@ -0,0 +1,600 @@
+// Patterns and the file formats they are exchanged in
+
+pub mod life;
+pub mod macrocell;
+pub mod plaintext;
+pub mod rle;
+
+use crate::cli::Cli;
+use crate::pattern::macrocell::Macrocell;
+use crate::simulation::hashlife::HashLife;
+use crate::simulation::rule::{Rule, table, topology};
+use crate::simulation::sparse::SparseUniverse;
+use crate::simulation::{DEAD, Universe};
+use anyhow::Context;
+use std::fmt;
+use std::path::Path;
+
+// Smallest universe chosen for a pattern, patterns get twice their size
+const UNIVERSE_SIZE_MIN: usize = 256;
+// Largest universe chosen for a macrocell pattern or an unbounded engine, the engines keep the
+// cells outside of it
+const WINDOW_UNIVERSE_SIZE_MAX: usize = 4096;
+// Largest universe the bounded engines allocate, 256 MiB of cells
+const DENSE_UNIVERSE_CELLS_MAX: usize = 1 << 28;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Rle,
+    // .cells
+    Plaintext,
+    Life105,
+    Life106,
+    // .mc, only practical to load straight into HashLife
+    Macrocell,
+}
+
+impl Format {
+    /// Guesses the format from the file contents, RLE if nothing else matches
+    pub fn detect(text: &str) -> Self {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        let Some(first_line) = lines.clone().next() else {
+            return Format::Rle;
+        };
+        if first_line.starts_with("#Life 1.06") {
+            return Format::Life106;
+        }
+        if first_line.starts_with("#Life 1.05") {
+            return Format::Life105;
+        }
+        if first_line.starts_with("[M2]") {
+            return Format::Macrocell;
+        }
+        if first_line.starts_with('!') {
+            return Format::Plaintext;
+        }
+
+        // RLE header after optional comments, otherwise plaintext rows
+        let Some(first_content_line) = lines.find(|line| !line.starts_with('#')) else {
+            return Format::Rle;
+        };
+        let is_plaintext_row = first_content_line
+            .chars()
+            .all(|character| matches!(character, '.' | 'O' | '*'));
+        if is_plaintext_row && !first_line.starts_with('#') {
+            Format::Plaintext
+        } else {
+            Format::Rle
+        }
+    }
+
+    /// Format to save into, RLE for unknown extensions
+    pub fn from_extension(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("cells") => Format::Plaintext,
+            Some("lif" | "life") => Format::Life106,
+            Some("mc") => Format::Macrocell,
+            _ => Format::Rle,
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Pattern, ParseError> {
+    match Format::detect(text) {
+        Format::Rle => rle::parse(text),
+        Format::Plaintext => plaintext::parse(text),
+        Format::Life105 => life::parse_105(text),
+        Format::Life106 => life::parse_106(text),
+        Format::Macrocell => {
+            let macrocell = macrocell::parse(text)?;
+            Ok(Pattern {
+                rule: macrocell.rule,
+                comments: macrocell.comments,
+                ..Pattern::from_cells(macrocell.hashlife.live_cells())
+            })
+        }
+    }
+}
+
+pub fn write(pattern: &Pattern, format: Format) -> String {
+    match format {
+        Format::Rle => rle::write(pattern),
+        Format::Plaintext => plaintext::write(pattern),
+        Format::Life105 => life::write_105(pattern),
+        Format::Life106 => life::write_106(pattern),
+        Format::Macrocell => {
+            let mut hashlife = HashLife::new();
+            for &(x, y, state) in &pattern.cells {
+                hashlife.set_cell(x, y, state);
+            }
+            macrocell::write(&hashlife, pattern.rule.as_deref(), &pattern.comments)
+        }
+    }
+}
+
+/// Contents of a pattern file
+#[derive(Debug)]
+pub enum PatternFile {
+    Cells(Pattern),
+    // Macrocell files are kept as a quadtree, they can be far too large for a cell list
+    Macrocell(Macrocell),
+}
+
+/// Reads a pattern file of any supported format
+pub fn load(path: &Path) -> anyhow::Result<PatternFile> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let pattern_file = if Format::detect(&text) == Format::Macrocell {
+        macrocell::parse(&text).map(PatternFile::Macrocell)
+    } else {
+        parse(&text).map(PatternFile::Cells)
+    };
+    pattern_file.with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pattern {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub comments: Vec<String>,
+    pub rule: Option<String>,
+    pub width: u64,
+    pub height: u64,
+    // (x, y, state) of non-dead cells, relative to the pattern top-left
+    pub cells: Vec<(i64, i64, u8)>,
+}
+
+impl Pattern {
+    /// Bounding box of the live cells of the universe
+    pub fn from_universe(universe: &Universe) -> Self {
+        Self {
+            rule: Some(universe.rule().to_string()),
+            ..Self::from_cells(universe.live_cells().collect())
+        }
+    }
+
+    /// Pattern of the bounding box of cells with arbitrary coordinates
+    pub fn from_cells(cells: Vec<(i64, i64, u8)>) -> Self {
+        let min_x = cells.iter().map(|&(x, _, _)| x).min().unwrap_or(0);
+        let min_y = cells.iter().map(|&(_, y, _)| y).min().unwrap_or(0);
+        let max_x = cells.iter().map(|&(x, _, _)| x + 1).max().unwrap_or(0);
+        let max_y = cells.iter().map(|&(_, y, _)| y + 1).max().unwrap_or(0);
+
+        Self {
+            width: (max_x - min_x) as u64,
+            height: (max_y - min_y) as u64,
+            cells: cells
+                .into_iter()
+                .map(|(x, y, state)| (x - min_x, y - min_y, state))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Rows of the pattern as text, one ASCII character per cell up to the last live cell of the
+    /// row
+    ///
+    /// Empty rows are a single dead character. Only the live cells are visited, not the whole
+    /// bounding box.
+    pub fn text_rows(&self, dead: char, alive: char) -> Vec<String> {
+        let mut cells: Vec<_> = self
+            .cells
+            .iter()
+            .filter(|&&(_, _, state)| state != DEAD)
+            .map(|&(x, y, _)| (y, x))
+            .collect();
+        cells.sort_unstable();
+        cells.dedup();
+
+        debug_assert!(dead.is_ascii() && alive.is_ascii());
+        let mut rows = vec![String::new(); self.height as usize];
+        for (y, x) in cells {
+            let row = &mut rows[y as usize];
+            row.extend(std::iter::repeat_n(dead, x as usize - row.len()));
+            row.push(alive);
+        }
+        for row in &mut rows {
+            if row.is_empty() {
+                row.push(dead);
+            }
+        }
+        rows
+    }
+
+    /// Cells of the pattern as rows of states, `width` x `height`
+    #[cfg(test)]
+    pub fn rows(&self) -> Vec<Vec<u8>> {
+        let mut rows = vec![vec![DEAD; self.width as usize]; self.height as usize];
+        for &(x, y, state) in &self.cells {
+            rows[y as usize][x as usize] = state;
+        }
+        rows
+    }
+
+    /// Turns the pattern a quarter turn clockwise, as seen on the screen
+    pub fn rotate_clockwise(&mut self) {
+        let height = self.height as i64;
+        for (x, y, _) in &mut self.cells {
+            (*x, *y) = (height - 1 - *y, *x);
+        }
+        (self.width, self.height) = (self.height, self.width);
+    }
+
+    /// Mirrors the pattern left to right
+    pub fn flip_horizontally(&mut self) {
+        let width = self.width as i64;
+        for (x, _, _) in &mut self.cells {
+            *x = width - 1 - *x;
+        }
+    }
+
+    /// Mirrors the pattern top to bottom
+    pub fn flip_vertically(&mut self) {
+        let height = self.height as i64;
+        for (_, y, _) in &mut self.cells {
+            *y = height - 1 - *y;
+        }
+    }
+
+    /// Places the pattern top-left at (x, y), cells outside of the universe are dropped
+    pub fn place_into(&self, universe: &mut Universe, x: i64, y: i64) {
+        for &(cell_x, cell_y, state) in &self.cells {
+            universe.set_cell(x + cell_x, y + cell_y, state);
+        }
+    }
+
+    /// Places the pattern in the middle of the universe
+    pub fn place_centered(&self, universe: &mut Universe) {
+        let x = (universe.width() as i64 - self.width as i64) / 2;
+        let y = (universe.height() as i64 - self.height as i64) / 2;
+        self.place_into(universe, x, y);
+    }
+}
+
+/// Malformed pattern file, line and column are 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Rule, size and cells of a loaded pattern, before they are put into an engine
+pub struct Board {
+    pub rule: Rule,
+    pub width: usize,
+    pub height: usize,
+    // Macrocell roots are centered at the origin until the board is built
+    pattern_file: PatternFile,
+}
+
+/// Board of the pattern, sized by the command line or the pattern
+pub fn create_board(cli: &Cli, pattern_file: PatternFile) -> anyhow::Result<Board> {
+    let pattern_rule = match &pattern_file {
+        PatternFile::Cells(pattern) => pattern.rule.as_deref(),
+        PatternFile::Macrocell(macrocell) => macrocell.rule.as_deref(),
+    };
+    // The command line rule wins over the one in the file
+    // Continuous automata only take the cells of the pattern
+    let pattern_rule = pattern_rule.filter(|_| cli.continuous.is_none());
+    let pattern_directory = cli.pattern.as_deref().and_then(Path::parent);
+    let rule = match (cli.rule.clone(), pattern_rule) {
+        (Some(rule), _) => rule,
+        (None, Some(rule)) => parse_pattern_rule(rule, pattern_directory)?,
+        (None, None) => Rule::LIFE,
+    };
+
+    // Bounded grids are the universe
+    let topology_size = rule
+        .topology()
+        .map(|topology| (topology.width() as usize, topology.height() as usize));
+    if let (Some(universe_size), Some(topology_size)) = (cli.universe_size, topology_size) {
+        anyhow::ensure!(
+            universe_size == topology_size,
+            "Universe size {}x{} doesn't match the grid of rule {rule}",
+            universe_size.0,
+            universe_size.1
+        );
+    }
+    let (width, height) = topology_size.or(cli.universe_size).unwrap_or_else(|| {
+        let (pattern_width, pattern_height) = match &pattern_file {
+            PatternFile::Cells(pattern) => (pattern.width as usize, pattern.height as usize),
+            PatternFile::Macrocell(macrocell) => {
+                let size = 1usize
+                    .checked_shl(macrocell.hashlife.node_level(macrocell.hashlife.root()))
+                    .unwrap_or(usize::MAX);
+                let size = size.min(WINDOW_UNIVERSE_SIZE_MAX / 2);
+                (size, size)
+            }
+        };
+        (
+            (pattern_width.saturating_mul(2)).max(UNIVERSE_SIZE_MIN),
+            (pattern_height.saturating_mul(2)).max(UNIVERSE_SIZE_MIN),
+        )
+    });
+
+    let mut pattern_file = pattern_file;
+    if let PatternFile::Macrocell(macrocell) = &mut pattern_file {
+        // Macrocell patterns are read into HashLife
+        ensure_unbounded_rule("Macrocell patterns", &rule, HashLife::supports)?;
+        macrocell.hashlife.set_rule(rule.clone());
+    }
+    Ok(Board {
+        rule,
+        width,
+        height,
+        pattern_file,
+    })
+}
+
+impl Board {
+    pub fn is_macrocell(&self) -> bool {
+        matches!(self.pattern_file, PatternFile::Macrocell(_))
+    }
+
+    /// Shrinks the board to the frame the unbounded engines place their cells in
+    ///
+    /// The pattern stays in the middle, the cells outside of the frame are still kept and drawn.
+    pub fn shrink_to_window(&mut self) {
+        self.width = self.width.min(WINDOW_UNIVERSE_SIZE_MAX);
+        self.height = self.height.min(WINDOW_UNIVERSE_SIZE_MAX);
+    }
+
+    /// Universe of the board size with the pattern in the middle, for the bounded engines
+    pub fn universe(self) -> anyhow::Result<Universe> {
+        anyhow::ensure!(
+            self.width
+                .checked_mul(self.height)
+                .is_some_and(|cells| cells <= DENSE_UNIVERSE_CELLS_MAX),
+            "A {}x{} universe is too large for a bounded engine, the sparse and HashLife \
+             engines don't allocate the whole board",
+            self.width,
+            self.height
+        );
+        let mut universe = Universe::new(self.width, self.height);
+        universe.set_rule(self.rule.clone());
+        match self.pattern_file {
+            PatternFile::Cells(pattern) => pattern.place_centered(&mut universe),
+            PatternFile::Macrocell(_) => self.hashlife()?.write_to(&mut universe),
+        }
+        Ok(universe)
+    }
+
+    /// HashLife with the pattern in the middle of the board, cells are never bounded
+    pub fn hashlife(self) -> anyhow::Result<HashLife> {
+        ensure_unbounded_rule("HashLife", &self.rule, HashLife::supports)?;
+        let (x, y) = self.pattern_origin();
+        match self.pattern_file {
+            PatternFile::Cells(pattern) => {
+                let mut hashlife = HashLife::new();
+                for (cell_x, cell_y, state) in pattern.cells {
+                    hashlife.set_cell(x + cell_x, y + cell_y, state);
+                }
+                hashlife.set_rule(self.rule);
+                Ok(hashlife)
+            }
+            PatternFile::Macrocell(mut macrocell) => {
+                macrocell.hashlife.translate(x, y);
+                Ok(macrocell.hashlife)
+            }
+        }
+    }
+
+    /// Sparse universe with the pattern in the middle of the board, cells are never bounded
+    pub fn sparse_universe(self) -> anyhow::Result<SparseUniverse> {
+        ensure_unbounded_rule("The sparse universe", &self.rule, SparseUniverse::supports)?;
+        if self.is_macrocell() {
+            return Ok(SparseUniverse::from_hashlife(&self.hashlife()?));
+        }
+        let (x, y) = self.pattern_origin();
+        let mut sparse_universe = SparseUniverse::new();
+        if let PatternFile::Cells(pattern) = self.pattern_file {
+            for (cell_x, cell_y, state) in pattern.cells {
+                sparse_universe.set_cell(x + cell_x, y + cell_y, state);
+            }
+        }
+        sparse_universe.set_rule(self.rule);
+        Ok(sparse_universe)
+    }
+
+    // Where the pattern top-left goes, the macrocell root is centered at the origin instead
+    fn pattern_origin(&self) -> (i64, i64) {
+        let (width, height) = (self.width as i64, self.height as i64);
+        match &self.pattern_file {
+            PatternFile::Cells(pattern) => (
+                (width - pattern.width as i64) / 2,
+                (height - pattern.height as i64) / 2,
+            ),
+            PatternFile::Macrocell(_) => (width / 2, height / 2),
+        }
+    }
+}
+
+// HashLife and the sparse universe run any range 1 rule, Hensel, von Neumann, hexagonal and
+// Generations included, but no B0, Larger than Life, rule tables or bounded grids
+fn ensure_unbounded_rule(
+    engine: &str,
+    rule: &Rule,
+    supports: fn(&Rule) -> bool,
+) -> anyhow::Result<()> {
+    if supports(rule) {
+        return Ok(());
+    }
+    let reason = if rule.has_b0() {
+        "B0 rules would turn the whole unbounded plane alive"
+    } else if rule.larger_than_life().is_some() {
+        "Larger than Life rules aren't supported"
+    } else if rule.rule_table().is_some() {
+        "rule tables aren't supported"
+    } else {
+        "the universe is unbounded, bounded grids aren't supported"
+    };
+    anyhow::bail!("{engine} can't run rule {rule}, {reason}")
+}
+
+// Rule string of a pattern file, other names are rule tables in `<name>.rule` next to the pattern
+fn parse_pattern_rule(rule: &str, pattern_directory: Option<&Path>) -> anyhow::Result<Rule> {
+    let error = match rule.parse() {
+        Ok(rule) => return Ok(rule),
+        Err(error) => error,
+    };
+    // The table name may end with a topology, `Wireworld:T100,50`
+    let Ok((name, topology)) = topology::split_suffix(rule.trim()) else {
+        return Err(error).context("Invalid rule in the pattern file");
+    };
+    let path = pattern_directory
+        .unwrap_or(Path::new(""))
+        .join(format!("{name}.rule"));
+    if !path.is_file() {
+        return Err(error).context("Invalid rule in the pattern file");
+    }
+    let mut rule = Rule::from_table(table::load(&path)?);
+    rule.set_topology(topology);
+    Ok(rule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::ALIVE;
+
+    #[test]
+    fn detects_formats_by_content() {
+        let cases = [
+            ("#N Glider\nx = 3, y = 3\nbo$2bo$3o!", Format::Rle),
+            ("x = 3, y = 3\nbo$2bo$3o!", Format::Rle),
+            ("!Name: Glider\n.O.\n..O\nOOO", Format::Plaintext),
+            (".O.\n..O\nOOO\n", Format::Plaintext),
+            ("#Life 1.05\n#P -1 -1\n.*.\n..*\n***", Format::Life105),
+            ("#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1", Format::Life106),
+            (
+                "[M2] (golly 4.2)\n#R B3/S23\n.*$..*$***$\n4 0 0 0 1",
+                Format::Macrocell,
+            ),
+        ];
+        for (text, format) in cases {
+            assert_eq!(Format::detect(text), format, "{text}");
+            assert_eq!(parse(text).unwrap().cells.len(), 5, "{text}");
+        }
+    }
+
+    #[test]
+    fn rotates_and_flips() {
+        // L-tromino in a 2x3 box with an empty row at the bottom
+        let mut pattern = Pattern {
+            width: 2,
+            height: 3,
+            cells: vec![(0, 0, 1), (0, 1, 1), (1, 1, 1)],
+            ..Default::default()
+        };
+        let original = pattern.clone();
+        pattern.rotate_clockwise();
+        assert_eq!((pattern.width, pattern.height), (3, 2));
+        assert_eq!(pattern.rows(), [[DEAD, 1, 1], [DEAD, 1, DEAD]]);
+        for _ in 0..3 {
+            pattern.rotate_clockwise();
+        }
+        assert_eq!(pattern, original);
+
+        pattern.flip_horizontally();
+        assert_eq!(pattern.rows(), [[DEAD, 1], [1, 1], [DEAD, DEAD]]);
+        pattern.flip_vertically();
+        assert_eq!(pattern.rows(), [[DEAD, DEAD], [1, 1], [DEAD, 1]]);
+    }
+
+    #[test]
+    fn huge_boards_only_allocate_on_bounded_engines() {
+        use clap::Parser;
+
+        let cli = Cli::parse_from(["game_of_life_wgpu"]);
+        let board = || {
+            let pattern = rle::parse("x = 100000, y = 100000\nbo$2bo$3o!").unwrap();
+            create_board(&cli, PatternFile::Cells(pattern)).unwrap()
+        };
+        // The glider is in the middle of the 200000x200000 board
+        let glider =
+            [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)].map(|(x, y)| (x + 50000, y + 50000, 1));
+
+        let mut sparse_cells = board().sparse_universe().unwrap().live_cells();
+        sparse_cells.sort_unstable();
+        let mut hashlife_cells = board().hashlife().unwrap().live_cells();
+        hashlife_cells.sort_unstable();
+        for cells in [sparse_cells, hashlife_cells] {
+            let mut expected = glider.to_vec();
+            expected.sort_unstable();
+            assert_eq!(cells, expected);
+        }
+        assert!(board().universe().is_err());
+    }
+
+    #[test]
+    fn macrocell_patterns_reach_past_the_window() {
+        use clap::Parser;
+
+        let mut hashlife = HashLife::new();
+        for (x, y) in [(0, 0), (10_000, 10_000)] {
+            hashlife.set_cell(x, y, ALIVE);
+        }
+        let text = macrocell::write(&hashlife, Some("B3/S23"), &[]);
+        let cli = Cli::parse_from(["game_of_life_wgpu"]);
+        let mut board = create_board(
+            &cli,
+            PatternFile::Macrocell(macrocell::parse(&text).unwrap()),
+        )
+        .unwrap();
+        board.shrink_to_window();
+        assert!(board.width <= WINDOW_UNIVERSE_SIZE_MAX);
+
+        let hashlife = board.hashlife().unwrap();
+        assert_eq!(hashlife.population(), 2);
+        let (min, max) = hashlife.bounding_box().unwrap();
+        assert_eq!((max.0 - min.0, max.1 - min.1), (10_001, 10_001));
+    }
+
+    #[test]
+    fn unbounded_engines_explain_unsupported_rules() {
+        for rule in ["B3/S2-i34q", "B1/SV", "B2/SH", "345/2/4"] {
+            let rule: Rule = rule.parse().unwrap();
+            assert!(ensure_unbounded_rule("HashLife", &rule, HashLife::supports).is_ok());
+        }
+        let cases = [
+            ("B0/S8", "B0"),
+            ("R5,C0,M1,S34..58,B34..45,NM", "Larger than Life"),
+            ("B3/S23:T100,50", "bounded grids"),
+        ];
+        for (rule, reason) in cases {
+            let rule: Rule = rule.parse().unwrap();
+            let error =
+                ensure_unbounded_rule("The sparse universe", &rule, SparseUniverse::supports)
+                    .unwrap_err();
+            assert!(error.to_string().contains(reason), "{error}");
+        }
+    }
+}
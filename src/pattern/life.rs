@@ -0,0 +1,185 @@
+// Life 1.05 and Life 1.06 formats
+// https://conwaylife.com/wiki/Life_1.05
+// https://conwaylife.com/wiki/Life_1.06
+
+use crate::pattern::{ParseError, Pattern};
+use crate::simulation::{ALIVE, DEAD};
+
+pub fn parse_105(text: &str) -> Result<Pattern, ParseError> {
+    let mut comments = Vec::new();
+    let mut rule = None;
+    let mut cells = Vec::new();
+    // Top-left of the current block and the row within it
+    let mut block = (0i64, 0i64);
+    let mut row = 0;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim_end();
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut characters = directive.chars();
+            let kind = characters.next();
+            let content = characters.as_str().trim();
+            match kind {
+                Some('D' | 'C') => comments.push(content.to_string()),
+                Some('N') => rule = Some("B3/S23".to_string()),
+                Some('R') => rule = Some(content.to_string()),
+                Some('P') => {
+                    let column = content.as_ptr() as usize - line.as_ptr() as usize + 1;
+                    block = parse_coordinates(content, line_number, column)?;
+                    row = 0;
+                }
+                // #Life header
+                _ => (),
+            }
+            continue;
+        }
+
+        for (x, character) in line.chars().enumerate() {
+            match character {
+                '.' => (),
+                '*' | 'O' => cells.push((block.0 + x as i64, block.1 + row, ALIVE)),
+                character => {
+                    return Err(ParseError::new(
+                        line_number,
+                        x + 1,
+                        format!("unexpected character '{character}', expected '.' or '*'"),
+                    ));
+                }
+            }
+        }
+        row += 1;
+    }
+
+    Ok(Pattern {
+        comments,
+        rule,
+        ..Pattern::from_cells(cells)
+    })
+}
+
+pub fn parse_106(text: &str) -> Result<Pattern, ParseError> {
+    let mut cells = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (x, y) = parse_coordinates(line, index + 1, 1)?;
+        cells.push((x, y, ALIVE));
+    }
+    Ok(Pattern::from_cells(cells))
+}
+
+/// One block at the pattern origin
+pub fn write_105(pattern: &Pattern) -> String {
+    let mut text = "#Life 1.05\n".to_string();
+    for comment in &pattern.comments {
+        text += &format!("#D {comment}\n");
+    }
+    if let Some(rule) = &pattern.rule {
+        text += &format!("#R {rule}\n");
+    }
+    text += "#P 0 0\n";
+    for row in pattern.text_rows('.', '*') {
+        text += &row;
+        text += "\n";
+    }
+    text
+}
+
+/// Life 1.06 has no metadata, only live cell coordinates
+pub fn write_106(pattern: &Pattern) -> String {
+    let mut cells: Vec<_> = pattern
+        .cells
+        .iter()
+        .filter(|&&(_, _, state)| state != DEAD)
+        .map(|&(x, y, _)| (y, x))
+        .collect();
+    cells.sort();
+
+    let mut text = "#Life 1.06\n".to_string();
+    for (y, x) in cells {
+        text += &format!("{x} {y}\n");
+    }
+    text
+}
+
+/// Two whitespace separated integers, `column` is where `text` starts in the line
+fn parse_coordinates(
+    text: &str,
+    line_number: usize,
+    column: usize,
+) -> Result<(i64, i64), ParseError> {
+    let mut coordinates = [0i64; 2];
+    let mut words = text.split_whitespace();
+    for coordinate in coordinates.iter_mut() {
+        let word = words.next().ok_or_else(|| {
+            ParseError::new(line_number, column + text.len(), "expected two coordinates")
+        })?;
+        let word_column = column + (word.as_ptr() as usize - text.as_ptr() as usize);
+        *coordinate = word.parse().map_err(|_| {
+            ParseError::new(
+                line_number,
+                word_column,
+                format!("expected an integer, found \"{word}\""),
+            )
+        })?;
+    }
+    if let Some(word) = words.next() {
+        let word_column = column + (word.as_ptr() as usize - text.as_ptr() as usize);
+        return Err(ParseError::new(
+            line_number,
+            word_column,
+            format!("unexpected \"{word}\" after coordinates"),
+        ));
+    }
+    Ok((coordinates[0], coordinates[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_cells(pattern: &Pattern) -> Vec<(i64, i64, u8)> {
+        let mut cells = pattern.cells.clone();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn life_105_blocks_are_merged() {
+        let text = "#Life 1.05\n#D Two blocks\n#R 23/3\n#P -3 -1\n**\n**\n#P 2 0\n*\n";
+        let pattern = parse_105(text).unwrap();
+        assert_eq!(pattern.rule.as_deref(), Some("23/3"));
+        assert_eq!(pattern.comments, ["Two blocks"]);
+        assert_eq!((pattern.width, pattern.height), (6, 2));
+        assert_eq!(
+            sorted_cells(&pattern),
+            [(0, 0, 1), (0, 1, 1), (1, 0, 1), (1, 1, 1), (5, 1, 1)]
+        );
+        assert_eq!(
+            sorted_cells(&parse_105(&write_105(&pattern)).unwrap()),
+            sorted_cells(&pattern)
+        );
+    }
+
+    #[test]
+    fn life_106_round_trips() {
+        let text = "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n";
+        let pattern = parse_106(text).unwrap();
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(write_106(&pattern), "#Life 1.06\n1 0\n2 1\n0 2\n1 2\n2 2\n");
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        let error = parse_106("#Life 1.06\n0 0\n1  x\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 4));
+
+        let error = parse_105("#Life 1.05\n#P 0 zero\n*\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 6));
+
+        let error = parse_105("#Life 1.05\n#P 0 0\n*.o\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 3));
+    }
+}
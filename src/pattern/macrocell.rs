@@ -0,0 +1,411 @@
+// Golly macrocell format (.mc), a serialized HashLife quadtree
+// https://golly.sourceforge.io/Help/formats.html#mc
+
+use crate::pattern::ParseError;
+use crate::simulation::hashlife::{HashLife, NodeId};
+use crate::simulation::{ALIVE, DEAD};
+use std::collections::{HashMap, HashSet};
+
+// Two-state files store 8x8 leaves as bitmaps
+const BITMAP_LEVEL: u32 = 3;
+const BITMAP_SIZE: i64 = 8;
+
+#[derive(Debug)]
+pub struct Macrocell {
+    pub hashlife: HashLife,
+    pub rule: Option<String>,
+    pub comments: Vec<String>,
+}
+
+pub fn parse(text: &str) -> Result<Macrocell, ParseError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line));
+    match lines.next() {
+        Some((_, line)) if line.starts_with("[M2]") => (),
+        _ => return Err(ParseError::new(1, 1, "expected \"[M2]\" header")),
+    }
+
+    let mut hashlife = HashLife::new();
+    let mut rule = None;
+    let mut comments = Vec::new();
+    // Node of every line, lines are referenced by 1-based index, 0 is the empty node
+    let mut nodes: Vec<NodeId> = Vec::new();
+    for (line_number, line) in lines {
+        let line = line.trim_end();
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut characters = directive.chars();
+            let kind = characters.next();
+            let content = characters.as_str().trim();
+            match kind {
+                Some('R') => rule = Some(content.to_string()),
+                Some('G') => {
+                    let generation = content.parse().map_err(|_| {
+                        ParseError::new(line_number, 4, format!("invalid generation \"{content}\""))
+                    })?;
+                    hashlife.set_generation(generation);
+                }
+                Some('C' | 'D' | 'N') => comments.push(content.to_string()),
+                _ => (),
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let node = if line.starts_with(['.', '*', '$']) {
+            parse_bitmap(line, line_number, &mut hashlife)?
+        } else {
+            parse_node(line, line_number, &nodes, &mut hashlife)?
+        };
+        nodes.push(node);
+    }
+
+    if let Some(&root) = nodes.last() {
+        hashlife.set_root(root);
+    }
+    Ok(Macrocell {
+        hashlife,
+        rule,
+        comments,
+    })
+}
+
+pub fn write(hashlife: &HashLife, rule: Option<&str>, comments: &[String]) -> String {
+    let mut text = "[M2] (game_of_life_wgpu)\n".to_string();
+    if let Some(rule) = rule {
+        text += &format!("#R {rule}\n");
+    }
+    if hashlife.generation() != 0 {
+        text += &format!("#G {}\n", hashlife.generation());
+    }
+    for comment in comments {
+        text += &format!("#C {comment}\n");
+    }
+
+    let multi_state = has_multi_state_leaves(hashlife, hashlife.root(), &mut HashSet::new());
+    // Bitmap leaves need a root of at least their size
+    let padded = (!multi_state && hashlife.node_level(hashlife.root()) < BITMAP_LEVEL).then(|| {
+        let mut padded = hashlife.clone();
+        padded.set_root(hashlife.root());
+        padded
+    });
+    let hashlife = padded.as_ref().unwrap_or(hashlife);
+
+    let mut writer = Writer {
+        hashlife,
+        multi_state,
+        indices: HashMap::new(),
+        lines: Vec::new(),
+    };
+    writer.write_node(hashlife.root());
+    for line in writer.lines {
+        text += &line;
+        text += "\n";
+    }
+    text
+}
+
+fn parse_bitmap(
+    line: &str,
+    line_number: usize,
+    hashlife: &mut HashLife,
+) -> Result<NodeId, ParseError> {
+    let mut node = hashlife.empty_node_of_level(BITMAP_LEVEL);
+    let (mut x, mut y) = (0, 0);
+    for (index, character) in line.chars().enumerate() {
+        let column = index + 1;
+        match character {
+            '.' | '*' => {
+                if x >= BITMAP_SIZE || y >= BITMAP_SIZE {
+                    return Err(ParseError::new(
+                        line_number,
+                        column,
+                        "leaf is larger than 8x8",
+                    ));
+                }
+                if character == '*' {
+                    node = hashlife.with_cell(node, x, y, ALIVE);
+                }
+                x += 1;
+            }
+            '$' => {
+                x = 0;
+                y += 1;
+            }
+            character => {
+                return Err(ParseError::new(
+                    line_number,
+                    column,
+                    format!("unexpected character '{character}' in leaf"),
+                ));
+            }
+        }
+    }
+    Ok(node)
+}
+
+fn parse_node(
+    line: &str,
+    line_number: usize,
+    nodes: &[NodeId],
+    hashlife: &mut HashLife,
+) -> Result<NodeId, ParseError> {
+    let words: Vec<_> = line
+        .split_whitespace()
+        .map(|word| (word, word.as_ptr() as usize - line.as_ptr() as usize + 1))
+        .collect();
+    if words.len() != 5 {
+        return Err(ParseError::new(
+            line_number,
+            1,
+            "expected \"level nw ne sw se\"",
+        ));
+    }
+    let parse_number = |(word, column): (&str, usize)| {
+        word.parse::<u64>().map_err(|_| {
+            ParseError::new(
+                line_number,
+                column,
+                format!("expected a non-negative integer, found \"{word}\""),
+            )
+        })
+    };
+
+    let level = parse_number(words[0])?;
+    if !(1..=62).contains(&level) {
+        return Err(ParseError::new(
+            line_number,
+            1,
+            format!("invalid level {level}"),
+        ));
+    }
+    let level = level as u32;
+
+    let mut children = [0; 4];
+    for (child, &word) in children.iter_mut().zip(&words[1..]) {
+        let value = parse_number(word)?;
+        *child = if level == 1 {
+            // Multi-state leaves are cell states
+            let state = u8::try_from(value).map_err(|_| {
+                ParseError::new(line_number, word.1, format!("invalid cell state {value}"))
+            })?;
+            HashLife::leaf(state)
+        } else if value == 0 {
+            hashlife.empty_node_of_level(level - 1)
+        } else {
+            let node = *nodes.get(value as usize - 1).ok_or_else(|| {
+                ParseError::new(
+                    line_number,
+                    word.1,
+                    format!("node {value} is not defined before this line"),
+                )
+            })?;
+            if hashlife.node_level(node) != level - 1 {
+                return Err(ParseError::new(
+                    line_number,
+                    word.1,
+                    format!(
+                        "node {value} has level {}, expected {}",
+                        hashlife.node_level(node),
+                        level - 1
+                    ),
+                ));
+            }
+            node
+        };
+    }
+    Ok(hashlife
+        .node(children)
+        .expect("children levels are checked"))
+}
+
+fn has_multi_state_leaves(
+    hashlife: &HashLife,
+    node: NodeId,
+    visited: &mut HashSet<NodeId>,
+) -> bool {
+    if hashlife.node_population(node) == 0 || !visited.insert(node) {
+        return false;
+    }
+    if hashlife.node_level(node) == 0 {
+        return node != HashLife::leaf(ALIVE);
+    }
+    hashlife
+        .node_children(node)
+        .into_iter()
+        .any(|child| has_multi_state_leaves(hashlife, child, visited))
+}
+
+struct Writer<'a> {
+    hashlife: &'a HashLife,
+    multi_state: bool,
+    // 1-based line index of every written node
+    indices: HashMap<NodeId, usize>,
+    lines: Vec<String>,
+}
+
+impl Writer<'_> {
+    fn write_node(&mut self, node: NodeId) -> usize {
+        if self.hashlife.node_population(node) == 0 {
+            return 0;
+        }
+        if let Some(&index) = self.indices.get(&node) {
+            return index;
+        }
+
+        let level = self.hashlife.node_level(node);
+        let line = if !self.multi_state && level == BITMAP_LEVEL {
+            self.bitmap(node)
+        } else if level == 1 {
+            let states = self.hashlife.node_children(node);
+            format!("1 {} {} {} {}", states[0], states[1], states[2], states[3])
+        } else {
+            let children = self.hashlife.node_children(node);
+            let indices = children.map(|child| self.write_node(child));
+            format!(
+                "{level} {} {} {} {}",
+                indices[0], indices[1], indices[2], indices[3]
+            )
+        };
+        self.lines.push(line);
+        self.indices.insert(node, self.lines.len());
+        self.lines.len()
+    }
+
+    fn bitmap(&self, node: NodeId) -> String {
+        let mut rows = vec![String::new(); BITMAP_SIZE as usize];
+        self.collect_bitmap(node, 0, 0, &mut rows);
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|row| row.trim_end_matches('.').to_string())
+            .collect();
+        let mut line = rows.join("$");
+        while line.ends_with('$') {
+            line.pop();
+        }
+        line + "$"
+    }
+
+    fn collect_bitmap(&self, node: NodeId, x: usize, y: usize, rows: &mut [String]) {
+        let level = self.hashlife.node_level(node);
+        if level == 0 {
+            let row = &mut rows[y];
+            while row.len() <= x {
+                row.push('.');
+            }
+            if node != HashLife::leaf(DEAD) {
+                row.replace_range(x..=x, "*");
+            }
+            return;
+        }
+        let half = 1 << (level - 1);
+        for (quadrant, child) in self.hashlife.node_children(node).into_iter().enumerate() {
+            self.collect_bitmap(
+                child,
+                x + half * (quadrant % 2),
+                y + half * (quadrant / 2),
+                rows,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_cells(hashlife: &HashLife) -> Vec<(i64, i64, u8)> {
+        let mut cells = hashlife.live_cells();
+        cells.sort_by_key(|&(x, y, _)| (y, x));
+        cells
+    }
+
+    #[test]
+    fn parses_two_state_file() {
+        let text = "[M2] (golly 4.2)\n#R B3/S23\n#G 12\n.*$..*$***$\n4 0 0 0 1\n";
+        let macrocell = parse(text).unwrap();
+        assert_eq!(macrocell.rule.as_deref(), Some("B3/S23"));
+        assert_eq!(macrocell.hashlife.generation(), 12);
+        // The glider is in the south-east quadrant of a 16x16 root centered at the origin
+        assert_eq!(
+            sorted_cells(&macrocell.hashlife),
+            [(1, 0, 1), (2, 1, 1), (0, 2, 1), (1, 2, 1), (2, 2, 1)]
+        );
+    }
+
+    #[test]
+    fn parses_multi_state_leaves() {
+        let text = "[M2]\n#R WireWorld\n1 0 1 2 3\n2 1 0 0 1\n";
+        let macrocell = parse(text).unwrap();
+        assert_eq!(
+            sorted_cells(&macrocell.hashlife),
+            [
+                (-1, -2, 1),
+                (-2, -1, 2),
+                (-1, -1, 3),
+                (1, 0, 1),
+                (0, 1, 2),
+                (1, 1, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        for text in [
+            "[M2]\n#R B3/S23\n.*$..*$***$\n4 0 0 0 1\n",
+            "[M2]\n1 0 1 2 3\n2 1 0 0 1\n",
+        ] {
+            let macrocell = parse(text).unwrap();
+            let written = write(
+                &macrocell.hashlife,
+                macrocell.rule.as_deref(),
+                &macrocell.comments,
+            );
+            let reparsed = parse(&written).unwrap();
+            assert_eq!(
+                sorted_cells(&reparsed.hashlife),
+                sorted_cells(&macrocell.hashlife)
+            );
+            assert_eq!(reparsed.rule, macrocell.rule);
+        }
+    }
+
+    #[test]
+    fn shares_identical_nodes() {
+        let mut hashlife = HashLife::new();
+        for i in 0..64 {
+            for (x, y) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                hashlife.set_cell(i * 64 + x, y, ALIVE);
+            }
+        }
+        let written = write(&hashlife, None, &[]);
+        // 64 identical blocks are one leaf, the rest are the tree above it
+        assert_eq!(
+            written.lines().filter(|line| line.ends_with('$')).count(),
+            1
+        );
+        assert_eq!(sorted_cells(&parse(&written).unwrap().hashlife).len(), 256);
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        let error = parse("#R B3/S23\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+
+        let error = parse("[M2]\n.*$..*$***$\n4 0 0 0 2\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 9));
+
+        let error = parse("[M2]\n.*$..*$***$\n5 0 0 0 1\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 9));
+
+        let error = parse("[M2]\n.*$..*$***x\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 11));
+
+        let error = parse("[M2]\n1 0 1 2 300\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 9));
+    }
+}
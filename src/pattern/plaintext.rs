@@ -0,0 +1,103 @@
+// Plaintext format (.cells)
+// https://conwaylife.com/wiki/Plaintext
+
+use crate::pattern::{ParseError, Pattern};
+use crate::simulation::ALIVE;
+
+pub fn parse(text: &str) -> Result<Pattern, ParseError> {
+    let mut name = None;
+    let mut author = None;
+    let mut comments = Vec::new();
+    let mut cells = Vec::new();
+    let mut y = 0;
+    let mut body_started = false;
+    for (index, line) in text.lines().enumerate() {
+        if let Some(comment) = line.strip_prefix('!') {
+            if let Some(pattern_name) = comment.strip_prefix("Name:") {
+                name = Some(pattern_name.trim().to_string());
+            } else if let Some(pattern_author) = comment.strip_prefix("Author:") {
+                author = Some(pattern_author.trim().to_string());
+            } else {
+                comments.push(comment.trim().to_string());
+            }
+            continue;
+        }
+        // Blank lines before the first row are not part of the pattern
+        if !body_started && line.trim().is_empty() {
+            continue;
+        }
+        body_started = true;
+
+        for (x, character) in line.trim_end().chars().enumerate() {
+            match character {
+                '.' => (),
+                'O' | '*' => cells.push((x as i64, y, ALIVE)),
+                character => {
+                    return Err(ParseError::new(
+                        index + 1,
+                        x + 1,
+                        format!("unexpected character '{character}', expected '.' or 'O'"),
+                    ));
+                }
+            }
+        }
+        y += 1;
+    }
+
+    let width = cells
+        .iter()
+        .map(|&(x, _, _)| x as u64 + 1)
+        .max()
+        .unwrap_or(0);
+    Ok(Pattern {
+        name,
+        author,
+        comments,
+        width,
+        height: y as u64,
+        cells,
+        ..Default::default()
+    })
+}
+
+/// Any non-dead state is written as 'O'
+pub fn write(pattern: &Pattern) -> String {
+    let mut text = String::new();
+    if let Some(name) = &pattern.name {
+        text += &format!("!Name: {name}\n");
+    }
+    if let Some(author) = &pattern.author {
+        text += &format!("!Author: {author}\n");
+    }
+    for comment in &pattern.comments {
+        text += &format!("!{comment}\n");
+    }
+    for row in pattern.text_rows('.', 'O') {
+        text += &row;
+        text += "\n";
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let text =
+            "!Name: Glider\n!Author: Richard K. Guy\n!The smallest spaceship.\n.O\n..O\nOOO\n";
+        let pattern = parse(text).unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.author.as_deref(), Some("Richard K. Guy"));
+        assert_eq!(pattern.comments, ["The smallest spaceship."]);
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(write(&pattern), text);
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        let error = parse("!Name: Broken\n.O.\n.Ox\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 3));
+    }
+}
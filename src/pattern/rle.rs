@@ -0,0 +1,481 @@
+// Run Length Encoded format
+// https://conwaylife.com/wiki/Run_Length_Encoded
+
+use crate::pattern::{ParseError, Pattern};
+use crate::simulation::{ALIVE, DEAD};
+
+// Body lines of written files are wrapped at this width
+const LINE_WIDTH: usize = 70;
+// Live cells are stored one by one, longer runs are rejected even in wide patterns
+const MAX_LIVE_RUN: u64 = 1 << 20;
+
+pub fn parse(text: &str) -> Result<Pattern, ParseError> {
+    let mut pattern = Pattern::default();
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line));
+
+    // Comments and header
+    let mut header_found = false;
+    for (line_number, line) in lines.by_ref() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(comment) = trimmed.strip_prefix('#') {
+            parse_comment(comment, &mut pattern);
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        if !trimmed.starts_with('x') {
+            return Err(ParseError::new(
+                line_number,
+                indent + 1,
+                "expected header \"x = .., y = ..\"",
+            ));
+        }
+        parse_header(line, line_number, &mut pattern)?;
+        header_found = true;
+        break;
+    }
+    if !header_found {
+        let last_line = text.lines().count().max(1);
+        return Err(ParseError::new(last_line, 1, "missing header"));
+    }
+
+    // Body
+    let mut x = 0i64;
+    let mut y = 0i64;
+    // Run count and the column where it started
+    let mut count: Option<(u64, usize)> = None;
+    // Multi-state prefix letter and its column
+    let mut prefix: Option<(char, usize)> = None;
+    let mut last_line = 0;
+    'body: for (line_number, line) in lines {
+        last_line = line_number;
+        for (index, character) in line.chars().enumerate() {
+            let column = index + 1;
+            if let Some((prefix_character, prefix_column)) = prefix
+                && !character.is_ascii_uppercase()
+            {
+                return Err(ParseError::new(
+                    line_number,
+                    prefix_column,
+                    format!("state prefix '{prefix_character}' must be followed by 'A'..'X'"),
+                ));
+            }
+
+            let run = count.map_or(1, |(count, _)| count);
+            let run_error = |message| {
+                let column = count.map_or(column, |(_, start_column)| start_column);
+                ParseError::new(line_number, column, message)
+            };
+            let past_width = || run_error("run goes past the width in the header");
+            match character {
+                '0'..='9' => {
+                    let digit = character.to_digit(10).unwrap() as u64;
+                    let (value, start_column) = count.unwrap_or((0, column));
+                    let value = value
+                        .checked_mul(10)
+                        .and_then(|value| value.checked_add(digit))
+                        .ok_or_else(|| {
+                            ParseError::new(line_number, start_column, "run count is too large")
+                        })?;
+                    count = Some((value, start_column));
+                    continue;
+                }
+                'p'..='y' => {
+                    prefix = Some((character, column));
+                    continue;
+                }
+                'b' | '.' => x = advance(x, run, pattern.width).ok_or_else(past_width)?,
+                'o' => {
+                    let end = advance(x, run, pattern.width).ok_or_else(past_width)?;
+                    check_live_run(run, y, pattern.height).map_err(run_error)?;
+                    push_run(&mut pattern, x, end, y, ALIVE);
+                    x = end;
+                }
+                'A'..='X' => {
+                    let state = state_from_letters(prefix.map(|(prefix, _)| prefix), character)
+                        .ok_or_else(|| {
+                            let column = prefix.map_or(column, |(_, column)| column);
+                            ParseError::new(line_number, column, "cell state is larger than 255")
+                        })?;
+                    prefix = None;
+                    let end = advance(x, run, pattern.width).ok_or_else(past_width)?;
+                    check_live_run(run, y, pattern.height).map_err(run_error)?;
+                    push_run(&mut pattern, x, end, y, state);
+                    x = end;
+                }
+                '$' => {
+                    x = 0;
+                    y = advance(y, run, pattern.height)
+                        .ok_or_else(|| run_error("rows go past the height in the header"))?;
+                }
+                '!' => {
+                    count = None;
+                    break 'body;
+                }
+                character if character.is_whitespace() => {
+                    if let Some((_, start_column)) = count {
+                        return Err(ParseError::new(
+                            line_number,
+                            start_column,
+                            "run count must be followed by a cell or '$'",
+                        ));
+                    }
+                    continue;
+                }
+                character => {
+                    return Err(ParseError::new(
+                        line_number,
+                        column,
+                        format!("unexpected character '{character}'"),
+                    ));
+                }
+            }
+            count = None;
+        }
+    }
+    if let Some((_, start_column)) = count {
+        return Err(ParseError::new(
+            last_line,
+            start_column,
+            "run count must be followed by a cell or '$'",
+        ));
+    }
+    if let Some((_, column)) = prefix {
+        return Err(ParseError::new(
+            last_line,
+            column,
+            "state prefix must be followed by 'A'..'X'",
+        ));
+    }
+
+    Ok(pattern)
+}
+
+pub fn write(pattern: &Pattern) -> String {
+    let mut text = String::new();
+    if let Some(name) = &pattern.name {
+        text += &format!("#N {name}\n");
+    }
+    if let Some(author) = &pattern.author {
+        text += &format!("#O {author}\n");
+    }
+    for comment in &pattern.comments {
+        text += &format!("#C {comment}\n");
+    }
+    text += &format!("x = {}, y = {}", pattern.width, pattern.height);
+    if let Some(rule) = &pattern.rule {
+        text += &format!(", rule = {rule}");
+    }
+    text += "\n";
+
+    let multi_state = pattern.cells.iter().any(|&(_, _, state)| state > ALIVE);
+    let mut cells: Vec<_> = pattern
+        .cells
+        .iter()
+        .filter(|&&(_, _, state)| state != DEAD)
+        .copied()
+        .collect();
+    cells.sort_by_key(|&(x, y, _)| (y, x));
+
+    // Runs of (count, tag), trailing dead cells of a row are never written
+    let mut runs: Vec<(u64, String)> = Vec::new();
+    let mut push = |count: u64, tag: String| {
+        if count == 0 {
+            return;
+        }
+        match runs.last_mut() {
+            Some((last_count, last_tag)) if *last_tag == tag => *last_count += count,
+            _ => runs.push((count, tag)),
+        }
+    };
+    let (mut x, mut y) = (0, 0);
+    for (cell_x, cell_y, state) in cells {
+        if cell_y > y {
+            push((cell_y - y) as u64, "$".to_string());
+            (x, y) = (0, cell_y);
+        }
+        push((cell_x - x) as u64, state_to_letters(DEAD, multi_state));
+        push(1, state_to_letters(state, multi_state));
+        x = cell_x + 1;
+    }
+    push(1, "!".to_string());
+
+    let mut line = String::new();
+    for (count, tag) in runs {
+        let item = if count == 1 {
+            tag
+        } else {
+            format!("{count}{tag}")
+        };
+        if line.len() + item.len() > LINE_WIDTH {
+            text += &line;
+            text += "\n";
+            line.clear();
+        }
+        line += &item;
+    }
+    text += &line;
+    text += "\n";
+    text
+}
+
+fn parse_comment(comment: &str, pattern: &mut Pattern) {
+    let mut characters = comment.chars();
+    let kind = characters.next();
+    let content = characters.as_str().trim().to_string();
+    match kind {
+        Some('N') => pattern.name = Some(content),
+        Some('O') => pattern.author = Some(content),
+        Some('C' | 'c') => pattern.comments.push(content),
+        Some('r') => pattern.rule = Some(content),
+        // #P and #R offsets are meaningless without an absolute origin
+        _ => (),
+    }
+}
+
+fn parse_header(line: &str, line_number: usize, pattern: &mut Pattern) -> Result<(), ParseError> {
+    let mut width = None;
+    let mut height = None;
+    let mut offset = 0;
+    for part in line.split(',') {
+        let part_column = offset + 1;
+        offset += part.len() + 1;
+
+        let Some((key, value)) = part.split_once('=') else {
+            let column = part_column + part.len() - part.trim_start().len();
+            return Err(ParseError::new(
+                line_number,
+                column,
+                format!("expected \"key = value\", found \"{}\"", part.trim()),
+            ));
+        };
+        let value_column = part_column + key.len() + 1 + value.len() - value.trim_start().len();
+        let value = value.trim();
+        let parse_size = |value: &str| {
+            value.parse::<u64>().map_err(|_| {
+                ParseError::new(
+                    line_number,
+                    value_column,
+                    format!("expected a non-negative integer, found \"{value}\""),
+                )
+            })
+        };
+        match key.trim() {
+            "x" => width = Some(parse_size(value)?),
+            "y" => height = Some(parse_size(value)?),
+            // The rule comes last and can contain commas, `R5,C0,M1,S34..58,B34..45,NM`
+            "rule" => {
+                let value_start = part_column + key.len();
+                pattern.rule = Some(line[value_start..].trim().to_string());
+                break;
+            }
+            // Unknown keys are allowed by the format
+            _ => (),
+        }
+    }
+
+    let (Some(width), Some(height)) = (width, height) else {
+        return Err(ParseError::new(
+            line_number,
+            1,
+            "header must contain both x and y",
+        ));
+    };
+    pattern.width = width;
+    pattern.height = height;
+    Ok(())
+}
+
+// Position after a run, None if the run goes past the size in the header
+fn advance(position: i64, run: u64, size: u64) -> Option<i64> {
+    let end = position.checked_add(i64::try_from(run).ok()?)?;
+    (end as u64 <= size).then_some(end)
+}
+
+fn check_live_run(run: u64, y: i64, height: u64) -> Result<(), &'static str> {
+    if y as u64 >= height {
+        return Err("row goes past the height in the header");
+    }
+    if run > MAX_LIVE_RUN {
+        return Err("run of live cells is too long");
+    }
+    Ok(())
+}
+
+fn push_run(pattern: &mut Pattern, start: i64, end: i64, y: i64, state: u8) {
+    pattern.cells.extend((start..end).map(|x| (x, y, state)));
+}
+
+// '.' is 0, 'A'..'X' are 1..24, 'pA'..'pX' are 25..48, up to 'yO' which is 255
+fn state_from_letters(prefix: Option<char>, letter: char) -> Option<u8> {
+    let base = prefix.map_or(0, |prefix| (prefix as u32 - 'p' as u32 + 1) * 24);
+    u8::try_from(base + letter as u32 - 'A' as u32 + 1).ok()
+}
+
+fn state_to_letters(state: u8, multi_state: bool) -> String {
+    if !multi_state {
+        return if state == DEAD { "b" } else { "o" }.to_string();
+    }
+    if state == DEAD {
+        return ".".to_string();
+    }
+    let index = state as u32 - 1;
+    let letter = char::from_u32('A' as u32 + index % 24).unwrap();
+    match index / 24 {
+        0 => letter.to_string(),
+        prefix => format!(
+            "{}{letter}",
+            char::from_u32('p' as u32 + prefix - 1).unwrap()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_cells(pattern: &Pattern) -> Vec<(i64, i64, u8)> {
+        let mut cells = pattern.cells.clone();
+        cells.sort_by_key(|&(x, y, _)| (y, x));
+        cells
+    }
+
+    #[test]
+    fn parses_glider() {
+        let text = "#N Glider\n#O Richard K. Guy\n#C The smallest spaceship.\n\
+                    x = 3, y = 3, rule = B3/S23\nbob$2bo$3o!\n";
+        let pattern = parse(text).unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.author.as_deref(), Some("Richard K. Guy"));
+        assert_eq!(pattern.comments, ["The smallest spaceship."]);
+        assert_eq!(pattern.rule.as_deref(), Some("B3/S23"));
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(
+            sorted_cells(&pattern),
+            [(1, 0, 1), (2, 1, 1), (0, 2, 1), (1, 2, 1), (2, 2, 1)]
+        );
+    }
+
+    #[test]
+    fn parses_multi_state_letters_and_blank_rows() {
+        let pattern = parse("x = 4, y = 4\n.A2B$pAyO2$3.X!").unwrap();
+        assert_eq!(
+            sorted_cells(&pattern),
+            [
+                (1, 0, 1),
+                (2, 0, 2),
+                (3, 0, 2),
+                (0, 1, 25),
+                (1, 1, 255),
+                (3, 3, 24)
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        for text in [
+            "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n",
+            "x = 5, y = 6, rule = B3/S23\n5o2$bo2bo3$4bo!\n",
+            "x = 3, y = 2, rule = Test\n.BpA$C!\n",
+            "x = 2, y = 1, rule = R5,C0,M1,S34..58,B34..45,NM\n2o!\n",
+            "x = 2, y = 1, rule = B3/S23:K100*,50\n2o!\n",
+        ] {
+            let pattern = parse(text).unwrap();
+            assert_eq!(write(&pattern), text);
+        }
+    }
+
+    #[test]
+    fn wraps_long_lines() {
+        let cells = (0..200).step_by(2).map(|x| (x, 0, ALIVE)).collect();
+        let pattern = Pattern {
+            width: 199,
+            height: 1,
+            cells,
+            ..Default::default()
+        };
+        let text = write(&pattern);
+        assert!(text.lines().all(|line| line.len() <= LINE_WIDTH));
+        assert_eq!(sorted_cells(&parse(&text).unwrap()), pattern.cells);
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        let error = parse("#C comment\nx = 3, y = 3\nbo$2bo$3k!").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 9));
+
+        let error = parse("x = 3, y = three\nbo!").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 12));
+
+        let error = parse("x = 3, y\nbo!").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 8));
+
+        let error = parse("bo$2bo$3o!").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+
+        let error = parse("x = 3, y = 3\nbo$\n 12 bo!").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 2));
+
+        let error = parse("x = 3, y = 3\nbo$pbo!").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 4));
+
+        let error = parse("x = 3, y = 3\nyP!").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert_eq!(
+            error.to_string(),
+            "line 2, column 1: cell state is larger than 255"
+        );
+    }
+
+    #[test]
+    fn rejects_runs_past_the_header() {
+        let error = parse(
+            "x = 3, y = 1
+1000000000o!",
+        )
+        .unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert!(
+            parse(
+                "x = 3, y = 2
+2b2o!"
+            )
+            .is_err()
+        );
+        assert!(
+            parse(
+                "x = 3, y = 2
+o2$o!"
+            )
+            .is_err()
+        );
+        assert!(
+            parse(
+                "x = 3, y = 2
+99999999999999999999$!"
+            )
+            .is_err()
+        );
+
+        // Wide enough for the header but too many cells to store
+        let error = parse(
+            "x = 100000000, y = 1
+50000000o!",
+        )
+        .unwrap_err();
+        assert_eq!(error.message, "run of live cells is too long");
+        assert!(
+            parse(
+                "x = 100000000, y = 1
+50000000bo!"
+            )
+            .is_ok()
+        );
+    }
+}
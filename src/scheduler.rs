@@ -0,0 +1,258 @@
+// Fixed-timestep clock of the simulation, generations per second don't depend on the frame rate
+
+use std::time::{Duration, Instant};
+
+// Timed speed that one generation per frame is taken as once the speed is changed
+const FRAME_RATE_GENERATIONS_PER_SECOND: f64 = 60.;
+// Slowest timed speed
+const MIN_GENERATIONS_PER_SECOND: f64 = 0.25;
+// Fastest timed speed, speeding up further runs at max speed
+const MAX_GENERATIONS_PER_SECOND: f64 = 1920.;
+// A slow engine falls behind instead of stepping ever more generations per frame
+const MAX_STEPS_PER_FRAME: u64 = 64;
+// Time of a frame spent stepping at max speed
+const MAX_SPEED_FRAME_TIME: Duration = Duration::from_millis(12);
+// GPU steps are only queued, they don't show up in the frame time
+const MAX_SPEED_STEPS_PER_FRAME: u64 = 256;
+
+/// Generations a frame should run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSteps {
+    Generations(u64),
+    // Max speed, steps until the deadline, at most `max_generations`
+    Until {
+        deadline: Instant,
+        max_generations: u64,
+    },
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    paused: bool,
+    // One generation per frame if not set
+    generations_per_second: Option<f64>,
+    // Runs as many generations per frame as fit, `generations_per_second` is kept for slowing down
+    max_speed: bool,
+    // Time the simulation has been stepped up to
+    clock: Instant,
+    // Generations left to step, the simulation pauses once they have run
+    queued_generations: Option<u64>,
+}
+
+impl Scheduler {
+    pub fn new(paused: bool, generations_per_second: Option<f64>, now: Instant) -> Self {
+        Self {
+            paused,
+            generations_per_second: generations_per_second.map(|generations_per_second| {
+                generations_per_second.clamp(MIN_GENERATIONS_PER_SECOND, MAX_GENERATIONS_PER_SECOND)
+            }),
+            max_speed: generations_per_second.is_some_and(|generations_per_second| {
+                generations_per_second > MAX_GENERATIONS_PER_SECOND
+            }),
+            clock: now,
+            queued_generations: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Generations are due from `now` on, none pile up while paused
+    ///
+    /// Queued generations are dropped.
+    pub fn set_paused(&mut self, paused: bool, now: Instant) {
+        self.paused = paused;
+        self.clock = now;
+        self.queued_generations = None;
+    }
+
+    /// Runs `generations` over the next frames and pauses after them, see `frame_steps`
+    pub fn queue_generations(&mut self, generations: u64, now: Instant) {
+        self.set_paused(false, now);
+        self.queued_generations = Some(generations);
+    }
+
+    pub fn queued_generations(&self) -> Option<u64> {
+        self.queued_generations
+    }
+
+    /// Timed speed, None while one generation runs per frame
+    pub fn generations_per_second(&self) -> Option<f64> {
+        self.generations_per_second
+    }
+
+    pub fn is_max_speed(&self) -> bool {
+        self.max_speed
+    }
+
+    /// Doubles the speed, past the fastest timed speed runs at max speed
+    ///
+    /// One generation per frame becomes a timed speed.
+    pub fn speed_up(&mut self) {
+        let generations_per_second = self
+            .generations_per_second
+            .unwrap_or(FRAME_RATE_GENERATIONS_PER_SECOND);
+        if generations_per_second >= MAX_GENERATIONS_PER_SECOND {
+            self.max_speed = true;
+        } else {
+            self.generations_per_second =
+                Some((generations_per_second * 2.).min(MAX_GENERATIONS_PER_SECOND));
+        }
+    }
+
+    /// Halves the speed, max speed drops back to the timed speed
+    pub fn slow_down(&mut self) {
+        let generations_per_second = self
+            .generations_per_second
+            .unwrap_or(FRAME_RATE_GENERATIONS_PER_SECOND);
+        if self.max_speed {
+            self.max_speed = false;
+        } else {
+            self.generations_per_second =
+                Some((generations_per_second / 2.).max(MIN_GENERATIONS_PER_SECOND));
+        }
+    }
+
+    pub fn toggle_max_speed(&mut self) {
+        self.max_speed = !self.max_speed;
+    }
+
+    /// Generations due since the last frame, the clock advances by their duration
+    ///
+    /// Queued generations run as fast as the max speed step limit allows, then pause.
+    pub fn frame_steps(&mut self, now: Instant) -> FrameSteps {
+        if self.paused {
+            self.clock = now;
+            return FrameSteps::Generations(0);
+        }
+        if let Some(queued_generations) = self.queued_generations {
+            let generations = queued_generations.min(MAX_SPEED_STEPS_PER_FRAME);
+            if generations == queued_generations {
+                self.set_paused(true, now);
+            } else {
+                self.queued_generations = Some(queued_generations - generations);
+            }
+            return FrameSteps::Generations(generations);
+        }
+        if self.max_speed {
+            self.clock = now;
+            return FrameSteps::Until {
+                deadline: now + MAX_SPEED_FRAME_TIME,
+                max_generations: MAX_SPEED_STEPS_PER_FRAME,
+            };
+        }
+
+        let Some(generations_per_second) = self.generations_per_second else {
+            self.clock = now;
+            return FrameSteps::Generations(1);
+        };
+        let step_duration = Duration::from_secs_f64(1. / generations_per_second);
+        let due = (now.saturating_duration_since(self.clock).as_secs_f64() * generations_per_second)
+            as u64;
+        if due > MAX_STEPS_PER_FRAME {
+            // Dropped generations are not made up later
+            self.clock = now;
+            return FrameSteps::Generations(MAX_STEPS_PER_FRAME);
+        }
+        self.clock += step_duration * due as u32;
+        FrameSteps::Generations(due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_at_a_fixed_rate() {
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new(false, Some(10.), start);
+        let at = |milliseconds| start + Duration::from_millis(milliseconds);
+
+        assert_eq!(scheduler.frame_steps(at(50)), FrameSteps::Generations(0));
+        assert_eq!(scheduler.frame_steps(at(120)), FrameSteps::Generations(1));
+        // The remaining 20 ms count towards the next generation
+        assert_eq!(scheduler.frame_steps(at(200)), FrameSteps::Generations(1));
+        assert_eq!(scheduler.frame_steps(at(516)), FrameSteps::Generations(3));
+        assert_eq!(
+            scheduler.frame_steps(at(60_000)),
+            FrameSteps::Generations(MAX_STEPS_PER_FRAME)
+        );
+
+        // Nothing piles up while paused
+        scheduler.set_paused(true, at(60_000));
+        assert_eq!(
+            scheduler.frame_steps(at(70_000)),
+            FrameSteps::Generations(0)
+        );
+        scheduler.set_paused(false, at(70_000));
+        assert_eq!(
+            scheduler.frame_steps(at(70_100)),
+            FrameSteps::Generations(1)
+        );
+    }
+
+    #[test]
+    fn changes_speed() {
+        let now = Instant::now();
+        let mut scheduler = Scheduler::new(false, Some(30.), now);
+        scheduler.speed_up();
+        assert_eq!(scheduler.generations_per_second(), Some(60.));
+        for _ in 0..5 {
+            scheduler.speed_up();
+        }
+        assert_eq!(
+            scheduler.generations_per_second(),
+            Some(MAX_GENERATIONS_PER_SECOND)
+        );
+        assert!(!scheduler.is_max_speed());
+        scheduler.speed_up();
+        assert!(scheduler.is_max_speed());
+        assert!(matches!(
+            scheduler.frame_steps(now),
+            FrameSteps::Until { .. }
+        ));
+
+        scheduler.slow_down();
+        assert!(!scheduler.is_max_speed());
+        for _ in 0..20 {
+            scheduler.slow_down();
+        }
+        assert_eq!(
+            scheduler.generations_per_second(),
+            Some(MIN_GENERATIONS_PER_SECOND)
+        );
+    }
+
+    #[test]
+    fn steps_once_per_frame_without_a_speed() {
+        let now = Instant::now();
+        let mut scheduler = Scheduler::new(false, None, now);
+        assert_eq!(scheduler.frame_steps(now), FrameSteps::Generations(1));
+        assert_eq!(
+            scheduler.frame_steps(now + Duration::from_secs(1)),
+            FrameSteps::Generations(1)
+        );
+        scheduler.speed_up();
+        assert_eq!(
+            scheduler.generations_per_second(),
+            Some(FRAME_RATE_GENERATIONS_PER_SECOND * 2.)
+        );
+    }
+
+    #[test]
+    fn spreads_queued_generations_over_frames() {
+        let now = Instant::now();
+        let mut scheduler = Scheduler::new(true, None, now);
+        scheduler.queue_generations(MAX_SPEED_STEPS_PER_FRAME + 10, now);
+        assert!(!scheduler.is_paused());
+        assert_eq!(
+            scheduler.frame_steps(now),
+            FrameSteps::Generations(MAX_SPEED_STEPS_PER_FRAME)
+        );
+        assert_eq!(scheduler.frame_steps(now), FrameSteps::Generations(10));
+        assert!(scheduler.is_paused());
+        assert_eq!(scheduler.frame_steps(now), FrameSteps::Generations(0));
+    }
+}
@@ -0,0 +1,43 @@
+// Draws the whole board as one quad, reading cell states straight from the board texture
+
+struct VertexIn {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+struct PushConstants {
+    mvp_matrix: mat4x4<f32>,
+}
+
+var<push_constant> push_constants: PushConstants;
+
+// `palette` is binding 0, see palette.wgsl
+@group(0) @binding(1) var board: texture_2d<u32>;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+struct FragmentIn {
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(vertex_in: VertexIn) -> VertexOut {
+    var out: VertexOut;
+    out.position = push_constants.mvp_matrix * vec4(vertex_in.position, 0.0, 1.0);
+    out.uv = vertex_in.uv;
+    return out;
+}
+
+@fragment
+fn fs_main(fragment_in: FragmentIn) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(board));
+    let cell = clamp(vec2<i32>(floor(fragment_in.uv * vec2<f32>(size))), vec2(0), size - 1);
+    let state = textureLoad(board, cell, 0).r;
+    if state == 0u {
+        discard;
+    }
+    return state_color(state);
+}
@@ -0,0 +1,69 @@
+// One time step of Lenia or SmoothLife, the board wraps around at the edges
+
+const LENIA = 0u;
+const SMOOTH_LIFE = 1u;
+
+// Parameters of both automata, see `ContinuousRule` for their meaning
+struct Rule {
+    kind: u32,
+    radius: i32,
+    dt: f32,
+    mu: f32,
+    sigma: f32,
+    alpha_n: f32,
+    birth: vec2<f32>,
+    death: vec2<f32>,
+    alpha_m: f32,
+}
+
+@group(0) @binding(0) var current_board: texture_2d<f32>;
+@group(0) @binding(1) var next_board: texture_storage_2d<r32float, write>;
+@group(0) @binding(2) var<uniform> rule: Rule;
+// Row by row from (-radius, -radius), Lenia uses x, SmoothLife the annulus in x and the disk in y
+@group(0) @binding(3) var<storage, read> kernel: array<vec2<f32>>;
+
+fn smooth_step(x: f32, edge: f32, alpha: f32) -> f32 {
+    return 1.0 / (1.0 + exp(-(x - edge) * 4.0 / alpha));
+}
+
+// Rafler's s(n, m)
+fn smooth_life_transition(annulus: f32, disk: f32) -> f32 {
+    let live = smooth_step(disk, 0.5, rule.alpha_m);
+    let low = mix(rule.birth.x, rule.death.x, live);
+    let high = mix(rule.birth.y, rule.death.y, live);
+    return smooth_step(annulus, low, rule.alpha_n) * (1.0 - smooth_step(annulus, high, rule.alpha_n));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(current_board));
+    let position = vec2<i32>(id.xy);
+    if any(position >= size) {
+        return;
+    }
+
+    var potential = vec2(0.0);
+    var index = 0u;
+    for (var dy = -rule.radius; dy <= rule.radius; dy++) {
+        for (var dx = -rule.radius; dx <= rule.radius; dx++) {
+            let weights = kernel[index];
+            index++;
+            if all(weights == vec2(0.0)) {
+                continue;
+            }
+            let neighbour = (position + vec2(dx, dy) + size) % size;
+            potential += weights * textureLoad(current_board, neighbour, 0).r;
+        }
+    }
+
+    let value = textureLoad(current_board, position, 0).r;
+    var next_value: f32;
+    if rule.kind == LENIA {
+        let distance = potential.x - rule.mu;
+        let growth = 2.0 * exp(-distance * distance / (2.0 * rule.sigma * rule.sigma)) - 1.0;
+        next_value = value + rule.dt * growth;
+    } else {
+        next_value = value + rule.dt * (smooth_life_transition(potential.x, potential.y) - value);
+    }
+    textureStore(next_board, position, vec4(clamp(next_value, 0.0, 1.0), 0.0, 0.0, 0.0));
+}
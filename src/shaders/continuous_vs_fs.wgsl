@@ -0,0 +1,53 @@
+// Draws the board of a continuous automaton as one quad, cell values go through a colormap
+
+struct VertexIn {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+struct PushConstants {
+    mvp_matrix: mat4x4<f32>,
+}
+
+var<push_constant> push_constants: PushConstants;
+
+// Evenly spaced stops from 0 to 1, black to yellow through blue and red
+const COLORMAP = array(
+    vec3(0.0, 0.0, 0.0),
+    vec3(0.1, 0.05, 0.45),
+    vec3(0.6, 0.1, 0.5),
+    vec3(0.95, 0.4, 0.15),
+    vec3(1.0, 0.95, 0.5),
+);
+
+fn colormap(value: f32) -> vec3<f32> {
+    let position = clamp(value, 0.0, 1.0) * 4.0;
+    let stop = min(u32(position), 3u);
+    return mix(COLORMAP[stop], COLORMAP[stop + 1u], position - f32(stop));
+}
+
+@group(0) @binding(0) var board: texture_2d<f32>;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+struct FragmentIn {
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(vertex_in: VertexIn) -> VertexOut {
+    var out: VertexOut;
+    out.position = push_constants.mvp_matrix * vec4(vertex_in.position, 0.0, 1.0);
+    out.uv = vertex_in.uv;
+    return out;
+}
+
+@fragment
+fn fs_main(fragment_in: FragmentIn) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(board));
+    let cell = clamp(vec2<i32>(floor(fragment_in.uv * vec2<f32>(size))), vec2(0), size - 1);
+    return vec4(colormap(textureLoad(board, cell, 0).r), 1.0);
+}
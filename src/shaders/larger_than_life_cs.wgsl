@@ -0,0 +1,101 @@
+// One generation of a Larger than Life rule in two passes
+// `row_sums_main` writes prefix sums of every row, `cs_main` adds up 2R + 1 row segments per cell
+
+// Counts are of cells in state 1 within the range, bounds are inclusive
+struct Rule {
+    range: i32,
+    // The cell counts itself
+    middle: u32,
+    // Diamond instead of square
+    von_neumann: u32,
+    states: u32,
+    survival: vec2<u32>,
+    birth: vec2<u32>,
+    // The topology wraps positions off the board, `wrap` has the details
+    topology: u32,
+    twisted_columns: u32,
+}
+
+@group(0) @binding(0) var current_board: texture_2d<u32>;
+@group(0) @binding(1) var next_board: texture_storage_2d<r32uint, write>;
+@group(0) @binding(2) var<uniform> rule: Rule;
+// Live cells of the row up to and including the column
+@group(0) @binding(3) var row_sums_out: texture_storage_2d<r32uint, write>;
+@group(0) @binding(4) var row_sums: texture_2d<u32>;
+
+@compute @workgroup_size(64)
+fn row_sums_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(current_board));
+    let y = i32(id.x);
+    if y >= size.y {
+        return;
+    }
+
+    var sum = 0u;
+    for (var x = 0; x < size.x; x++) {
+        sum += select(0u, 1u, textureLoad(current_board, vec2(x, y), 0).r == 1u);
+        textureStore(row_sums_out, vec2(x, y), vec4(sum, 0u, 0u, 0u));
+    }
+}
+
+// Live cells of the row between the columns, cells outside of the board are dead
+fn row_segment_sum(y: i32, left: i32, right: i32, size: vec2<i32>) -> u32 {
+    let clamped_left = max(left, 0);
+    let clamped_right = min(right, size.x - 1);
+    if y < 0 || y >= size.y || clamped_left > clamped_right {
+        return 0u;
+    }
+    var sum = textureLoad(row_sums, vec2(clamped_right, y), 0).r;
+    if clamped_left > 0 {
+        sum -= textureLoad(row_sums, vec2(clamped_left - 1, y), 0).r;
+    }
+    return sum;
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(current_board));
+    let position = vec2<i32>(id.xy);
+    if any(position >= size) {
+        return;
+    }
+
+    var count = 0u;
+    for (var dy = -rule.range; dy <= rule.range; dy++) {
+        let half_width = select(rule.range, rule.range - abs(dy), rule.von_neumann != 0u);
+        let left = position.x - half_width;
+        let right = position.x + half_width;
+        let y = position.y + dy;
+        if rule.topology == PLANE || (y >= 0 && y < size.y && left >= 0 && right < size.x) {
+            count += row_segment_sum(y, left, right, size);
+            continue;
+        }
+        // Wrapped cells one at a time, rows don't stay rows on every topology
+        for (var x = left; x <= right; x++) {
+            let wrapped = wrap(vec2(x, y), size);
+            if all(wrapped >= vec2(0)) && all(wrapped < size) {
+                count += select(0u, 1u, textureLoad(current_board, wrapped, 0).r == 1u);
+            }
+        }
+    }
+
+    let current_state = textureLoad(current_board, position, 0).r;
+    if current_state == 1u && rule.middle == 0u {
+        count -= 1u;
+    }
+    var next_state = 0u;
+    if current_state == 0u {
+        if count >= rule.birth.x && count <= rule.birth.y {
+            next_state = 1u;
+        }
+    } else if current_state == 1u {
+        if count >= rule.survival.x && count <= rule.survival.y {
+            next_state = 1u;
+        } else if rule.states > 2u {
+            next_state = 2u;
+        }
+    } else if current_state + 1u < rule.states {
+        next_state = current_state + 1u;
+    }
+    textureStore(next_board, position, vec4(next_state, 0u, 0u, 0u));
+}
@@ -0,0 +1,63 @@
+// One generation of an isotropic rule, reads the current board and writes the next one
+
+// Bit `neighbours | alive << 8` is set if the cell is alive in the next generation
+// Cells that don't survive decay through states 2..states, only state 1 is alive
+// The topology wraps positions off the board, `wrap` has the details
+struct Rule {
+    table: array<vec4<u32>, 4>,
+    states: u32,
+    topology: u32,
+    twisted_columns: u32,
+}
+
+// Clockwise from north, bit i of the neighbourhood is neighbour i
+const NEIGHBOURS = array(
+    vec2(0, -1), vec2(1, -1), vec2(1, 0), vec2(1, 1),
+    vec2(0, 1), vec2(-1, 1), vec2(-1, 0), vec2(-1, -1),
+);
+
+@group(0) @binding(0) var current_board: texture_2d<u32>;
+@group(0) @binding(1) var next_board: texture_storage_2d<r32uint, write>;
+@group(0) @binding(2) var<uniform> rule: Rule;
+
+// Cells off the board are dead unless the topology wraps them around
+fn state(position: vec2<i32>, size: vec2<i32>) -> u32 {
+    let wrapped = wrap(position, size);
+    if any(wrapped < vec2(0)) || any(wrapped >= size) {
+        return 0u;
+    }
+    return textureLoad(current_board, wrapped, 0).r;
+}
+
+fn is_alive(position: vec2<i32>, size: vec2<i32>) -> u32 {
+    return select(0u, 1u, state(position, size) == 1u);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(current_board));
+    let position = vec2<i32>(id.xy);
+    if any(position >= size) {
+        return;
+    }
+
+    var neighbours = 0u;
+    for (var i = 0; i < 8; i++) {
+        neighbours |= is_alive(position + NEIGHBOURS[i], size) << u32(i);
+    }
+
+    let current_state = state(position, size);
+    var next_state = 0u;
+    if current_state <= 1u {
+        let index = neighbours | current_state << 8u;
+        let word = index / 32u;
+        if (rule.table[word / 4u][word % 4u] >> (index % 32u) & 1u) != 0u {
+            next_state = 1u;
+        } else if current_state == 1u && rule.states > 2u {
+            next_state = 2u;
+        }
+    } else if current_state + 1u < rule.states {
+        next_state = current_state + 1u;
+    }
+    textureStore(next_board, position, vec4(next_state, 0u, 0u, 0u));
+}
@@ -0,0 +1,8 @@
+// Colors of the cell states, put in front of the shaders that draw cells
+
+// Color of every state of the rule
+@group(0) @binding(0) var<uniform> palette: array<vec4<f32>, 256>;
+
+fn state_color(state: u32) -> vec4<f32> {
+    return palette[min(state, 255u)];
+}
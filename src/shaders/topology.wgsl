@@ -0,0 +1,51 @@
+// Topologies of bounded grids, put in front of the compute shaders of counting rules
+// `wrap` reads `rule.topology` and `rule.twisted_columns` of the shader it is put in front of
+
+const PLANE = 0u;
+const TORUS = 1u;
+const KLEIN_BOTTLE = 2u;
+const CROSS_SURFACE = 3u;
+const SPHERE = 4u;
+
+// Floor division, WGSL rounds towards zero
+fn div_floor(a: i32, b: i32) -> i32 {
+    return select(a / b, (a + 1) / b - 1, a < 0);
+}
+
+// Cell of the board that the position stands for, like `Topology::wrap`
+// Positions that stay off the board are always dead
+fn wrap(position: vec2<i32>, size: vec2<i32>) -> vec2<i32> {
+    let inside = all(position >= vec2(0)) && all(position < size);
+    if inside || rule.topology == PLANE {
+        return position;
+    }
+    if rule.topology == SPHERE {
+        // Rows above the board are the columns left of it, rows below are right of it
+        let x = position.x;
+        let y = position.y;
+        if x >= 0 && x < size.x {
+            return select(vec2(size.x - 1 - (y - size.y), x), vec2(-y - 1, x), y < 0);
+        }
+        if y >= 0 && y < size.y {
+            return select(vec2(y, size.y - 1 - (x - size.x)), vec2(y, -x - 1), x < 0);
+        }
+        return vec2(-1);
+    }
+
+    // Times the left or right and the top or bottom edges are crossed
+    let crossings = vec2(div_floor(position.x, size.x), div_floor(position.y, size.y));
+    var wrapped = position - crossings * size;
+    let mirrored = size - 1 - wrapped;
+    let odd = (crossings & vec2(1)) != vec2(0);
+    let twisted_columns = rule.topology == CROSS_SURFACE
+        || (rule.topology == KLEIN_BOTTLE && rule.twisted_columns != 0u);
+    let twisted_rows = rule.topology == CROSS_SURFACE
+        || (rule.topology == KLEIN_BOTTLE && rule.twisted_columns == 0u);
+    if twisted_columns && odd.y {
+        wrapped.x = mirrored.x;
+    }
+    if twisted_rows && odd.x {
+        wrapped.y = mirrored.y;
+    }
+    return wrapped;
+}
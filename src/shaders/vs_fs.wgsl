@@ -1,7 +1,12 @@
 struct VertexIn {
     @location(0) position: vec2<f32>,
     @location(1) uv: vec2<f32>,
-    @location(2) color: vec3<f32>,
+}
+
+struct InstanceIn {
+    // Cell position in cells
+    @location(2) position: vec2<f32>,
+    @location(3) state: u32,
 }
 
 struct PushConstants {
@@ -13,26 +18,27 @@ var<push_constant> push_constants: PushConstants;
 struct VertexOut {
     @builtin(position) position: vec4<f32>,
     @location(0) uv: vec2<f32>,
-    @location(1) color: vec3<f32>,
+    @location(1) @interpolate(flat) state: u32,
 }
 
 struct FragmentIn {
     @location(0) uv: vec2<f32>,
-    @location(1) color: vec3<f32>,
+    @location(1) @interpolate(flat) state: u32,
 }
 
 @vertex
-fn vs_main(vertex_in: VertexIn) -> VertexOut {
-    var out_position = push_constants.mvp_matrix * vec4(vertex_in.position, 0.0, 1.0);
+fn vs_main(vertex_in: VertexIn, instance_in: InstanceIn) -> VertexOut {
+    let cell_position = vertex_in.position + instance_in.position;
+    var out_position = push_constants.mvp_matrix * vec4(cell_position, 0.0, 1.0);
 
     var out: VertexOut;
     out.position = out_position;
     out.uv = vertex_in.uv;
-    out.color = vertex_in.color;
+    out.state = instance_in.state;
     return out;
 }
 
 @fragment
 fn fs_main(fragment_in: FragmentIn) -> @location(0) vec4<f32> {
-    return vec4(fragment_in.color, 1.0);
+    return state_color(fragment_in.state);
 }
@@ -0,0 +1,544 @@
+// Game of Life simulation
+// Pure CPU code, doesn't depend on the graphics context
+
+pub mod continuous;
+pub mod hashlife;
+pub mod packed;
+pub mod rule;
+pub mod sparse;
+
+use crate::simulation::rule::larger_than_life::LargerThanLife;
+use crate::simulation::rule::table::RuleTable;
+use crate::simulation::rule::{NEIGHBOURS, Neighbourhood, Rule};
+
+pub const DEAD: u8 = 0;
+pub const ALIVE: u8 = 1;
+
+#[derive(Debug, Clone)]
+pub struct Universe {
+    width: usize,
+    height: usize,
+    cells: Vec<u8>,
+    next_cells: Vec<u8>,
+    generation: u64,
+    rule: Rule,
+}
+
+impl Universe {
+    /// Bounded universe, cells outside of it are always dead
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![DEAD; width * height],
+            next_cells: vec![DEAD; width * height],
+            generation: 0,
+            rule: Rule::LIFE,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// The size of the topology of the rule must be the size of the universe
+    pub fn set_rule(&mut self, rule: Rule) {
+        if let Some(topology) = rule.topology() {
+            assert_eq!(
+                (topology.width() as usize, topology.height() as usize),
+                (self.width, self.height),
+                "Topology doesn't match the universe size"
+            );
+        }
+        self.rule = rule;
+    }
+
+    /// Row-major cell states
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    pub fn population(&self) -> usize {
+        self.cells.iter().filter(|&&state| state != DEAD).count()
+    }
+
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        x >= 0 && y >= 0 && (x as u64) < self.width as u64 && (y as u64) < self.height as u64
+    }
+
+    /// Cells outside of the universe are dead unless the topology of the rule wraps them around
+    pub fn get_cell(&self, x: i64, y: i64) -> u8 {
+        if self.contains(x, y) {
+            return self.cells[y as usize * self.width + x as usize];
+        }
+        match self
+            .rule
+            .topology()
+            .and_then(|topology| topology.wrap(x, y))
+        {
+            Some((x, y)) => self.cells[y as usize * self.width + x as usize],
+            None => DEAD,
+        }
+    }
+
+    /// Cells outside of the universe are ignored
+    pub fn set_cell(&mut self, x: i64, y: i64, state: u8) {
+        if !self.contains(x, y) {
+            return;
+        }
+        self.cells[y as usize * self.width + x as usize] = state;
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(DEAD);
+    }
+
+    /// Makes about half of the cells in the rectangle alive, the same seed gives the same soup
+    pub fn fill_random(&mut self, x: i64, y: i64, width: i64, height: i64, seed: u64) {
+        let mut random = xorshift(seed);
+        for cell_y in y..y + height {
+            for cell_x in x..x + width {
+                if random.next().unwrap().is_multiple_of(2) {
+                    self.set_cell(cell_x, cell_y, ALIVE);
+                }
+            }
+        }
+    }
+
+    /// Iterator over (x, y, state) of all non-dead cells
+    pub fn live_cells(&self) -> impl Iterator<Item = (i64, i64, u8)> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| **state != DEAD)
+            .map(|(index, state)| {
+                (
+                    (index % self.width) as i64,
+                    (index / self.width) as i64,
+                    *state,
+                )
+            })
+    }
+
+    /// Advances the universe by one generation
+    pub fn step(&mut self) {
+        if let Some(&larger_than_life) = self.rule.larger_than_life() {
+            self.step_in_range(&larger_than_life);
+            return;
+        }
+        if self.rule.rule_table().is_some() {
+            // Shares the table, the rule can't be borrowed while the cells change
+            let rule = self.rule.clone();
+            self.step_by_table(rule.rule_table().expect("rule without a table"));
+            return;
+        }
+
+        let width = self.width as i64;
+        let height = self.height as i64;
+        for y in 0..height {
+            for x in 0..width {
+                let mut neighbours = 0;
+                for (bit, (dx, dy)) in NEIGHBOURS.into_iter().enumerate() {
+                    // Decaying cells of Generations rules are not live neighbours
+                    if self.get_cell(x + dx, y + dy) == ALIVE {
+                        neighbours |= 1 << bit;
+                    }
+                }
+                self.next_cells[(y * width + x) as usize] =
+                    self.rule.next_state(self.get_cell(x, y), neighbours);
+            }
+        }
+        std::mem::swap(&mut self.cells, &mut self.next_cells);
+        self.generation += 1;
+    }
+
+    // Every cell looks up the states of its neighbours in the transitions
+    fn step_by_table(&mut self, rule_table: &RuleTable) {
+        let offsets: Vec<(i64, i64)> = NEIGHBOURS
+            .into_iter()
+            .enumerate()
+            .filter(|&(bit, _)| rule_table.neighbourhood().contains(bit))
+            .map(|(_, offset)| offset)
+            .collect();
+        let mut neighbours = vec![DEAD; offsets.len()];
+        for y in 0..self.height as i64 {
+            for x in 0..self.width as i64 {
+                for (neighbour, (dx, dy)) in neighbours.iter_mut().zip(&offsets) {
+                    *neighbour = self.get_cell(x + dx, y + dy);
+                }
+                self.next_cells[y as usize * self.width + x as usize] =
+                    rule_table.next_state(self.get_cell(x, y), &neighbours);
+            }
+        }
+        std::mem::swap(&mut self.cells, &mut self.next_cells);
+        self.generation += 1;
+    }
+
+    // Counts within the range come from a summed-area table, at most 2R + 1 lookups per count
+    fn step_in_range(&mut self, larger_than_life: &LargerThanLife) {
+        let range = larger_than_life.range() as i64;
+        // Wrapped cells within the range around the universe are part of the table
+        let margin = if self.rule.topology().is_some() {
+            range
+        } else {
+            0
+        };
+        let table_width = (self.width as i64 + 2 * margin) as usize;
+        let table_height = (self.height as i64 + 2 * margin) as usize;
+        // sums[(y + 1) * (table_width + 1) + x + 1] is the number of live cells in rows 0..=y,
+        // columns 0..=x of the table, which starts at (-margin, -margin)
+        // Wrapping arithmetic keeps differences exact even if the total overflows
+        let mut sums = vec![0u32; (table_width + 1) * (table_height + 1)];
+        for y in 0..table_height {
+            let mut row_sum = 0u32;
+            for x in 0..table_width {
+                let state = self.get_cell(x as i64 - margin, y as i64 - margin);
+                row_sum += (state == ALIVE) as u32;
+                sums[(y + 1) * (table_width + 1) + x + 1] =
+                    sums[y * (table_width + 1) + x + 1].wrapping_add(row_sum);
+            }
+        }
+        // Live cells in the rectangle, clamped to the table
+        let rectangle_sum = |left: i64, top: i64, right: i64, bottom: i64| {
+            let left = (left + margin).max(0) as usize;
+            let top = (top + margin).max(0) as usize;
+            let right = (right + margin + 1).min(table_width as i64);
+            let bottom = (bottom + margin + 1).min(table_height as i64);
+            if right <= left as i64 || bottom <= top as i64 {
+                return 0;
+            }
+            let (right, bottom) = (right as usize, bottom as usize);
+            sums[bottom * (table_width + 1) + right]
+                .wrapping_sub(sums[top * (table_width + 1) + right])
+                .wrapping_sub(sums[bottom * (table_width + 1) + left])
+                .wrapping_add(sums[top * (table_width + 1) + left])
+        };
+
+        let (width, height) = (self.width, self.height);
+        let von_neumann = self.rule.neighbourhood() == Neighbourhood::VonNeumann;
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let count = if von_neumann {
+                    (-range..=range)
+                        .map(|dy| {
+                            let half_width = range - dy.abs();
+                            rectangle_sum(x - half_width, y + dy, x + half_width, y + dy)
+                        })
+                        .sum()
+                } else {
+                    rectangle_sum(x - range, y - range, x + range, y + range)
+                };
+                let index = y as usize * width + x as usize;
+                self.next_cells[index] = self.rule.next_state_in_range(self.cells[index], count);
+            }
+        }
+        std::mem::swap(&mut self.cells, &mut self.next_cells);
+        self.generation += 1;
+    }
+}
+
+// Xorshift, the same seed gives the same numbers
+pub(crate) fn xorshift(seed: u64) -> impl Iterator<Item = u64> {
+    // The state must not be zero
+    let mut state = seed.max(1);
+    std::iter::repeat_with(move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    })
+}
+
+#[cfg(test)]
+pub(crate) fn sorted_live_cells(universe: &Universe) -> Vec<(i64, i64)> {
+    let mut cells: Vec<_> = universe.live_cells().map(|(x, y, _)| (x, y)).collect();
+    cells.sort();
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn universe_with(width: usize, height: usize, cells: &[(i64, i64)]) -> Universe {
+        let mut universe = Universe::new(width, height);
+        for &(x, y) in cells {
+            universe.set_cell(x, y, ALIVE);
+        }
+        universe
+    }
+
+    #[test]
+    fn blinker_oscillates() {
+        let horizontal = [(1, 2), (2, 2), (3, 2)];
+        let vertical = [(2, 1), (2, 2), (2, 3)];
+        let mut universe = universe_with(5, 5, &horizontal);
+
+        universe.step();
+        assert_eq!(sorted_live_cells(&universe), vertical);
+        universe.step();
+        assert_eq!(sorted_live_cells(&universe), horizontal);
+        assert_eq!(universe.generation(), 2);
+    }
+
+    #[test]
+    fn block_is_still_life() {
+        let block = [(1, 1), (1, 2), (2, 1), (2, 2)];
+        let mut universe = universe_with(4, 4, &block);
+
+        for _ in 0..4 {
+            universe.step();
+            assert_eq!(sorted_live_cells(&universe), block);
+        }
+    }
+
+    #[test]
+    fn glider_moves_diagonally() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut universe = universe_with(16, 16, &glider);
+
+        for _ in 0..4 {
+            universe.step();
+        }
+        let mut moved: Vec<_> = glider.iter().map(|&(x, y)| (x + 1, y + 1)).collect();
+        moved.sort();
+        assert_eq!(sorted_live_cells(&universe), moved);
+        assert_eq!(universe.population(), 5);
+    }
+
+    #[test]
+    fn glider_returns_around_a_torus() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut universe = universe_with(8, 6, &glider);
+        universe.set_rule("B3/S23:T8,6".parse().unwrap());
+
+        // 4 generations per cell, 24 cross both pairs of edges back to the start
+        for _ in 0..24 {
+            universe.step();
+            assert_eq!(universe.population(), 5);
+        }
+        let mut moved: Vec<_> = glider.iter().map(|&(x, y)| ((x + 6) % 8, y)).collect();
+        moved.sort();
+        assert_eq!(sorted_live_cells(&universe), moved);
+    }
+
+    #[test]
+    fn hensel_rules_tell_neighbourhoods_apart() {
+        // Block cells see 3a, blinker ends 1e and its middle 2i
+        let block = [(1, 1), (2, 1), (1, 2), (2, 2)];
+        let mut universe = universe_with(4, 4, &block);
+        universe.set_rule("B3/S23-a".parse().unwrap());
+        universe.step();
+        assert_eq!(universe.population(), 0);
+
+        let mut universe = universe_with(4, 4, &block);
+        universe.set_rule("B3/S2-i3".parse().unwrap());
+        universe.step();
+        assert_eq!(
+            sorted_live_cells(&universe),
+            [(1, 1), (1, 2), (2, 1), (2, 2)]
+        );
+
+        // tlife, the middle of the blinker dies and only the 3i births remain
+        let mut universe = universe_with(5, 5, &[(1, 2), (2, 2), (3, 2)]);
+        universe.set_rule("B3/S2-i34q".parse().unwrap());
+        universe.step();
+        assert_eq!(sorted_live_cells(&universe), [(2, 1), (2, 3)]);
+
+        // Only 2a births, a domino moves up and down
+        let mut universe = universe_with(6, 6, &[(2, 2), (3, 2)]);
+        universe.set_rule("B2a/S".parse().unwrap());
+        universe.step();
+        assert_eq!(
+            sorted_live_cells(&universe),
+            [(2, 1), (2, 3), (3, 1), (3, 3)]
+        );
+    }
+
+    #[test]
+    fn generations_cells_decay() {
+        // Brian's Brain, live cells always start decaying and only they count as neighbours
+        let mut universe = universe_with(6, 6, &[(2, 2), (3, 2)]);
+        universe.set_rule("B2/S/C3".parse().unwrap());
+        universe.step();
+        let cells: Vec<_> = universe.live_cells().collect();
+        assert_eq!(
+            cells,
+            [
+                (2, 1, ALIVE),
+                (3, 1, ALIVE),
+                (2, 2, 2),
+                (3, 2, 2),
+                (2, 3, ALIVE),
+                (3, 3, ALIVE)
+            ]
+        );
+        universe.step();
+        assert_eq!(universe.get_cell(2, 2), DEAD);
+        assert_eq!(universe.get_cell(2, 1), 2);
+        assert_eq!(universe.get_cell(2, 0), ALIVE);
+    }
+
+    #[test]
+    fn neighbourhoods_select_neighbours() {
+        let cases = [
+            ("B1/SV", vec![(2, 1), (1, 2), (3, 2), (2, 3)]),
+            (
+                "B1/SH",
+                vec![(1, 1), (2, 1), (1, 2), (3, 2), (2, 3), (3, 3)],
+            ),
+        ];
+        for (rule, expected) in cases {
+            let mut universe = universe_with(5, 5, &[(2, 2)]);
+            universe.set_rule(rule.parse().unwrap());
+            universe.step();
+            let cells: Vec<_> = universe.live_cells().map(|(x, y, _)| (x, y)).collect();
+            assert_eq!(cells, expected, "{rule}");
+        }
+    }
+
+    #[test]
+    fn bugs_squares_settle_or_grow() {
+        let square = |min: i64, max: i64| -> Vec<(i64, i64)> {
+            (min..=max)
+                .flat_map(|x| (min..=max).map(move |y| (x, y)))
+                .collect()
+        };
+        let bugs: Rule = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+
+        // Every cell of a 6x6 square sees all 36 cells, the cells next to it see only 30
+        let six = square(10, 15);
+        let mut universe = universe_with(30, 30, &six);
+        universe.set_rule(bugs.clone());
+        for _ in 0..4 {
+            universe.step();
+            assert_eq!(sorted_live_cells(&universe), six);
+        }
+
+        // Cells beside the middle five rows of a 7x7 square see 35 cells and are born, the ones
+        // beside its ends and corners see 30 or less
+        let mut universe = universe_with(30, 30, &square(10, 16));
+        universe.set_rule(bugs);
+        universe.step();
+        let inside = |coordinate| (10..=16).contains(&coordinate);
+        let middle = |coordinate| (11..=15).contains(&coordinate);
+        let beside = |coordinate| coordinate == 9 || coordinate == 17;
+        let expected: Vec<_> = square(9, 17)
+            .into_iter()
+            .filter(|&(x, y)| {
+                (inside(x) && inside(y)) || (beside(x) && middle(y)) || (middle(x) && beside(y))
+            })
+            .collect();
+        assert_eq!(expected.len(), 69);
+        assert_eq!(sorted_live_cells(&universe), expected);
+    }
+
+    #[test]
+    fn larger_than_life_matches_direct_counting() {
+        // Bugs, Majority and a von Neumann Generations rule without the middle cell
+        let rules = [
+            "R5,C0,M1,S34..58,B34..45,NM",
+            "R4,C0,M1,S41..81,B41..81,NM",
+            "R3,C4,M0,S2..9,B3..5,NN",
+        ];
+        for rule in rules {
+            let mut universe = Universe::new(48, 40);
+            universe.set_rule(rule.parse().unwrap());
+            universe.fill_random(10, 10, 28, 20, 1);
+
+            for _ in 0..8 {
+                let rule = universe.rule().clone();
+                let larger_than_life = rule.larger_than_life().unwrap();
+                let range = larger_than_life.range() as i64;
+                let mut expected = universe.clone();
+                for y in 0..40 {
+                    for x in 0..48 {
+                        let mut count = 0;
+                        for dy in -range..=range {
+                            for dx in -range..=range {
+                                let in_range = rule.neighbourhood() == Neighbourhood::Moore
+                                    || dx.abs() + dy.abs() <= range;
+                                if in_range && universe.get_cell(x + dx, y + dy) == ALIVE {
+                                    count += 1;
+                                }
+                            }
+                        }
+                        let state = rule.next_state_in_range(universe.get_cell(x, y), count);
+                        expected.set_cell(x, y, state);
+                    }
+                }
+                universe.step();
+                assert_eq!(universe.cells(), expected.cells(), "{rule}");
+            }
+        }
+    }
+
+    #[test]
+    fn majority_fills_in_a_solid_square() {
+        // Every cell of a 9x9 square with one hole sees a majority of live cells in range 4
+        let mut universe = Universe::new(9, 9);
+        universe.set_rule("R4,C0,M1,S41..81,B41..81,NM".parse().unwrap());
+        for y in 0..9 {
+            for x in 0..9 {
+                universe.set_cell(x, y, ALIVE);
+            }
+        }
+        universe.set_cell(4, 4, DEAD);
+        universe.step();
+        assert_eq!(universe.get_cell(4, 4), ALIVE);
+        // Corners see 25 cells of 81
+        assert_eq!(universe.get_cell(0, 0), DEAD);
+        assert_eq!(universe.get_cell(8, 8), DEAD);
+    }
+
+    #[test]
+    fn cells_outside_are_dead() {
+        let mut universe = Universe::new(3, 3);
+        universe.set_cell(-1, 0, ALIVE);
+        universe.set_cell(3, 3, ALIVE);
+        assert_eq!(universe.population(), 0);
+        assert_eq!(universe.get_cell(-1, 0), DEAD);
+    }
+
+    #[test]
+    fn rule_tables_move_electrons_along_wires() {
+        let rule_table = rule::table::parse(rule::table::tests::WIREWORLD).unwrap();
+        let rule = Rule::from_table(rule_table);
+        assert_eq!(rule.to_string(), "WireWorld");
+        assert_eq!(rule.states(), 4);
+        assert!(!rule.has_b0());
+        assert_eq!(rule.color(3), [255, 128, 0]);
+
+        // Tail and head on a wire, the electron moves right by one cell every generation
+        let mut universe = Universe::new(8, 3);
+        universe.set_rule(rule);
+        for (x, state) in [2, 1, 3, 3, 3, 3, 3, 3].into_iter().enumerate() {
+            universe.set_cell(x as i64, 1, state);
+        }
+        for generation in 1..=4 {
+            universe.step();
+            let row: Vec<u8> = (0..8).map(|x| universe.get_cell(x, 1)).collect();
+            let head = generation + 1;
+            assert_eq!(row[head], 1, "{row:?}");
+            assert_eq!(row[head - 1], 2, "{row:?}");
+            assert!(row[head + 1..].iter().all(|&state| state == 3), "{row:?}");
+        }
+        assert_eq!(universe.population(), 8);
+    }
+}
@@ -0,0 +1,376 @@
+// Continuous cellular automata, cells are values in 0..=1 instead of states
+// Reference implementation of the compute shader, the universe wraps around at the edges
+
+use crate::simulation::xorshift;
+use std::fmt;
+
+/// Largest kernel radius, the kernel is up to 129x129 cells
+pub const MAX_KERNEL_RADIUS: u32 = 64;
+
+/// Lenia, growth is a bell curve of the potential given by a ring kernel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lenia {
+    pub radius: u32,
+    // Relative heights of the kernel rings from the inside out
+    pub peaks: Vec<f32>,
+    // Center and width of the growth bell curve
+    pub mu: f32,
+    pub sigma: f32,
+    pub dt: f32,
+}
+
+impl Default for Lenia {
+    /// Orbium, the glider of Lenia
+    fn default() -> Self {
+        Self {
+            radius: 13,
+            peaks: vec![1.],
+            mu: 0.15,
+            sigma: 0.015,
+            dt: 0.1,
+        }
+    }
+}
+
+/// SmoothLife, a disk and the annulus around it play the cell and its neighbours
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmoothLife {
+    // Outer radius of the annulus, the disk has a third of it
+    pub radius: u32,
+    // Annulus fillings that turn a dead or a live cell alive
+    pub birth: (f32, f32),
+    pub death: (f32, f32),
+    // Smoothness of the steps over the annulus and the disk filling
+    pub alpha_n: f32,
+    pub alpha_m: f32,
+    // 1 is discrete time, the next value is the transition function
+    pub dt: f32,
+}
+
+impl Default for SmoothLife {
+    /// Rafler's parameters from the SmoothLife paper
+    fn default() -> Self {
+        Self {
+            radius: 21,
+            birth: (0.278, 0.365),
+            death: (0.267, 0.445),
+            alpha_n: 0.028,
+            alpha_m: 0.147,
+            dt: 1.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContinuousRule {
+    Lenia(Lenia),
+    SmoothLife(SmoothLife),
+}
+
+impl ContinuousRule {
+    pub fn radius(&self) -> u32 {
+        match self {
+            ContinuousRule::Lenia(lenia) => lenia.radius,
+            ContinuousRule::SmoothLife(smooth_life) => smooth_life.radius,
+        }
+    }
+
+    /// Weights of the cells within the radius, row by row from (-radius, -radius)
+    ///
+    /// Lenia only uses the first weight. SmoothLife has the annulus first and the disk second.
+    /// Both kernels add up to 1.
+    pub fn kernel(&self) -> Vec<[f32; 2]> {
+        let radius = self.radius() as i32;
+        let mut kernel = Vec::with_capacity((2 * radius as usize + 1).pow(2));
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                kernel.push(match self {
+                    ContinuousRule::Lenia(lenia) => [lenia_shell(lenia, distance), 0.],
+                    ContinuousRule::SmoothLife(smooth_life) => {
+                        // Edges are antialiased over one cell
+                        let outer_radius = smooth_life.radius as f32;
+                        let inner_radius = outer_radius / 3.;
+                        let disk = (inner_radius + 0.5 - distance).clamp(0., 1.);
+                        let outer_disk = (outer_radius + 0.5 - distance).clamp(0., 1.);
+                        [outer_disk - disk, disk]
+                    }
+                });
+            }
+        }
+
+        for channel in 0..2 {
+            let sum: f32 = kernel.iter().map(|weights| weights[channel]).sum();
+            if sum > 0. {
+                for weights in &mut kernel {
+                    weights[channel] /= sum;
+                }
+            }
+        }
+        kernel
+    }
+
+    /// Next value of a cell from its value and the kernel sums around it
+    pub fn next_value(&self, value: f32, potential: [f32; 2]) -> f32 {
+        match self {
+            ContinuousRule::Lenia(lenia) => {
+                let distance = potential[0] - lenia.mu;
+                let growth =
+                    2. * (-distance * distance / (2. * lenia.sigma * lenia.sigma)).exp() - 1.;
+                (value + lenia.dt * growth).clamp(0., 1.)
+            }
+            ContinuousRule::SmoothLife(smooth_life) => {
+                let [annulus, disk] = potential;
+                let transition = smooth_life_transition(smooth_life, annulus, disk);
+                (value + smooth_life.dt * (transition - value)).clamp(0., 1.)
+            }
+        }
+    }
+}
+
+// Bump of the ring the distance falls into, zero outside of the radius
+fn lenia_shell(lenia: &Lenia, distance: f32) -> f32 {
+    let relative_distance = distance / lenia.radius as f32;
+    if relative_distance >= 1. || lenia.peaks.is_empty() {
+        return 0.;
+    }
+    let rings = relative_distance * lenia.peaks.len() as f32;
+    let peak = lenia.peaks[(rings as usize).min(lenia.peaks.len() - 1)];
+    let position = rings.fract();
+    if position <= 0. || position >= 1. {
+        return 0.;
+    }
+    // Exponential core, 1 in the middle of the ring
+    peak * (4. - 1. / (position * (1. - position))).exp()
+}
+
+// Rafler's s(n, m) with smooth steps
+fn smooth_life_transition(smooth_life: &SmoothLife, annulus: f32, disk: f32) -> f32 {
+    let step = |x: f32, edge: f32, alpha: f32| 1. / (1. + (-(x - edge) * 4. / alpha).exp());
+    let live = step(disk, 0.5, smooth_life.alpha_m);
+    let threshold = |dead: f32, alive: f32| dead * (1. - live) + alive * live;
+    let low = threshold(smooth_life.birth.0, smooth_life.death.0);
+    let high = threshold(smooth_life.birth.1, smooth_life.death.1);
+    step(annulus, low, smooth_life.alpha_n) * (1. - step(annulus, high, smooth_life.alpha_n))
+}
+
+impl fmt::Display for ContinuousRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContinuousRule::Lenia(lenia) => {
+                let peaks: Vec<String> = lenia.peaks.iter().map(f32::to_string).collect();
+                write!(
+                    f,
+                    "Lenia R={} peaks={} mu={} sigma={} dt={}",
+                    lenia.radius,
+                    peaks.join(","),
+                    lenia.mu,
+                    lenia.sigma,
+                    lenia.dt
+                )
+            }
+            ContinuousRule::SmoothLife(smooth_life) => write!(
+                f,
+                "SmoothLife R={} dt={}",
+                smooth_life.radius, smooth_life.dt
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContinuousUniverse {
+    width: usize,
+    height: usize,
+    cells: Vec<f32>,
+    next_cells: Vec<f32>,
+    generation: u64,
+    rule: ContinuousRule,
+    // Nonzero weights of the kernel with their offsets
+    kernel: Vec<(i64, i64, [f32; 2])>,
+}
+
+impl ContinuousUniverse {
+    pub fn new(width: usize, height: usize, rule: ContinuousRule) -> Self {
+        let radius = rule.radius() as i64;
+        let kernel = rule
+            .kernel()
+            .into_iter()
+            .enumerate()
+            .map(|(index, weights)| {
+                let side = 2 * radius + 1;
+                (
+                    index as i64 % side - radius,
+                    index as i64 / side - radius,
+                    weights,
+                )
+            })
+            .filter(|&(_, _, weights)| weights != [0., 0.])
+            .collect();
+        Self {
+            width,
+            height,
+            cells: vec![0.; width * height],
+            next_cells: vec![0.; width * height],
+            generation: 0,
+            rule,
+            kernel,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    /// Row-major cell values
+    pub fn cells(&self) -> &[f32] {
+        &self.cells
+    }
+
+    /// Coordinates wrap around
+    pub fn get_cell(&self, x: i64, y: i64) -> f32 {
+        self.cells[self.index(x, y)]
+    }
+
+    /// Coordinates wrap around, the value is clamped to 0..=1
+    pub fn set_cell(&mut self, x: i64, y: i64, value: f32) {
+        let index = self.index(x, y);
+        self.cells[index] = value.clamp(0., 1.);
+    }
+
+    /// Fills the rectangle with uniform noise, the same seed gives the same noise
+    pub fn fill_random(&mut self, x: i64, y: i64, width: i64, height: i64, seed: u64) {
+        let mut random = xorshift(seed);
+        for cell_y in y..y + height {
+            for cell_x in x..x + width {
+                let state = random.next().unwrap();
+                self.set_cell(cell_x, cell_y, (state >> 40) as f32 / (1 << 24) as f32);
+            }
+        }
+    }
+
+    /// Advances the universe by one time step
+    ///
+    /// The app steps on the GPU, this is the reference the compute shader is tested against.
+    pub fn step(&mut self) {
+        for y in 0..self.height as i64 {
+            for x in 0..self.width as i64 {
+                let mut potential = [0.; 2];
+                for &(dx, dy, weights) in &self.kernel {
+                    let value = self.get_cell(x + dx, y + dy);
+                    potential[0] += weights[0] * value;
+                    potential[1] += weights[1] * value;
+                }
+                let index = self.index(x, y);
+                self.next_cells[index] = self.rule.next_value(self.cells[index], potential);
+            }
+        }
+        std::mem::swap(&mut self.cells, &mut self.next_cells);
+        self.generation += 1;
+    }
+
+    fn index(&self, x: i64, y: i64) -> usize {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        y * self.width + x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_add_up_to_one() {
+        let rules = [
+            ContinuousRule::Lenia(Lenia::default()),
+            ContinuousRule::Lenia(Lenia {
+                radius: 20,
+                peaks: vec![0.5, 1., 0.667],
+                ..Lenia::default()
+            }),
+            ContinuousRule::SmoothLife(SmoothLife::default()),
+        ];
+        for rule in rules {
+            let kernel = rule.kernel();
+            let side = 2 * rule.radius() as usize + 1;
+            assert_eq!(kernel.len(), side * side);
+            let first: f32 = kernel.iter().map(|weights| weights[0]).sum();
+            assert!((first - 1.).abs() < 1e-4, "{rule}");
+            // Nothing at the center of a Lenia kernel or the corners of any kernel
+            assert_eq!(kernel[0], [0., 0.], "{rule}");
+            if let ContinuousRule::Lenia(_) = rule {
+                assert_eq!(kernel[kernel.len() / 2], [0., 0.], "{rule}");
+            }
+        }
+    }
+
+    #[test]
+    fn growth_follows_the_potential() {
+        let lenia = ContinuousRule::Lenia(Lenia::default());
+        // Growth is largest at mu and negative far from it
+        assert!((lenia.next_value(0.5, [0.15, 0.]) - 0.6).abs() < 1e-6);
+        assert!((lenia.next_value(0.5, [0.5, 0.]) - 0.4).abs() < 1e-6);
+        assert_eq!(lenia.next_value(0.95, [0.15, 0.]), 1.);
+        assert_eq!(lenia.next_value(0.05, [0., 0.]), 0.);
+
+        let smooth_life = ContinuousRule::SmoothLife(SmoothLife::default());
+        // A dead disk is born in the birth interval, a live one survives in the death interval
+        assert!(smooth_life.next_value(0., [0.32, 0.]) > 0.9);
+        assert!(smooth_life.next_value(0., [0.1, 0.]) < 0.1);
+        assert!(smooth_life.next_value(1., [0.4, 1.]) > 0.9);
+        assert!(smooth_life.next_value(1., [0.6, 1.]) < 0.1);
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let mut universe = ContinuousUniverse::new(64, 64, ContinuousRule::Lenia(Lenia::default()));
+        universe.fill_random(20, 20, 24, 24, 7);
+        for _ in 0..20 {
+            universe.step();
+        }
+        assert_eq!(universe.generation(), 20);
+        assert!(universe.cells().iter().sum::<f32>().is_finite());
+        assert!(
+            universe
+                .cells()
+                .iter()
+                .all(|value| (0.0..=1.0).contains(value))
+        );
+    }
+
+    #[test]
+    fn wraps_around_the_edges() {
+        let rule = ContinuousRule::SmoothLife(SmoothLife {
+            radius: 6,
+            ..SmoothLife::default()
+        });
+        // The same patch on both sides of an edge evolves the same way
+        let mut middle = ContinuousUniverse::new(32, 32, rule.clone());
+        let mut edge = ContinuousUniverse::new(32, 32, rule);
+        middle.fill_random(10, 10, 12, 12, 3);
+        edge.fill_random(26, 26, 12, 12, 3);
+        for _ in 0..4 {
+            middle.step();
+            edge.step();
+        }
+        for y in 0..12 {
+            for x in 0..12 {
+                let expected = middle.get_cell(10 + x, 10 + y);
+                assert!((edge.get_cell(26 + x, 26 + y) - expected).abs() < 1e-5);
+            }
+        }
+    }
+}
@@ -0,0 +1,687 @@
+// HashLife, Gosper's algorithm
+// The plane is a quadtree of canonicalized nodes, each node memoizes its future center
+
+use crate::simulation::rule::{NEIGHBOURS, Rule};
+use crate::simulation::{ALIVE, DEAD, Universe};
+use std::collections::HashMap;
+
+pub type NodeId = u32;
+
+// Leaves are nodes 0..=255, the id of a leaf is its cell state
+const LEAF_COUNT: usize = 256;
+const DEAD_LEAF: NodeId = DEAD as NodeId;
+
+// Cache size after which nodes unreachable from the root are freed
+const DEFAULT_MAX_NODES: usize = 1 << 22;
+
+#[derive(Debug, Clone)]
+struct Node {
+    // Node covers 2^level x 2^level cells, leaves are level 0
+    level: u32,
+    // nw, ne, sw, se
+    children: [NodeId; 4],
+    population: u64,
+    // [min_x, min_y, max_x, max_y) of the live cells relative to the node top-left, None if empty
+    // Nodes never change, it is worked out from the children when the node is made
+    bounding_box: Option<[i64; 4]>,
+    // Memoized `HashLife::next` of the node
+    result: Option<NodeId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HashLife {
+    nodes: Vec<Node>,
+    lookup: HashMap<[NodeId; 4], NodeId>,
+    // Empty node of each level
+    empty: Vec<NodeId>,
+    root: NodeId,
+    // World position of the root top-left cell
+    origin: (i64, i64),
+    // Every step advances 2^step_exponent generations
+    step_exponent: u32,
+    rule: Rule,
+    generation: u64,
+    max_nodes: usize,
+}
+
+impl Default for HashLife {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HashLife {
+    pub fn new() -> Self {
+        let leaf = |state: usize| Node {
+            level: 0,
+            children: [DEAD_LEAF; 4],
+            population: (state != DEAD as usize) as u64,
+            bounding_box: (state != DEAD as usize).then_some([0, 0, 1, 1]),
+            result: None,
+        };
+        let mut hashlife = Self {
+            nodes: (0..LEAF_COUNT).map(leaf).collect(),
+            lookup: HashMap::new(),
+            empty: vec![DEAD_LEAF],
+            root: DEAD_LEAF,
+            origin: (0, 0),
+            step_exponent: 0,
+            rule: Rule::LIFE,
+            generation: 0,
+            max_nodes: DEFAULT_MAX_NODES,
+        };
+        hashlife.root = hashlife.empty_node(3);
+        hashlife
+    }
+
+    pub fn from_universe(universe: &Universe) -> Self {
+        let mut hashlife = Self::new();
+        for (x, y, state) in universe.live_cells() {
+            hashlife.set_cell(x, y, state);
+        }
+        hashlife.generation = universe.generation();
+        hashlife.set_rule(universe.rule().clone());
+        hashlife
+    }
+
+    /// Replaces the universe contents, cells outside of it are dropped
+    pub fn write_to(&self, universe: &mut Universe) {
+        universe.clear();
+        let (width, height) = (universe.width() as i64, universe.height() as i64);
+        self.for_each_live_cell_in((0, 0), (width, height), &mut |x, y, state| {
+            universe.set_cell(x, y, state)
+        });
+        universe.set_generation(self.generation);
+        universe.set_rule(self.rule.clone());
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn population(&self) -> u64 {
+        self.nodes[self.root as usize].population
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn step_exponent(&self) -> u32 {
+        self.step_exponent
+    }
+
+    /// Every following step advances 2^step_exponent generations
+    pub fn set_step_exponent(&mut self, step_exponent: u32) {
+        if step_exponent == self.step_exponent {
+            return;
+        }
+        self.step_exponent = step_exponent;
+        // Memoized results are only valid for one step size
+        self.clear_results();
+    }
+
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// B0 rules are not supported, empty space must stay empty
+    /// Neither are Larger than Life rules and rule tables, a node only depends on its range 1
+    /// surroundings. Nor bounded grids, the universe is unbounded
+    pub fn supports(rule: &Rule) -> bool {
+        !rule.has_b0()
+            && rule.larger_than_life().is_none()
+            && rule.rule_table().is_none()
+            && rule.topology().is_none()
+    }
+
+    /// See `supports`
+    pub fn set_rule(&mut self, rule: Rule) {
+        assert!(Self::supports(&rule), "HashLife can't run rule {rule}");
+        if rule == self.rule {
+            return;
+        }
+        self.rule = rule;
+        self.clear_results();
+    }
+
+    fn clear_results(&mut self) {
+        for node in self.nodes.iter_mut() {
+            node.result = None;
+        }
+    }
+
+    /// Cache size after which unreachable nodes are freed before a step
+    pub fn set_max_nodes(&mut self, max_nodes: usize) {
+        self.max_nodes = max_nodes;
+    }
+
+    pub fn get_cell(&self, x: i64, y: i64) -> u8 {
+        let size = 1i64 << self.level(self.root);
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        if x < 0 || y < 0 || x >= size || y >= size {
+            return DEAD;
+        }
+
+        let mut node = self.root;
+        let (mut x, mut y) = (x, y);
+        while self.level(node) > 0 {
+            let half = 1i64 << (self.level(node) - 1);
+            let quadrant = (x >= half) as usize + 2 * (y >= half) as usize;
+            node = self.nodes[node as usize].children[quadrant];
+            x %= half;
+            y %= half;
+        }
+        node as u8
+    }
+
+    /// Any state is stored, e.g. from macrocell files, but stepping only counts `ALIVE` neighbours
+    pub fn set_cell(&mut self, x: i64, y: i64, state: u8) {
+        loop {
+            let size = 1i64 << self.level(self.root);
+            let (local_x, local_y) = (x - self.origin.0, y - self.origin.1);
+            if local_x >= 0 && local_y >= 0 && local_x < size && local_y < size {
+                self.root = self.set_cell_in(self.root, local_x, local_y, state as NodeId);
+                return;
+            }
+            self.expand();
+        }
+    }
+
+    /// Advances 2^step_exponent generations
+    pub fn step(&mut self) {
+        if self.nodes.len() > self.max_nodes {
+            self.collect_garbage();
+        }
+
+        // The pattern must stay within the center half of the root after the step
+        while self.level(self.root) < self.step_exponent + 3 || !self.is_padded(self.root) {
+            self.expand();
+        }
+
+        let quarter = 1i64 << (self.level(self.root) - 2);
+        self.root = self.next(self.root);
+        self.origin.0 += quarter;
+        self.origin.1 += quarter;
+        self.generation += 1 << self.step_exponent;
+    }
+
+    /// Frees all nodes not reachable from the root
+    pub fn collect_garbage(&mut self) {
+        let mut remap = vec![NodeId::MAX; self.nodes.len()];
+        let mut nodes = self.nodes[..LEAF_COUNT].to_vec();
+        for (leaf, remapped) in remap.iter_mut().take(LEAF_COUNT).enumerate() {
+            *remapped = leaf as NodeId;
+        }
+        let root = self.copy_reachable(self.root, &mut remap, &mut nodes);
+
+        // Keep memoized results which are still alive
+        for (old_id, &new_id) in remap.iter().enumerate() {
+            if new_id == NodeId::MAX {
+                continue;
+            }
+            nodes[new_id as usize].result = self.nodes[old_id]
+                .result
+                .map(|result| remap[result as usize])
+                .filter(|&result| result != NodeId::MAX);
+        }
+
+        self.lookup = nodes
+            .iter()
+            .enumerate()
+            .skip(LEAF_COUNT)
+            .map(|(id, node)| (node.children, id as NodeId))
+            .collect();
+        self.nodes = nodes;
+        self.empty.truncate(1);
+        self.root = root;
+    }
+
+    /// Calls `f(x, y, state)` for every non-dead cell within [min, max)
+    pub fn for_each_live_cell_in(
+        &self,
+        min: (i64, i64),
+        max: (i64, i64),
+        f: &mut impl FnMut(i64, i64, u8),
+    ) {
+        self.for_each_live_cell_in_node(self.root, self.origin, min, max, f);
+    }
+
+    fn for_each_live_cell_in_node(
+        &self,
+        node: NodeId,
+        origin: (i64, i64),
+        min: (i64, i64),
+        max: (i64, i64),
+        f: &mut impl FnMut(i64, i64, u8),
+    ) {
+        let node_data = &self.nodes[node as usize];
+        let size = 1i64 << node_data.level;
+        if node_data.population == 0
+            || origin.0 >= max.0
+            || origin.1 >= max.1
+            || origin.0 + size <= min.0
+            || origin.1 + size <= min.1
+        {
+            return;
+        }
+        if node_data.level == 0 {
+            f(origin.0, origin.1, node as u8);
+            return;
+        }
+
+        let half = size / 2;
+        for (quadrant, &child) in node_data.children.iter().enumerate() {
+            let child_origin = (
+                origin.0 + half * (quadrant % 2) as i64,
+                origin.1 + half * (quadrant / 2) as i64,
+            );
+            self.for_each_live_cell_in_node(child, child_origin, min, max, f);
+        }
+    }
+
+    /// Smallest rectangle [min, max) around all live cells, None if there are none
+    ///
+    /// Every node keeps its own bounding box, it works for patterns far too large to list.
+    pub fn bounding_box(&self) -> Option<((i64, i64), (i64, i64))> {
+        let [min_x, min_y, max_x, max_y] = self.nodes[self.root as usize].bounding_box?;
+        let (x, y) = self.origin;
+        Some(((x + min_x, y + min_y), (x + max_x, y + max_y)))
+    }
+
+    /// Live cells of the whole plane, for small patterns
+    pub fn live_cells(&self) -> Vec<(i64, i64, u8)> {
+        let mut cells = Vec::new();
+        self.for_each_live_cell_in(
+            (i64::MIN, i64::MIN),
+            (i64::MAX, i64::MAX),
+            &mut |x, y, state| cells.push((x, y, state)),
+        );
+        cells
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// Replaces the whole plane with the node, its center is placed at the world origin
+    pub fn set_root(&mut self, root: NodeId) {
+        self.root = root;
+        while self.level(self.root) < 3 {
+            let [nw, ne, sw, se] = self.children(self.root);
+            let empty = self.empty_node(self.level(self.root) - 1);
+            let children = [
+                self.join([empty, empty, empty, nw]),
+                self.join([empty, empty, ne, empty]),
+                self.join([empty, sw, empty, empty]),
+                self.join([se, empty, empty, empty]),
+            ];
+            self.root = self.join(children);
+        }
+        let half = 1i64 << (self.level(self.root) - 1);
+        self.origin = (-half, -half);
+    }
+
+    /// Moves the whole plane
+    pub fn translate(&mut self, dx: i64, dy: i64) {
+        self.origin.0 += dx;
+        self.origin.1 += dy;
+    }
+
+    pub fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    pub fn leaf(state: u8) -> NodeId {
+        state as NodeId
+    }
+
+    /// Canonical node of four children of the same level, `None` if the levels differ
+    pub fn node(&mut self, children: [NodeId; 4]) -> Option<NodeId> {
+        let level = self.level(children[0]);
+        if children.iter().any(|&child| self.level(child) != level) {
+            return None;
+        }
+        Some(self.join(children))
+    }
+
+    pub fn node_level(&self, node: NodeId) -> u32 {
+        self.level(node)
+    }
+
+    pub fn node_children(&self, node: NodeId) -> [NodeId; 4] {
+        self.children(node)
+    }
+
+    pub fn node_population(&self, node: NodeId) -> u64 {
+        self.nodes[node as usize].population
+    }
+
+    pub fn empty_node_of_level(&mut self, level: u32) -> NodeId {
+        self.empty_node(level)
+    }
+
+    /// Node with one cell of it changed, (x, y) is relative to the node top-left
+    pub fn with_cell(&mut self, node: NodeId, x: i64, y: i64, state: u8) -> NodeId {
+        self.set_cell_in(node, x, y, state as NodeId)
+    }
+
+    fn level(&self, node: NodeId) -> u32 {
+        self.nodes[node as usize].level
+    }
+
+    fn children(&self, node: NodeId) -> [NodeId; 4] {
+        self.nodes[node as usize].children
+    }
+
+    fn join(&mut self, children: [NodeId; 4]) -> NodeId {
+        if let Some(&node) = self.lookup.get(&children) {
+            return node;
+        }
+        let level = self.level(children[0]) + 1;
+        let node = Node {
+            level,
+            children,
+            population: children
+                .iter()
+                .map(|&child| self.nodes[child as usize].population)
+                .sum(),
+            bounding_box: self.join_bounding_boxes(level, children),
+            result: None,
+        };
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(node);
+        self.lookup.insert(children, id);
+        id
+    }
+
+    // Bounding box of a node of `level` from the ones of its children
+    fn join_bounding_boxes(&self, level: u32, children: [NodeId; 4]) -> Option<[i64; 4]> {
+        // Boxes saturate past the i64 coordinate range
+        let half = 1i64
+            .checked_shl(level - 1)
+            .filter(|&half| half > 0)
+            .unwrap_or(i64::MAX);
+        let mut bounding_box: Option<[i64; 4]> = None;
+        for (quadrant, &child) in children.iter().enumerate() {
+            let Some([min_x, min_y, max_x, max_y]) = self.nodes[child as usize].bounding_box else {
+                continue;
+            };
+            let (dx, dy) = (half * (quadrant % 2) as i64, half * (quadrant / 2) as i64);
+            let child_box = [
+                min_x.saturating_add(dx),
+                min_y.saturating_add(dy),
+                max_x.saturating_add(dx),
+                max_y.saturating_add(dy),
+            ];
+            bounding_box = Some(bounding_box.map_or(child_box, |[a, b, c, d]| {
+                [
+                    a.min(child_box[0]),
+                    b.min(child_box[1]),
+                    c.max(child_box[2]),
+                    d.max(child_box[3]),
+                ]
+            }));
+        }
+        bounding_box
+    }
+
+    fn empty_node(&mut self, level: u32) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let child = *self.empty.last().unwrap();
+            let node = self.join([child; 4]);
+            self.empty.push(node);
+        }
+        self.empty[level as usize]
+    }
+
+    /// Doubles the root size keeping the pattern in the center
+    fn expand(&mut self) {
+        let level = self.level(self.root);
+        let [nw, ne, sw, se] = self.children(self.root);
+        let empty = self.empty_node(level - 1);
+        let nw = self.join([empty, empty, empty, nw]);
+        let ne = self.join([empty, empty, ne, empty]);
+        let sw = self.join([empty, sw, empty, empty]);
+        let se = self.join([se, empty, empty, empty]);
+        self.root = self.join([nw, ne, sw, se]);
+
+        let half = 1i64 << (level - 1);
+        self.origin.0 -= half;
+        self.origin.1 -= half;
+    }
+
+    /// Whether all live cells are within the center quarter of the node
+    fn is_padded(&self, node: NodeId) -> bool {
+        let [nw, ne, sw, se] = self.children(node);
+        let population = |node: NodeId| self.nodes[node as usize].population;
+        let grandchild = |node: NodeId, quadrant: usize| self.children(node)[quadrant];
+        population(nw) == population(grandchild(grandchild(nw, 3), 3))
+            && population(ne) == population(grandchild(grandchild(ne, 2), 2))
+            && population(sw) == population(grandchild(grandchild(sw, 1), 1))
+            && population(se) == population(grandchild(grandchild(se, 0), 0))
+    }
+
+    fn set_cell_in(&mut self, node: NodeId, x: i64, y: i64, leaf: NodeId) -> NodeId {
+        let level = self.level(node);
+        if level == 0 {
+            return leaf;
+        }
+        let half = 1i64 << (level - 1);
+        let quadrant = (x >= half) as usize + 2 * (y >= half) as usize;
+        let mut children = self.children(node);
+        children[quadrant] = self.set_cell_in(children[quadrant], x % half, y % half, leaf);
+        self.join(children)
+    }
+
+    fn center(&mut self, node: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.children(node);
+        self.join([
+            self.children(nw)[3],
+            self.children(ne)[2],
+            self.children(sw)[1],
+            self.children(se)[0],
+        ])
+    }
+
+    fn horizontal_center(&mut self, west: NodeId, east: NodeId) -> NodeId {
+        let [_, west_ne, _, west_se] = self.children(west);
+        let [east_nw, _, east_sw, _] = self.children(east);
+        self.join([west_ne, east_nw, west_se, east_sw])
+    }
+
+    fn vertical_center(&mut self, north: NodeId, south: NodeId) -> NodeId {
+        let [_, _, north_sw, north_se] = self.children(north);
+        let [south_nw, south_ne, _, _] = self.children(south);
+        self.join([north_sw, north_se, south_nw, south_ne])
+    }
+
+    /// Center of the node after min(2^step_exponent, 2^(level - 2)) generations
+    fn next(&mut self, node: NodeId) -> NodeId {
+        if let Some(result) = self.nodes[node as usize].result {
+            return result;
+        }
+
+        let level = self.level(node);
+        let result = if self.nodes[node as usize].population == 0 {
+            self.empty_node(level - 1)
+        } else if level == 2 {
+            self.next_level_2(node)
+        } else {
+            let [nw, ne, sw, se] = self.children(node);
+            let n01 = self.horizontal_center(nw, ne);
+            let n10 = self.vertical_center(nw, sw);
+            let n11 = self.center(node);
+            let n12 = self.vertical_center(ne, se);
+            let n21 = self.horizontal_center(sw, se);
+            let parts = [nw, n01, ne, n10, n11, n12, sw, n21, se];
+
+            // At full speed both halves of the step advance, otherwise only the second one
+            let full_speed = level - 2 <= self.step_exponent;
+            let mut r = [DEAD_LEAF; 9];
+            for (r, part) in r.iter_mut().zip(parts) {
+                *r = if full_speed {
+                    self.next(part)
+                } else {
+                    self.center(part)
+                };
+            }
+
+            let c00 = self.join([r[0], r[1], r[3], r[4]]);
+            let c01 = self.join([r[1], r[2], r[4], r[5]]);
+            let c10 = self.join([r[3], r[4], r[6], r[7]]);
+            let c11 = self.join([r[4], r[5], r[7], r[8]]);
+            let children = [
+                self.next(c00),
+                self.next(c01),
+                self.next(c10),
+                self.next(c11),
+            ];
+            self.join(children)
+        };
+
+        self.nodes[node as usize].result = Some(result);
+        result
+    }
+
+    /// One generation of the 4x4 node, brute force
+    fn next_level_2(&mut self, node: NodeId) -> NodeId {
+        let mut cells = [[DEAD; 4]; 4];
+        for (quadrant, child) in self.children(node).into_iter().enumerate() {
+            for (sub_quadrant, leaf) in self.children(child).into_iter().enumerate() {
+                let x = 2 * (quadrant % 2) + sub_quadrant % 2;
+                let y = 2 * (quadrant / 2) + sub_quadrant / 2;
+                cells[y][x] = leaf as u8;
+            }
+        }
+
+        let mut leaves = [DEAD_LEAF; 4];
+        for (i, leaf) in leaves.iter_mut().enumerate() {
+            let (x, y) = (1 + i % 2, 1 + i / 2);
+            let mut neighbours = 0;
+            for (bit, (dx, dy)) in NEIGHBOURS.into_iter().enumerate() {
+                if cells[(y as i64 + dy) as usize][(x as i64 + dx) as usize] == ALIVE {
+                    neighbours |= 1 << bit;
+                }
+            }
+            *leaf = self.rule.next_state(cells[y][x], neighbours) as NodeId;
+        }
+        self.join(leaves)
+    }
+
+    fn copy_reachable(&self, node: NodeId, remap: &mut [NodeId], nodes: &mut Vec<Node>) -> NodeId {
+        if remap[node as usize] != NodeId::MAX {
+            return remap[node as usize];
+        }
+        let mut copy = self.nodes[node as usize].clone();
+        for child in copy.children.iter_mut() {
+            *child = self.copy_reachable(*child, remap, nodes);
+        }
+        copy.result = None;
+        let id = nodes.len() as NodeId;
+        nodes.push(copy);
+        remap[node as usize] = id;
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::sorted_live_cells;
+
+    #[test]
+    fn agrees_with_naive_stepper_on_soups() {
+        let rules = [
+            "B3/S23",
+            "B36/S23",
+            "B2/S",
+            "B3678/S34678",
+            "B3/S2-i34q",
+            "B2-a/S12",
+            "B2/S013V",
+            "B2/S34H",
+        ];
+        for seed in 1..=8u64 {
+            let mut naive = Universe::new(160, 160);
+            naive.set_rule(rules[seed as usize % rules.len()].parse().unwrap());
+            naive.fill_random(64, 64, 32, 32, seed);
+            let mut single_steps = HashLife::from_universe(&naive);
+            let mut jump = HashLife::from_universe(&naive);
+            jump.set_step_exponent(5);
+
+            for _ in 0..32 {
+                naive.step();
+                single_steps.step();
+            }
+            jump.step();
+
+            for hashlife in [&single_steps, &jump] {
+                let mut universe = Universe::new(160, 160);
+                hashlife.write_to(&mut universe);
+                assert_eq!(sorted_live_cells(&universe), sorted_live_cells(&naive));
+                assert_eq!(hashlife.population(), naive.population() as u64);
+                assert_eq!(universe.generation(), 32);
+            }
+        }
+    }
+
+    #[test]
+    fn supports_unbounded_range_one_rules() {
+        for rule in ["B3/S23", "B3/S2-i34q", "B2/S/C3", "B2/S34H"] {
+            assert!(HashLife::supports(&rule.parse().unwrap()), "{rule}");
+        }
+        for rule in [
+            "B0/S8",
+            "R5,C0,M1,S34..58,B34..45,NM",
+            "B3/S23:T10,10",
+            "B3/S23:P10,10",
+        ] {
+            assert!(!HashLife::supports(&rule.parse().unwrap()), "{rule}");
+        }
+    }
+
+    #[test]
+    fn glider_after_large_jump() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut hashlife = HashLife::new();
+        for (x, y) in glider {
+            hashlife.set_cell(x, y, ALIVE);
+        }
+        hashlife.set_step_exponent(20);
+        hashlife.step();
+
+        // c/4 diagonally
+        let offset = (1 << 20) / 4;
+        assert_eq!(hashlife.generation(), 1 << 20);
+        assert_eq!(hashlife.population(), 5);
+        for (x, y) in glider {
+            assert_eq!(hashlife.get_cell(x + offset, y + offset), ALIVE);
+        }
+        assert_eq!(
+            hashlife.bounding_box(),
+            Some(((offset, offset), (offset + 3, offset + 3)))
+        );
+        assert_eq!(HashLife::new().bounding_box(), None);
+    }
+
+    #[test]
+    fn garbage_collection_keeps_pattern() {
+        let mut naive = Universe::new(128, 128);
+        naive.fill_random(48, 48, 32, 32, 42);
+        let mut hashlife = HashLife::from_universe(&naive);
+        hashlife.set_max_nodes(0);
+
+        for _ in 0..16 {
+            naive.step();
+            hashlife.step();
+        }
+        let nodes_before = hashlife.node_count();
+        hashlife.collect_garbage();
+        assert!(hashlife.node_count() <= nodes_before);
+
+        let mut universe = Universe::new(128, 128);
+        hashlife.write_to(&mut universe);
+        assert_eq!(sorted_live_cells(&universe), sorted_live_cells(&naive));
+    }
+}
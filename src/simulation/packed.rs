@@ -0,0 +1,327 @@
+// Bit-packed stepper, 64 cells per u64
+// Neighbour counts of a whole word come out of bitwise full adders, rows are stepped in parallel
+
+use crate::simulation::rule::topology::TopologyKind;
+use crate::simulation::rule::{NEIGHBOURS, Rule};
+use crate::simulation::{ALIVE, Universe};
+use rayon::prelude::*;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+#[derive(Debug, Clone)]
+pub struct PackedUniverse {
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+    // Bit x % 64 of word y * words_per_row + x / 64 is cell (x, y)
+    // Bits past the width stay clear
+    cells: Vec<u64>,
+    next_cells: Vec<u64>,
+    generation: u64,
+    rule: Rule,
+    // Bit n is set if n live neighbours give birth or keep a cell alive
+    birth: u16,
+    survival: u16,
+}
+
+impl PackedUniverse {
+    /// Two-state rules that only count the live neighbours, on the plane
+    pub fn supports(rule: &Rule) -> bool {
+        rule.states() == 2
+            && rule.outer_totalistic_counts().is_some()
+            && rule
+                .topology()
+                .is_none_or(|topology| topology.kind() == TopologyKind::Plane)
+    }
+
+    /// `supports` as an error for rules picked on the command line or in a pattern file
+    pub fn check_rule(rule: &Rule) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            Self::supports(rule),
+            "The packed engine can't run rule {rule}, it needs two states, neighbour counts only \
+             and no bounded grid other than the plane"
+        );
+        Ok(())
+    }
+
+    pub fn from_universe(universe: &Universe) -> Self {
+        let (width, height) = (universe.width(), universe.height());
+        let words_per_row = width.div_ceil(WORD_BITS);
+        let mut packed_universe = Self {
+            width,
+            height,
+            words_per_row,
+            cells: vec![0; words_per_row * height],
+            next_cells: vec![0; words_per_row * height],
+            generation: universe.generation(),
+            rule: Rule::LIFE,
+            birth: 0,
+            survival: 0,
+        };
+        packed_universe.set_rule(universe.rule().clone());
+        for (x, y, state) in universe.live_cells() {
+            packed_universe.set_cell(x as usize, y as usize, state == ALIVE);
+        }
+        packed_universe
+    }
+
+    /// Replaces the universe contents, sizes must match
+    pub fn write_to(&self, universe: &mut Universe) {
+        assert_eq!(
+            (universe.width(), universe.height()),
+            (self.width, self.height)
+        );
+        universe.clear();
+        for (x, y, state) in self.live_cells() {
+            universe.set_cell(x, y, state);
+        }
+        universe.set_generation(self.generation);
+        universe.set_rule(self.rule.clone());
+    }
+
+    /// Live cells in row order, only the set bits of each word are visited
+    pub fn live_cells(&self) -> impl Iterator<Item = (i64, i64, u8)> + '_ {
+        self.cells.iter().enumerate().flat_map(|(index, &word)| {
+            let (y, first_x) = (
+                index / self.words_per_row,
+                index % self.words_per_row * WORD_BITS,
+            );
+            let mut word = word;
+            std::iter::from_fn(move || {
+                (word != 0).then(|| {
+                    let x = first_x + word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    (x as i64, y as i64, ALIVE)
+                })
+            })
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    pub fn population(&self) -> u64 {
+        self.cells.iter().map(|word| word.count_ones() as u64).sum()
+    }
+
+    pub fn cells(&self) -> &[u64] {
+        &self.cells
+    }
+
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// Only rules that `supports` accepts
+    pub fn set_rule(&mut self, rule: Rule) {
+        assert!(
+            Self::supports(&rule),
+            "The packed engine can't run rule {rule}"
+        );
+        (self.birth, self.survival) = rule.outer_totalistic_counts().expect("rule without counts");
+        self.rule = rule;
+    }
+
+    pub fn get_cell(&self, x: usize, y: usize) -> bool {
+        assert!(x < self.width && y < self.height);
+        self.cells[y * self.words_per_row + x / WORD_BITS] >> (x % WORD_BITS) & 1 != 0
+    }
+
+    pub fn set_cell(&mut self, x: usize, y: usize, alive: bool) {
+        assert!(x < self.width && y < self.height);
+        let word = &mut self.cells[y * self.words_per_row + x / WORD_BITS];
+        let bit = 1 << (x % WORD_BITS);
+        if alive {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+
+    /// Advances the universe by one generation
+    pub fn step(&mut self) {
+        let words_per_row = self.words_per_row;
+        let height = self.height;
+        // Live bits of the last word of a row
+        let last_word_mask = match self.width % WORD_BITS {
+            0 => u64::MAX,
+            bits => (1 << bits) - 1,
+        };
+        let neighbourhood = self.rule.neighbourhood();
+        let (birth, survival) = (self.birth, self.survival);
+        let cells = &self.cells;
+        let empty_row = vec![0; words_per_row];
+        let row = |y: usize| &cells[y * words_per_row..(y + 1) * words_per_row];
+
+        self.next_cells
+            .par_chunks_mut(words_per_row)
+            .enumerate()
+            .for_each(|(y, next_row)| {
+                let up = if y > 0 { row(y - 1) } else { &empty_row };
+                let middle = row(y);
+                let down = if y + 1 < height {
+                    row(y + 1)
+                } else {
+                    &empty_row
+                };
+                for (index, next_word) in next_row.iter_mut().enumerate() {
+                    // Bit x of `west(r)` is the cell left of x, `east(r)` the one right of it
+                    let west = |r: &[u64]| {
+                        let carry = if index > 0 { r[index - 1] >> 63 } else { 0 };
+                        r[index] << 1 | carry
+                    };
+                    let east = |r: &[u64]| {
+                        let carry = r.get(index + 1).map_or(0, |word| word << 63);
+                        r[index] >> 1 | carry
+                    };
+                    // In the order of `NEIGHBOURS`
+                    let neighbour_words = [
+                        up[index],
+                        east(up),
+                        east(middle),
+                        east(down),
+                        down[index],
+                        west(down),
+                        west(middle),
+                        west(up),
+                    ];
+                    let mut neighbours = [0; NEIGHBOURS.len()];
+                    for (bit, word) in neighbour_words.into_iter().enumerate() {
+                        if neighbourhood.contains(bit) {
+                            neighbours[bit] = word;
+                        }
+                    }
+
+                    let counts = count_bits(neighbours);
+                    let alive = middle[index];
+                    let mut next = 0;
+                    for count in 0..=8 {
+                        let is_born = birth >> count & 1 != 0;
+                        let survives = survival >> count & 1 != 0;
+                        if !is_born && !survives {
+                            continue;
+                        }
+                        let with_count = equals(counts, count);
+                        if is_born {
+                            next |= with_count & !alive;
+                        }
+                        if survives {
+                            next |= with_count & alive;
+                        }
+                    }
+                    if index + 1 == words_per_row {
+                        next &= last_word_mask;
+                    }
+                    *next_word = next;
+                }
+            });
+        std::mem::swap(&mut self.cells, &mut self.next_cells);
+        self.generation += 1;
+    }
+}
+
+// Bit planes of the number of set bits at every position, least significant plane first
+fn count_bits(words: [u64; 8]) -> [u64; 4] {
+    let [a, b, c, d, e, f, g, h] = words;
+    let (sum_abc, carry_abc) = full_adder(a, b, c);
+    let (sum_def, carry_def) = full_adder(d, e, f);
+    let (sum_gh, carry_gh) = half_adder(g, h);
+    let (ones, carry_ones) = full_adder(sum_abc, sum_def, sum_gh);
+    // Carries are worth two
+    let (sum_twos, carry_twos) = full_adder(carry_abc, carry_def, carry_gh);
+    let (twos, carry) = half_adder(sum_twos, carry_ones);
+    // Worth four
+    let (fours, eights) = half_adder(carry_twos, carry);
+    [ones, twos, fours, eights]
+}
+
+fn full_adder(a: u64, b: u64, c: u64) -> (u64, u64) {
+    let sum = a ^ b;
+    (sum ^ c, a & b | sum & c)
+}
+
+fn half_adder(a: u64, b: u64) -> (u64, u64) {
+    (a ^ b, a & b)
+}
+
+// Positions where the count of `count_bits` is `count`
+fn equals(counts: [u64; 4], count: u32) -> u64 {
+    counts
+        .iter()
+        .enumerate()
+        .fold(u64::MAX, |equal, (bit, &plane)| {
+            equal & if count >> bit & 1 != 0 { plane } else { !plane }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_universe_stepper() {
+        for rule in [
+            "B3/S23",
+            "B36/S23",
+            "B3678/S34678",
+            "B2/S",
+            "B2/S013V",
+            "B2/S34H",
+        ] {
+            let rule: Rule = rule.parse().unwrap();
+            // Widths around the word size
+            for width in [1, 63, 64, 65, 130] {
+                let height = 37;
+                let mut universe = Universe::new(width, height);
+                universe.set_rule(rule.clone());
+                universe.fill_random(0, 0, width as i64, height as i64, 1);
+
+                let mut packed_universe = PackedUniverse::from_universe(&universe);
+                let mut unpacked = Universe::new(width, height);
+                for _ in 0..16 {
+                    universe.step();
+                    packed_universe.step();
+                    packed_universe.write_to(&mut unpacked);
+                    assert_eq!(unpacked.cells(), universe.cells(), "{rule} {width}");
+                    assert!(packed_universe.live_cells().eq(universe.live_cells()));
+                }
+                assert_eq!(packed_universe.population(), universe.population() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn counts_bits_with_adders() {
+        for neighbours in 0..=u8::MAX {
+            let words = std::array::from_fn(|bit| (neighbours as u64 >> bit & 1) * u64::MAX);
+            let counts = count_bits(words);
+            assert!(equals(counts, neighbours.count_ones()) == u64::MAX);
+        }
+    }
+
+    #[test]
+    fn rejects_rules_that_depend_on_positions() {
+        for rule in [
+            "B2/S/C3",
+            "B3/S2-i34q",
+            "R5,C0,M1,S34..58,B34..45,NM",
+            "B3/S23:T10,10",
+        ] {
+            assert!(!PackedUniverse::supports(&rule.parse().unwrap()), "{rule}");
+        }
+        assert!(PackedUniverse::supports(&"B3/S23:P10,10".parse().unwrap()));
+    }
+}
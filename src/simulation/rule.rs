@@ -0,0 +1,1122 @@
+// Cellular automaton rules, B3/S23 is Conway's Life
+
+pub mod larger_than_life;
+pub mod table;
+pub mod topology;
+
+use crate::simulation::rule::larger_than_life::LargerThanLife;
+use crate::simulation::rule::table::RuleTable;
+use crate::simulation::rule::topology::Topology;
+use crate::simulation::{ALIVE, DEAD};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Neighbour offsets in the order of the neighbourhood bits, clockwise from north
+pub const NEIGHBOURS: [(i64, i64); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+// Hensel letters of counts 1-4 in canonical order, with a representative neighbourhood
+// Counts 5-7 use the complements of the same letters of counts 3-1
+const HENSEL_LETTERS: [(u32, char, u8); 31] = [
+    (1, 'c', 0b0000_0010),
+    (1, 'e', 0b0000_0001),
+    (2, 'c', 0b0000_1010),
+    (2, 'e', 0b0000_0101),
+    (2, 'k', 0b0000_1001),
+    (2, 'a', 0b0000_0011),
+    (2, 'i', 0b0001_0001),
+    (2, 'n', 0b0010_0010),
+    (3, 'c', 0b0010_1010),
+    (3, 'e', 0b0001_0101),
+    (3, 'k', 0b0010_0101),
+    (3, 'a', 0b0000_0111),
+    (3, 'i', 0b1000_0011),
+    (3, 'n', 0b0000_1011),
+    (3, 'y', 0b0010_1001),
+    (3, 'q', 0b0010_0011),
+    (3, 'j', 0b0100_0011),
+    (3, 'r', 0b0001_0011),
+    (4, 'c', 0b1010_1010),
+    (4, 'e', 0b0101_0101),
+    (4, 'k', 0b0100_1011),
+    (4, 'a', 0b0000_1111),
+    (4, 'i', 0b0001_1011),
+    (4, 'n', 0b1000_1011),
+    (4, 'y', 0b0010_1011),
+    (4, 'q', 0b0010_0111),
+    (4, 'j', 0b0101_0011),
+    (4, 'r', 0b0001_0111),
+    (4, 't', 0b1001_0011),
+    (4, 'w', 0b0110_0011),
+    (4, 'z', 0b0011_0011),
+];
+
+// Generations rules can have up to 256 states, one byte per cell
+const MAX_STATES: u16 = 256;
+
+// Default colors, decaying states of Generations rules fade from the first to the last one
+const ALIVE_COLOR: [u8; 3] = [0, 255, 0];
+const DECAY_FIRST_COLOR: [u8; 3] = [255, 217, 0];
+const DECAY_LAST_COLOR: [u8; 3] = [89, 0, 26];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Neighbourhood {
+    #[default]
+    Moore,
+    // `V` suffix, the four orthogonal neighbours
+    VonNeumann,
+    // `H` suffix, all but the north-east and south-west neighbours
+    // The grid is drawn sheared so that these six neighbours surround the cell
+    Hexagonal,
+}
+
+impl Neighbourhood {
+    // Bits of the Moore neighbourhood that are neighbours
+    fn mask(self) -> u8 {
+        match self {
+            Neighbourhood::Moore => 0b1111_1111,
+            Neighbourhood::VonNeumann => 0b0101_0101,
+            Neighbourhood::Hexagonal => 0b1101_1101,
+        }
+    }
+
+    /// Whether the neighbour at `NEIGHBOURS[bit]` is in the neighbourhood
+    pub fn contains(self, bit: usize) -> bool {
+        self.mask() >> bit & 1 != 0
+    }
+
+    fn size(self) -> u32 {
+        self.mask().count_ones()
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Neighbourhood::Moore => "",
+            Neighbourhood::VonNeumann => "V",
+            Neighbourhood::Hexagonal => "H",
+        }
+    }
+}
+
+/// Rule of the Moore, von Neumann or hexagonal neighbourhood, a Larger than Life rule or a table
+///
+/// Moore rules can be isotropic non-totalistic, outer-totalistic rules are a special case.
+/// Generations rules have more than two states, cells that don't survive decay through
+/// states 2..states before they die. Only state 1 counts as a live neighbour.
+/// Rule tables of .rule files give the next state for the states of all neighbours.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rule {
+    // Bit `neighbours | alive << 8` is set if the cell is alive in the next generation
+    // Neighbours outside of the neighbourhood don't change the result
+    table: [u32; 16],
+    states: u16,
+    neighbourhood: Neighbourhood,
+    // Counts within a larger range replace the table
+    larger_than_life: Option<LargerThanLife>,
+    // Transitions of a .rule file replace the table, shared between the engines
+    rule_table: Option<Arc<RuleTable>>,
+    // Bounded grid of the suffix, the universe decides the size without one
+    topology: Option<Topology>,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self::LIFE
+    }
+}
+
+impl Rule {
+    pub const LIFE: Rule = Rule::totalistic(1 << 3, 1 << 2 | 1 << 3);
+
+    // Bit n of the masks is set if a cell with n live neighbours is alive in the next generation
+    const fn totalistic(birth: u16, survival: u16) -> Self {
+        let mut table = [0; 16];
+        let mut index = 0;
+        while index < 512 {
+            let counts = if index & 0x100 != 0 { survival } else { birth };
+            if counts >> (index as u8).count_ones() & 1 != 0 {
+                table[index / 32] |= 1 << (index % 32);
+            }
+            index += 1;
+        }
+        Self {
+            table,
+            states: 2,
+            neighbourhood: Neighbourhood::Moore,
+            larger_than_life: None,
+            rule_table: None,
+            topology: None,
+        }
+    }
+
+    // Nothing is ever born or survives, parsing fills it in
+    const EMPTY: Rule = Rule {
+        table: [0; 16],
+        states: 2,
+        neighbourhood: Neighbourhood::Moore,
+        larger_than_life: None,
+        rule_table: None,
+        topology: None,
+    };
+
+    /// Rule of a .rule file, it is named after the table
+    pub fn from_table(rule_table: RuleTable) -> Self {
+        Self {
+            states: rule_table.states(),
+            neighbourhood: rule_table.neighbourhood(),
+            rule_table: Some(Arc::new(rule_table)),
+            ..Self::EMPTY
+        }
+    }
+
+    /// 512-entry lookup table as bits, indexed by `neighbours | alive << 8`
+    pub fn table(&self) -> &[u32; 16] {
+        &self.table
+    }
+
+    /// B0 rules turn empty space alive, unbounded engines can't run them
+    pub fn has_b0(&self) -> bool {
+        if let Some(rule_table) = &self.rule_table {
+            let neighbours = [DEAD; 8];
+            return rule_table.next_state(DEAD, &neighbours[..self.neighbourhood.size() as usize])
+                != DEAD;
+        }
+        match &self.larger_than_life {
+            Some(larger_than_life) => larger_than_life.is_born(0),
+            None => self.get(false, 0),
+        }
+    }
+
+    /// Birth and survival neighbour counts as bits, `None` if the rule also depends on where
+    /// the neighbours are
+    pub fn outer_totalistic_counts(&self) -> Option<(u16, u16)> {
+        if self.larger_than_life.is_some() || self.rule_table.is_some() {
+            return None;
+        }
+        let mask = self.neighbourhood.mask();
+        let counts = |alive: bool| {
+            let mut counts = 0u16;
+            for count in 0..=mask.count_ones() {
+                let mut results = (0..=u8::MAX)
+                    .filter(|&neighbours| {
+                        neighbours & !mask == 0 && neighbours.count_ones() == count
+                    })
+                    .map(|neighbours| self.get(alive, neighbours));
+                let first = results.next()?;
+                if results.any(|result| result != first) {
+                    return None;
+                }
+                counts |= (first as u16) << count;
+            }
+            Some(counts)
+        };
+        Some((counts(false)?, counts(true)?))
+    }
+
+    /// Number of cell states including dead, 2 unless it is a Generations rule
+    pub fn states(&self) -> u16 {
+        self.states
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    /// Range and counts of a Larger than Life rule, `None` for range 1 rules
+    pub fn larger_than_life(&self) -> Option<&LargerThanLife> {
+        self.larger_than_life.as_ref()
+    }
+
+    /// Transitions of a .rule file
+    pub fn rule_table(&self) -> Option<&RuleTable> {
+        self.rule_table.as_deref()
+    }
+
+    /// Bounded grid of a `:T100,50` suffix
+    pub fn topology(&self) -> Option<&Topology> {
+        self.topology.as_ref()
+    }
+
+    pub fn set_topology(&mut self, topology: Option<Topology>) {
+        self.topology = topology;
+    }
+
+    /// Color the state is drawn in, `@COLORS` of a rule table override the defaults
+    pub fn color(&self, state: u8) -> [u8; 3] {
+        if let Some(color) = self
+            .rule_table
+            .as_ref()
+            .and_then(|rule_table| rule_table.color(state))
+        {
+            return color;
+        }
+        if state <= ALIVE {
+            return ALIVE_COLOR;
+        }
+        // States 2..states, the first one is 0 and the last one is 1
+        let decay = (state - 2) as f32 / (self.states.max(4) - 3) as f32;
+        let decay = decay.clamp(0., 1.);
+        std::array::from_fn(|i| {
+            let (first, last) = (DECAY_FIRST_COLOR[i] as f32, DECAY_LAST_COLOR[i] as f32);
+            (first + (last - first) * decay).round() as u8
+        })
+    }
+
+    /// Bit i of `neighbours` is set if the neighbour at `NEIGHBOURS[i]` is in state 1
+    pub fn next_state(&self, state: u8, neighbours: u8) -> u8 {
+        self.next_state_by(state, |alive| self.get(alive, neighbours))
+    }
+
+    /// Whether a two-state cell is alive in the next generation, see `next_state`
+    pub fn next_alive(&self, alive: bool, neighbours: u8) -> bool {
+        self.next_state(if alive { ALIVE } else { DEAD }, neighbours) == ALIVE
+    }
+
+    /// `count` is the number of cells in state 1 within the range, including the cell itself
+    ///
+    /// Only valid for Larger than Life rules.
+    pub fn next_state_in_range(&self, state: u8, count: u32) -> u8 {
+        let larger_than_life = self
+            .larger_than_life
+            .as_ref()
+            .expect("Not a Larger than Life rule");
+        let count = if state == ALIVE && !larger_than_life.includes_middle() {
+            // At least the cell itself is counted, unless the caller got it wrong
+            count.saturating_sub(1)
+        } else {
+            count
+        };
+        self.next_state_by(state, |alive| {
+            if alive {
+                larger_than_life.survives(count)
+            } else {
+                larger_than_life.is_born(count)
+            }
+        })
+    }
+
+    // `is_alive_next(alive)` tells if a dead or live cell is alive in the next generation
+    fn next_state_by(&self, state: u8, is_alive_next: impl Fn(bool) -> bool) -> u8 {
+        match state {
+            DEAD if is_alive_next(false) => ALIVE,
+            DEAD => DEAD,
+            ALIVE if is_alive_next(true) => ALIVE,
+            // Decaying, the last state is followed by dead
+            _ if state as u16 + 1 < self.states => state + 1,
+            _ => DEAD,
+        }
+    }
+
+    fn get(&self, alive: bool, neighbours: u8) -> bool {
+        let index = neighbours as usize | (alive as usize) << 8;
+        self.table[index / 32] >> (index % 32) & 1 != 0
+    }
+
+    fn set(&mut self, alive: bool, neighbours: u8) {
+        let index = neighbours as usize | (alive as usize) << 8;
+        self.table[index / 32] |= 1 << (index % 32);
+    }
+
+    // Birth or survival counts in Hensel notation
+    fn counts_to_string(&self, alive: bool) -> String {
+        let mut text = String::new();
+        if self.neighbourhood != Neighbourhood::Moore {
+            // The first `count` neighbours stand for all neighbourhoods of the count
+            let neighbours: Vec<u8> = (0..8)
+                .map(|bit| 1 << bit)
+                .filter(|bit| self.neighbourhood.mask() & bit != 0)
+                .collect();
+            for count in 0..=neighbours.len() {
+                if self.get(alive, neighbours[..count].iter().sum()) {
+                    text.push(char::from(b'0' + count as u8));
+                }
+            }
+            return text;
+        }
+
+        for count in 0..=8 {
+            let letters = hensel_letters(count);
+            if letters.is_empty() {
+                // 0 and 8 have a single neighbourhood
+                if self.get(alive, if count == 0 { 0 } else { u8::MAX }) {
+                    text.push(char::from(b'0' + count as u8));
+                }
+                continue;
+            }
+
+            let mut present = Vec::new();
+            let mut missing = Vec::new();
+            for (letter, neighbours) in letters {
+                if self.get(alive, neighbours) {
+                    present.push(letter);
+                } else {
+                    missing.push(letter);
+                }
+            }
+            if present.is_empty() {
+                continue;
+            }
+            text.push(char::from(b'0' + count as u8));
+            if missing.is_empty() {
+                continue;
+            }
+            if present.len() > missing.len() {
+                text.push('-');
+                text.extend(missing);
+            } else {
+                text.extend(present);
+            }
+        }
+        text
+    }
+}
+
+// (letter, representative neighbourhood) of the count, empty for 0 and 8
+fn hensel_letters(count: u32) -> Vec<(char, u8)> {
+    HENSEL_LETTERS
+        .iter()
+        .filter(|&&(letter_count, _, _)| letter_count == count || letter_count == 8 - count)
+        .map(|&(letter_count, letter, neighbours)| {
+            if letter_count == count {
+                (letter, neighbours)
+            } else {
+                (letter, !neighbours)
+            }
+        })
+        .filter(|&(_, neighbours)| neighbours.count_ones() == count)
+        .collect()
+}
+
+// All rotations and reflections of the neighbourhood
+fn symmetries(neighbours: u8) -> [u8; 8] {
+    // Mirrors around the north-south axis, bit i goes to bit (8 - i) % 8
+    let reflected = (0..8).fold(0u8, |reflected, bit| {
+        reflected | (neighbours >> bit & 1) << ((8 - bit) % 8)
+    });
+    let mut symmetries = [0; 8];
+    for quarter_turns in 0..4 {
+        symmetries[quarter_turns] = neighbours.rotate_left(2 * quarter_turns as u32);
+        symmetries[4 + quarter_turns] = reflected.rotate_left(2 * quarter_turns as u32);
+    }
+    symmetries
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(rule_table) = &self.rule_table {
+            write!(f, "{}", rule_table.name())?;
+        } else if let Some(larger_than_life) = &self.larger_than_life {
+            let (survival_min, survival_max) = larger_than_life.survival();
+            let (birth_min, birth_max) = larger_than_life.birth();
+            write!(
+                f,
+                "R{},C{},M{},S{survival_min}..{survival_max},B{birth_min}..{birth_max},N{}",
+                larger_than_life.range(),
+                if self.states > 2 { self.states } else { 0 },
+                larger_than_life.includes_middle() as u8,
+                if self.neighbourhood == Neighbourhood::VonNeumann {
+                    'N'
+                } else {
+                    'M'
+                },
+            )?;
+        } else {
+            write!(
+                f,
+                "B{}/S{}",
+                self.counts_to_string(false),
+                self.counts_to_string(true)
+            )?;
+            if self.states > 2 {
+                write!(f, "/C{}", self.states)?;
+            }
+            write!(f, "{}", self.neighbourhood.suffix())?;
+        }
+        if let Some(topology) = &self.topology {
+            write!(f, "{topology}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleParseError;
+
+    /// Parses `B3/S23`, `b3s23` and the older survival-first `23/3`, counts may have Hensel letters
+    ///
+    /// Generations rules add the state count, `B2/S/C3` or `/2/3`.
+    /// A `V` or `H` suffix selects the von Neumann or hexagonal neighbourhood.
+    /// Larger than Life rules are in Golly's notation, `R5,C0,M1,S34..58,B34..45,NM`.
+    /// Any of them can end with the bounded grid of Golly's suffix, `B3/S23:T100,50`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            return Err(RuleParseError::new(text, 1, "rule is empty"));
+        }
+        // Columns are reported in the untrimmed text
+        let offset = text.len() - text.trim_start().len();
+        let mut chars: Vec<(usize, char)> = trimmed
+            .chars()
+            .enumerate()
+            .map(|(index, character)| (offset + index + 1, character))
+            .collect();
+
+        let topology = match chars.iter().position(|&(_, character)| character == ':') {
+            Some(colon) => {
+                let topology = topology::parse(text, &chars[colon..])?;
+                chars.truncate(colon);
+                Some(topology)
+            }
+            None => None,
+        };
+        let mut rule = parse_rule(text, chars)?;
+        rule.topology = topology;
+        Ok(rule)
+    }
+}
+
+// Rule without the topology suffix
+fn parse_rule(text: &str, mut chars: Vec<(usize, char)>) -> Result<Rule, RuleParseError> {
+    let mut rule = Rule::EMPTY;
+    if let Some((_, 'R' | 'r')) = chars.first() {
+        let (larger_than_life, states, neighbourhood) = larger_than_life::parse(text, &chars)?;
+        rule.larger_than_life = Some(larger_than_life);
+        rule.states = states;
+        rule.neighbourhood = neighbourhood;
+        return Ok(rule);
+    }
+
+    let end_column = chars.last().map_or(1, |&(column, _)| column + 1);
+    rule.neighbourhood = match chars.last() {
+        Some((_, 'V' | 'v')) => Neighbourhood::VonNeumann,
+        Some((_, 'H' | 'h')) => Neighbourhood::Hexagonal,
+        _ => Neighbourhood::Moore,
+    };
+    if rule.neighbourhood != Neighbourhood::Moore {
+        chars.pop();
+    }
+
+    let has_sections = chars
+        .iter()
+        .any(|(_, character)| matches!(character, 'B' | 'b' | 'S' | 's'));
+    if has_sections {
+        parse_birth_survival(text, &chars, rule)
+    } else {
+        parse_survival_birth(text, &chars, end_column, rule)
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::iter::Copied<std::slice::Iter<'a, (usize, char)>>>;
+
+// `B3/S23/C3`, sections in any order, the slashes are optional
+fn parse_birth_survival(
+    text: &str,
+    chars: &[(usize, char)],
+    mut rule: Rule,
+) -> Result<Rule, RuleParseError> {
+    let mut seen_sections = Vec::new();
+    let mut chars = chars.iter().copied().peekable();
+    while let Some((column, character)) = chars.next() {
+        let section = character.to_ascii_uppercase();
+        if !matches!(section, 'B' | 'S' | 'C' | 'G') {
+            return Err(RuleParseError::unexpected(
+                text,
+                column,
+                character,
+                "expected 'B', 'S' or 'C'",
+            ));
+        }
+        // C and G both give the number of states
+        let section = if section == 'G' { 'C' } else { section };
+        if seen_sections.contains(&section) {
+            return Err(RuleParseError::new(
+                text,
+                column,
+                format!("'{character}' appears twice"),
+            ));
+        }
+        seen_sections.push(section);
+
+        if section == 'C' {
+            rule.states = parse_states(text, &mut chars, column + 1)?;
+        } else {
+            // Lowercase c is a Hensel letter inside the counts
+            parse_counts(text, &mut chars, section == 'S', &mut rule, |character| {
+                matches!(character, '/' | 'B' | 'b' | 'S' | 's' | 'C' | 'G')
+            })?;
+        }
+        chars.next_if(|&(_, character)| character == '/');
+    }
+    Ok(rule)
+}
+
+// `23/3/4`, survival counts first, then birth counts and the optional number of states
+// `end_column` is right after the rule
+fn parse_survival_birth(
+    text: &str,
+    chars: &[(usize, char)],
+    end_column: usize,
+    mut rule: Rule,
+) -> Result<Rule, RuleParseError> {
+    let mut chars = chars.iter().copied().peekable();
+    parse_counts(text, &mut chars, true, &mut rule, |character| {
+        character == '/'
+    })?;
+    if chars.next().is_none() {
+        return Err(RuleParseError::new(
+            text,
+            end_column,
+            "expected '/' between survival and birth counts",
+        ));
+    }
+    parse_counts(text, &mut chars, false, &mut rule, |character| {
+        character == '/'
+    })?;
+    if let Some((column, character)) = chars.next() {
+        // A trailing slash doesn't start a number of states
+        if chars.peek().is_none() {
+            return Err(RuleParseError::unexpected(
+                text,
+                column,
+                character,
+                "expected the number of states",
+            ));
+        }
+        rule.states = parse_states(text, &mut chars, column + 1)?;
+    }
+    if let Some((column, character)) = chars.next() {
+        return Err(RuleParseError::unexpected(
+            text,
+            column,
+            character,
+            "expected the end of the rule",
+        ));
+    }
+    Ok(rule)
+}
+
+// Next character, case-insensitive
+fn expect(
+    text: &str,
+    chars: &mut Chars,
+    end_column: usize,
+    expected: char,
+) -> Result<(), RuleParseError> {
+    match chars.next() {
+        Some((_, character)) if character.to_ascii_uppercase() == expected => Ok(()),
+        Some((column, character)) => Err(RuleParseError::unexpected(
+            text,
+            column,
+            character,
+            &format!("expected '{expected}'"),
+        )),
+        None => Err(RuleParseError::new(
+            text,
+            end_column,
+            format!("expected '{expected}'"),
+        )),
+    }
+}
+
+// Decimal number and the column it starts at
+fn parse_number(
+    text: &str,
+    chars: &mut Chars,
+    end_column: usize,
+) -> Result<(u32, usize), RuleParseError> {
+    let column = chars.peek().map_or(end_column, |&(column, _)| column);
+    let mut digits = String::new();
+    while let Some((_, character)) = chars.next_if(|(_, character)| character.is_ascii_digit()) {
+        digits.push(character);
+    }
+    if digits.is_empty() {
+        return Err(match chars.peek() {
+            Some(&(column, character)) => {
+                RuleParseError::unexpected(text, column, character, "expected a number")
+            }
+            None => RuleParseError::new(text, column, "expected a number"),
+        });
+    }
+    let number = digits
+        .parse()
+        .map_err(|_| RuleParseError::new(text, column, "number is too large"))?;
+    Ok((number, column))
+}
+
+// Number of states of a Generations rule, `column` is where it should start
+fn parse_states(text: &str, chars: &mut Chars, column: usize) -> Result<u16, RuleParseError> {
+    let mut digits = String::new();
+    while let Some((_, character)) = chars.next_if(|(_, character)| character.is_ascii_digit()) {
+        digits.push(character);
+    }
+    if digits.is_empty() {
+        return Err(match chars.peek() {
+            Some(&(column, character)) => {
+                RuleParseError::unexpected(text, column, character, "expected the number of states")
+            }
+            None => RuleParseError::new(text, column, "expected the number of states"),
+        });
+    }
+    match digits.parse() {
+        Ok(states @ 2..=MAX_STATES) => Ok(states),
+        _ => Err(RuleParseError::new(
+            text,
+            column,
+            format!("number of states must be in 2..={MAX_STATES}"),
+        )),
+    }
+}
+
+// Digits with optional Hensel letters, `3-ai` excludes the letters, until `is_end` or the end
+fn parse_counts(
+    text: &str,
+    chars: &mut Chars,
+    alive: bool,
+    rule: &mut Rule,
+    is_end: impl Fn(char) -> bool,
+) -> Result<(), RuleParseError> {
+    // Count being parsed with its letters
+    let mut count: Option<(u32, Vec<char>)> = None;
+    let mut excluded = false;
+    let mut end_column = 0;
+    let max_count = char::from(b'0' + rule.neighbourhood.size() as u8);
+    while let Some(&(column, character)) = chars.peek() {
+        if is_end(character) {
+            break;
+        }
+        chars.next();
+        end_column = column + 1;
+
+        // Only the Moore neighbourhood has Hensel letters
+        let letters = match &count {
+            Some((count, _)) if rule.neighbourhood == Neighbourhood::Moore => {
+                hensel_letters(*count)
+            }
+            _ => Vec::new(),
+        };
+        match (character, &mut count) {
+            ('0'..='8', _) if character <= max_count => {
+                if let Some((count, letters)) = count.take() {
+                    apply_count(text, column, count, &letters, excluded, alive, rule)?;
+                }
+                count = Some((character as u32 - '0' as u32, Vec::new()));
+                excluded = false;
+            }
+            ('-', Some((_, count_letters)))
+                if !letters.is_empty() && count_letters.is_empty() && !excluded =>
+            {
+                excluded = true
+            }
+            (_, Some((_, count_letters)))
+                if letters.iter().any(|&(letter, _)| letter == character) =>
+            {
+                count_letters.push(character)
+            }
+            _ => {
+                let expected = match &count {
+                    Some((count, _)) if !letters.is_empty() => {
+                        format!("expected a neighbour count 0-8 or a Hensel letter of {count}")
+                    }
+                    _ => format!("expected a neighbour count 0-{max_count}"),
+                };
+                return Err(RuleParseError::unexpected(
+                    text, column, character, &expected,
+                ));
+            }
+        }
+    }
+    if let Some((count, letters)) = count {
+        apply_count(text, end_column, count, &letters, excluded, alive, rule)?;
+    }
+    Ok(())
+}
+
+// Marks the neighbourhoods of the count and letters, `column` is right after them
+fn apply_count(
+    text: &str,
+    column: usize,
+    count: u32,
+    letters: &[char],
+    excluded: bool,
+    alive: bool,
+    rule: &mut Rule,
+) -> Result<(), RuleParseError> {
+    if excluded && letters.is_empty() {
+        return Err(RuleParseError::new(
+            text,
+            column,
+            "expected a Hensel letter after '-'",
+        ));
+    }
+    let letter_neighbours: Vec<u8> = hensel_letters(count)
+        .into_iter()
+        .filter(|(letter, _)| letters.contains(letter))
+        .flat_map(|(_, neighbours)| symmetries(neighbours))
+        .collect();
+    for neighbours in 0..=u8::MAX {
+        if (neighbours & rule.neighbourhood.mask()).count_ones() != count {
+            continue;
+        }
+        let included = letters.is_empty() || letter_neighbours.contains(&neighbours) != excluded;
+        if included {
+            rule.set(alive, neighbours);
+        }
+    }
+    Ok(())
+}
+
+/// Malformed rule string, the column is 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleParseError {
+    pub rule: String,
+    pub column: usize,
+    pub message: String,
+}
+
+impl RuleParseError {
+    fn new(rule: &str, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            rule: rule.to_string(),
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn unexpected(rule: &str, column: usize, character: char, expected: &str) -> Self {
+        Self::new(
+            rule,
+            column,
+            format!("unexpected '{character}', {expected}"),
+        )
+    }
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rule \"{}\", column {}: {}",
+            self.rule, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for RuleParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::rule::topology::TopologyKind;
+
+    #[test]
+    fn parses_notations() {
+        let cases = [
+            ("B3/S23", "B3/S23"),
+            ("b3s23", "B3/S23"),
+            ("S23/B3", "B3/S23"),
+            ("23/3", "B3/S23"),
+            ("B36/S23", "B36/S23"),
+            ("B3678/S34678", "B3678/S34678"),
+            ("B2/S", "B2/S"),
+            ("/2", "B2/S"),
+            (" B1357/S1357 ", "B1357/S1357"),
+        ];
+        for (text, expected) in cases {
+            let rule: Rule = text.parse().unwrap();
+            assert_eq!(rule.to_string(), expected, "{text}");
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+        assert_eq!("B3/S23".parse::<Rule>().unwrap(), Rule::LIFE);
+    }
+
+    #[test]
+    fn applies_counts() {
+        let highlife: Rule = "B36/S23".parse().unwrap();
+        assert!(highlife.next_alive(false, 0b0011_1111));
+        assert!(!highlife.next_alive(true, 0b1110_0111));
+        assert!(highlife.next_alive(true, 0b1000_0001));
+        assert!(!highlife.next_alive(false, 0b0001_0001));
+        assert!(!highlife.has_b0());
+        assert!("B0/S8".parse::<Rule>().unwrap().has_b0());
+    }
+
+    #[test]
+    fn hensel_letters_partition_neighbourhoods() {
+        let mut classes = [None; 256];
+        for count in 0..=8 {
+            let letters = hensel_letters(count);
+            let representatives = if letters.is_empty() {
+                vec![('-', if count == 0 { 0 } else { u8::MAX })]
+            } else {
+                letters
+            };
+            for (letter, representative) in representatives {
+                for neighbours in symmetries(representative) {
+                    let class = classes[neighbours as usize].get_or_insert((count, letter));
+                    assert_eq!(*class, (count, letter), "{neighbours:08b}");
+                }
+            }
+        }
+        assert!(classes.iter().all(Option::is_some));
+        assert_eq!(hensel_letters(4).len(), 13);
+        assert_eq!(hensel_letters(5).len(), 10);
+    }
+
+    #[test]
+    fn parses_hensel_notation() {
+        let cases = [
+            ("B3/S2-i34q", "B3/S2-i34q"),
+            ("b2-a/s12", "B2-a/S12"),
+            ("B2ce3aik/S1e2a", "B2ce3kai/S1e2a"),
+            ("B3cekainyqjr/S2cekain3", "B3/S23"),
+            ("B4-cekainyqjrtwz/S", "B/S"),
+            ("B2-cek/S", "B2ain/S"),
+            ("B2aikn/S", "B2-ce/S"),
+            ("S2-i34q/B3", "B3/S2-i34q"),
+            ("2-i34q/3", "B3/S2-i34q"),
+        ];
+        for (text, expected) in cases {
+            let rule: Rule = text.parse().unwrap();
+            assert_eq!(rule.to_string(), expected, "{text}");
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+        assert_eq!(
+            "B3cekainyqjr/S2cekain3".parse::<Rule>().unwrap(),
+            Rule::LIFE
+        );
+    }
+
+    #[test]
+    fn applies_hensel_letters() {
+        // Every cell of a block has three neighbours around a corner, 3a
+        let block_killer: Rule = "B3/S23-a".parse().unwrap();
+        assert!(!block_killer.next_alive(true, 0b0001_1100));
+        assert!(Rule::LIFE.next_alive(true, 0b0001_1100));
+        // The middle cell of a blinker has two opposite neighbours, 2i
+        let tlife: Rule = "B3/S2-i34q".parse().unwrap();
+        assert!(!tlife.next_alive(true, 0b0001_0001));
+        assert!(!tlife.next_alive(true, 0b0100_0100));
+        assert!(tlife.next_alive(true, 0b0000_0011));
+        // 5c is the complement of 3c, NE, SE and SW dead
+        let five_corners: Rule = "B5c/S".parse().unwrap();
+        assert!(five_corners.next_alive(false, !0b0010_1010));
+        assert!(five_corners.next_alive(false, !0b1010_1000));
+        assert!(!five_corners.next_alive(false, !0b0001_0101));
+    }
+
+    #[test]
+    fn parses_generations() {
+        let cases = [
+            ("B2/S/C3", "B2/S/C3", 3),
+            ("b2/s/c3", "B2/S/C3", 3),
+            ("/2/3", "B2/S/C3", 3),
+            ("345/2/4", "B2/S345/C4", 4),
+            ("B2c/S/G5", "B2c/S/C5", 5),
+            ("B3/S23/C2", "B3/S23", 2),
+            ("B2/S/C256", "B2/S/C256", 256),
+        ];
+        for (text, expected, states) in cases {
+            let rule: Rule = text.parse().unwrap();
+            assert_eq!(rule.to_string(), expected, "{text}");
+            assert_eq!(rule.states(), states, "{text}");
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+    }
+
+    #[test]
+    fn decays_through_states() {
+        let star_wars: Rule = "345/2/4".parse().unwrap();
+        assert_eq!(star_wars.next_state(DEAD, 0b0000_0011), ALIVE);
+        assert_eq!(star_wars.next_state(ALIVE, 0b0000_0111), ALIVE);
+        assert_eq!(star_wars.next_state(ALIVE, 0b0000_0011), 2);
+        // Decaying cells ignore their neighbours
+        assert_eq!(star_wars.next_state(2, 0b0000_0111), 3);
+        assert_eq!(star_wars.next_state(3, 0b0000_0011), DEAD);
+        assert_eq!(Rule::LIFE.next_state(ALIVE, 0), DEAD);
+    }
+
+    #[test]
+    fn parses_neighbourhoods() {
+        let cases = [
+            ("B1/S1V", "B1/S1V", Neighbourhood::VonNeumann),
+            ("b13/s0234v", "B13/S0234V", Neighbourhood::VonNeumann),
+            ("S013/B2V", "B2/S013V", Neighbourhood::VonNeumann),
+            ("B2/S34H", "B2/S34H", Neighbourhood::Hexagonal),
+            ("34/2h", "B2/S34H", Neighbourhood::Hexagonal),
+            ("B2/S/C3H", "B2/S/C3H", Neighbourhood::Hexagonal),
+            ("B3/S23", "B3/S23", Neighbourhood::Moore),
+        ];
+        for (text, expected, neighbourhood) in cases {
+            let rule: Rule = text.parse().unwrap();
+            assert_eq!(rule.to_string(), expected, "{text}");
+            assert_eq!(rule.neighbourhood(), neighbourhood, "{text}");
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+    }
+
+    #[test]
+    fn ignores_cells_outside_of_the_neighbourhood() {
+        let von_neumann: Rule = "B1/SV".parse().unwrap();
+        assert_eq!(von_neumann.next_state(DEAD, 0b0000_0001), ALIVE);
+        assert_eq!(von_neumann.next_state(DEAD, 0b1010_1011), ALIVE);
+        assert_eq!(von_neumann.next_state(DEAD, 0b0000_0010), DEAD);
+        // North-east and south-west aren't hexagonal neighbours
+        let hexagonal: Rule = "B2/SH".parse().unwrap();
+        assert_eq!(hexagonal.next_state(DEAD, 0b1000_0001), ALIVE);
+        assert_eq!(hexagonal.next_state(DEAD, 0b0010_0010), DEAD);
+        assert_eq!(hexagonal.next_state(DEAD, 0b0010_0011), DEAD);
+    }
+
+    #[test]
+    fn parses_larger_than_life() {
+        let cases = [
+            ("R5,C0,M1,S34..58,B34..45,NM", "R5,C0,M1,S34..58,B34..45,NM"),
+            ("r4,c2,m1,s41..81,b41..81,nm", "R4,C0,M1,S41..81,B41..81,NM"),
+            ("R3,C4,M0,S2..9,B3..5,NN", "R3,C4,M0,S2..9,B3..5,NN"),
+            ("R10,C0,M1,S0..441,B0..0,NM", "R10,C0,M1,S0..441,B0..0,NM"),
+        ];
+        for (text, expected) in cases {
+            let rule: Rule = text.parse().unwrap();
+            assert_eq!(rule.to_string(), expected, "{text}");
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+
+        let bugs: Rule = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+        assert_eq!(bugs.larger_than_life().unwrap().range(), 5);
+        assert_eq!(bugs.states(), 2);
+        assert!(!bugs.has_b0());
+        assert!(Rule::LIFE.larger_than_life().is_none());
+        assert!("R1,C0,M0,S2..3,B0..3,NN".parse::<Rule>().unwrap().has_b0());
+    }
+
+    #[test]
+    fn parses_topologies() {
+        let cases = [
+            ("B3/S23:T100,50", "B3/S23:T100,50"),
+            ("b3/s23:p20,10", "B3/S23:P20,10"),
+            ("B36/S23:K30*,20", "B36/S23:K30*,20"),
+            ("B3/S23:K30,20*", "B3/S23:K30,20*"),
+            ("B2/S/C3:C8,8", "B2/S/C3:C8,8"),
+            ("B2/S34H:S40", "B2/S34H:S40"),
+            (
+                "R5,C0,M1,S34..58,B34..45,NM:T64,64",
+                "R5,C0,M1,S34..58,B34..45,NM:T64,64",
+            ),
+        ];
+        for (text, expected) in cases {
+            let rule: Rule = text.parse().unwrap();
+            assert_eq!(rule.to_string(), expected, "{text}");
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+
+        let rule: Rule = "B3/S23:K30*,20".parse().unwrap();
+        let topology = rule.topology().unwrap();
+        assert_eq!(
+            topology.kind(),
+            TopologyKind::KleinBottle {
+                twisted_columns: true
+            }
+        );
+        assert_eq!((topology.width(), topology.height()), (30, 20));
+        assert_eq!(Rule::LIFE.topology(), None);
+    }
+
+    #[test]
+    fn counts_cells_in_range() {
+        let bugs: Rule = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+        // The middle cell is part of the count
+        assert_eq!(bugs.next_state_in_range(ALIVE, 34), ALIVE);
+        assert_eq!(bugs.next_state_in_range(ALIVE, 33), DEAD);
+        assert_eq!(bugs.next_state_in_range(DEAD, 45), ALIVE);
+        assert_eq!(bugs.next_state_in_range(DEAD, 46), DEAD);
+        let without_middle: Rule = "R2,C3,M0,S3..4,B3..3,NN".parse().unwrap();
+        assert_eq!(without_middle.next_state_in_range(ALIVE, 4), ALIVE);
+        assert_eq!(without_middle.next_state_in_range(ALIVE, 3), 2);
+        assert_eq!(without_middle.next_state_in_range(2, 5), DEAD);
+        assert_eq!(without_middle.next_state_in_range(ALIVE, 0), 2);
+    }
+
+    #[test]
+    fn reports_unexpected_characters() {
+        let cases = [
+            ("B3/S2x", 6, "unexpected 'x'"),
+            ("B9/S23", 2, "unexpected '9'"),
+            ("Q3/S23", 1, "unexpected 'Q'"),
+            ("B3/B6", 4, "'B' appears twice"),
+            ("23/3/", 5, "unexpected '/'"),
+            ("23/3/4/", 7, "unexpected '/'"),
+            ("23", 3, "expected '/'"),
+            ("", 1, "rule is empty"),
+            ("B2x/S23", 3, "unexpected 'x'"),
+            ("B0c/S23", 3, "unexpected 'c'"),
+            ("B4t/S1t", 7, "unexpected 't'"),
+            ("B3-/S23", 4, "expected a Hensel letter after '-'"),
+            ("B3--a/S23", 4, "unexpected '-'"),
+            ("Ba/S23", 2, "unexpected 'a'"),
+            ("B2/S/C", 7, "expected the number of states"),
+            ("B2/S/Cx", 7, "unexpected 'x'"),
+            ("B2/S/C1", 7, "number of states must be in 2..=256"),
+            ("B2/S/C257", 7, "number of states must be in 2..=256"),
+            ("B2/S/C3/C4", 9, "'C' appears twice"),
+            ("345/2/4x", 8, "unexpected 'x'"),
+            ("B5/S4V", 2, "expected a neighbour count 0-4"),
+            ("B2/S7H", 5, "expected a neighbour count 0-6"),
+            ("B2a/SV", 3, "unexpected 'a'"),
+            ("V", 2, "expected '/'"),
+            ("R0,C0,M1,S1..2,B1..2,NM", 2, "range must be in 1..=50"),
+            ("R51,C0,M1,S1..2,B1..2,NM", 2, "range must be in 1..=50"),
+            (
+                "R2,C1,M1,S1..2,B1..2,NM",
+                5,
+                "number of states must be 0 or in 2..=256",
+            ),
+            ("R2,C0,M2,S1..2,B1..2,NM", 8, "middle must be 0 or 1"),
+            ("R2,C0,M1,S3..2,B1..2,NM", 11, "minimum count is larger"),
+            ("R2,C0,M1,S1..26,B1..2,NM", 11, "counts must be at most 25"),
+            ("R2,C0,M1,S1..2,B1..14,NN", 17, "counts must be at most 13"),
+            ("R2,C0,M1,S1.2,B1..2,NM", 13, "unexpected '2', expected '.'"),
+            ("R2,C0,M1,S1..2,B1..2,NX", 23, "expected 'M' or 'N'"),
+            ("R2,C0,M1,S1..2,B1..2", 21, "expected ','"),
+            (
+                "R2,C0,M1,S1..2,B1..2,NM,",
+                24,
+                "expected the end of the rule",
+            ),
+            ("R2,C0,M1,Sx", 11, "expected a number"),
+            ("B3/S23:X10,10", 8, "expected 'P', 'T', 'K', 'C' or 'S'"),
+            ("B3/S23:", 8, "expected 'P', 'T', 'K', 'C' or 'S'"),
+            ("B3/S23:T0,10", 9, "size must be in 1..=65536"),
+            ("B3/S23:T10", 11, "expected ','"),
+            (
+                "B3/S23:K10,10",
+                14,
+                "needs '*' after either the width or the height",
+            ),
+            (
+                "B3/S23:K10*,10*",
+                16,
+                "needs '*' after either the width or the height",
+            ),
+            (
+                "B3/S23:T10*,10",
+                15,
+                "only Klein bottles have twisted edges",
+            ),
+            ("B3/S23:S10,10", 11, "expected the end of the rule"),
+        ];
+        for (text, column, message) in cases {
+            let error = text.parse::<Rule>().unwrap_err();
+            assert_eq!(error.column, column, "{text}: {error}");
+            assert!(error.message.contains(message), "{text}: {error}");
+        }
+    }
+}
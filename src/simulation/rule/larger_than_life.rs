@@ -0,0 +1,176 @@
+// Larger than Life, outer-totalistic rules of a larger range in Golly's notation
+
+use crate::simulation::rule::{
+    Chars, MAX_STATES, Neighbourhood, RuleParseError, expect, parse_number,
+};
+
+/// Largest supported range, the neighbourhood is up to 101x101 cells
+pub const MAX_RANGE: u8 = 50;
+
+/// Birth and survival need a number of live cells within the range, bounds are inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LargerThanLife {
+    range: u8,
+    // The cell counts itself
+    middle: bool,
+    survival: (u16, u16),
+    birth: (u16, u16),
+}
+
+impl LargerThanLife {
+    pub fn range(&self) -> u8 {
+        self.range
+    }
+
+    pub fn includes_middle(&self) -> bool {
+        self.middle
+    }
+
+    pub fn survival(&self) -> (u16, u16) {
+        self.survival
+    }
+
+    pub fn birth(&self) -> (u16, u16) {
+        self.birth
+    }
+
+    pub fn survives(&self, count: u32) -> bool {
+        (self.survival.0 as u32..=self.survival.1 as u32).contains(&count)
+    }
+
+    pub fn is_born(&self, count: u32) -> bool {
+        (self.birth.0 as u32..=self.birth.1 as u32).contains(&count)
+    }
+}
+
+// Cells within the range including the middle one
+fn neighbourhood_size(range: u32, neighbourhood: Neighbourhood) -> u32 {
+    match neighbourhood {
+        Neighbourhood::VonNeumann => 2 * range * (range + 1) + 1,
+        _ => (2 * range + 1).pow(2),
+    }
+}
+
+/// `R5,C0,M1,S34..58,B34..45,NM`: range, states, middle, survival, birth and neighbourhood
+///
+/// C0 and C2 are both two states, `NM` is the Moore and `NN` the von Neumann neighbourhood.
+pub(super) fn parse(
+    text: &str,
+    chars: &[(usize, char)],
+) -> Result<(LargerThanLife, u16, Neighbourhood), RuleParseError> {
+    let end_column = chars.last().map_or(1, |&(column, _)| column + 1);
+    let mut chars = chars.iter().copied().peekable();
+
+    expect(text, &mut chars, end_column, 'R')?;
+    let (range, range_column) = parse_number(text, &mut chars, end_column)?;
+    if !(1..=MAX_RANGE as u32).contains(&range) {
+        return Err(RuleParseError::new(
+            text,
+            range_column,
+            format!("range must be in 1..={MAX_RANGE}"),
+        ));
+    }
+
+    expect(text, &mut chars, end_column, ',')?;
+    expect(text, &mut chars, end_column, 'C')?;
+    let (states, states_column) = parse_number(text, &mut chars, end_column)?;
+    let states = match states {
+        0 => 2,
+        _ if (2..=MAX_STATES as u32).contains(&states) => states as u16,
+        _ => {
+            return Err(RuleParseError::new(
+                text,
+                states_column,
+                format!("number of states must be 0 or in 2..={MAX_STATES}"),
+            ));
+        }
+    };
+
+    expect(text, &mut chars, end_column, ',')?;
+    expect(text, &mut chars, end_column, 'M')?;
+    let (middle, middle_column) = parse_number(text, &mut chars, end_column)?;
+    if middle > 1 {
+        return Err(RuleParseError::new(
+            text,
+            middle_column,
+            "middle must be 0 or 1",
+        ));
+    }
+
+    expect(text, &mut chars, end_column, ',')?;
+    expect(text, &mut chars, end_column, 'S')?;
+    let (survival, survival_column) = parse_bounds(text, &mut chars, end_column)?;
+    expect(text, &mut chars, end_column, ',')?;
+    expect(text, &mut chars, end_column, 'B')?;
+    let (birth, birth_column) = parse_bounds(text, &mut chars, end_column)?;
+
+    expect(text, &mut chars, end_column, ',')?;
+    expect(text, &mut chars, end_column, 'N')?;
+    let neighbourhood = match chars.next() {
+        Some((_, 'M' | 'm')) => Neighbourhood::Moore,
+        Some((_, 'N' | 'n')) => Neighbourhood::VonNeumann,
+        Some((column, character)) => {
+            return Err(RuleParseError::unexpected(
+                text,
+                column,
+                character,
+                "expected 'M' or 'N'",
+            ));
+        }
+        None => return Err(RuleParseError::new(text, end_column, "expected 'M' or 'N'")),
+    };
+    if let Some((column, character)) = chars.next() {
+        return Err(RuleParseError::unexpected(
+            text,
+            column,
+            character,
+            "expected the end of the rule",
+        ));
+    }
+
+    let size = neighbourhood_size(range, neighbourhood);
+    for (bounds, column) in [(survival, survival_column), (birth, birth_column)] {
+        if bounds.1 as u32 > size {
+            return Err(RuleParseError::new(
+                text,
+                column,
+                format!("counts must be at most {size}, the size of the neighbourhood"),
+            ));
+        }
+    }
+
+    let larger_than_life = LargerThanLife {
+        range: range as u8,
+        middle: middle == 1,
+        survival,
+        birth,
+    };
+    Ok((larger_than_life, states, neighbourhood))
+}
+
+// `34..58`, inclusive
+fn parse_bounds(
+    text: &str,
+    chars: &mut Chars,
+    end_column: usize,
+) -> Result<((u16, u16), usize), RuleParseError> {
+    let (min, column) = parse_number(text, chars, end_column)?;
+    expect(text, chars, end_column, '.')?;
+    expect(text, chars, end_column, '.')?;
+    let (max, _) = parse_number(text, chars, end_column)?;
+    if min > max {
+        return Err(RuleParseError::new(
+            text,
+            column,
+            "minimum count is larger than the maximum",
+        ));
+    }
+    // Anything above u16 is rejected against the neighbourhood size
+    Ok((
+        (
+            min.min(u16::MAX as u32) as u16,
+            max.min(u16::MAX as u32) as u16,
+        ),
+        column,
+    ))
+}
@@ -0,0 +1,715 @@
+// Golly's .rule files, transition tables of multi-state automata like WireWorld
+
+use crate::simulation::rule::{MAX_STATES, Neighbourhood};
+use anyhow::Context;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+// Transitions after variables and symmetries are expanded, more would not fit in memory
+const MAX_TRANSITIONS: usize = 1 << 16;
+
+// Bit s is set if state s is in the set
+type StateSet = [u64; 4];
+
+fn single_state(state: u8) -> StateSet {
+    let mut set = [0; 4];
+    set[state as usize / 64] |= 1 << (state % 64);
+    set
+}
+
+fn contains(set: &StateSet, state: u8) -> bool {
+    set[state as usize / 64] >> (state % 64) & 1 != 0
+}
+
+fn states_of(set: &StateSet) -> impl Iterator<Item = u8> + '_ {
+    (0..=u8::MAX).filter(|&state| contains(set, state))
+}
+
+/// `@TABLE` of a .rule file, the first matching transition gives the next state
+///
+/// Neighbours are clockwise from north like in Golly: N, NE, E, SE, S, SW, W, NW for the Moore,
+/// N, E, S, W for the von Neumann and N, E, SE, S, W, NW for the hexagonal neighbourhood.
+/// Cells that no transition matches keep their state.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RuleTable {
+    name: String,
+    states: u16,
+    neighbourhood: Neighbourhood,
+    // Next state of every transition
+    outputs: Vec<u8>,
+    // Bit t of word `(position * states + state) * words + t / 64` is set if transition t
+    // accepts the state at the position, position 0 is the cell and 1.. its neighbours
+    accepts: Vec<u64>,
+    words: usize,
+    // `@COLORS`, states without a color keep the default one
+    colors: Vec<(u8, [u8; 3])>,
+}
+
+impl RuleTable {
+    /// Name after `@RULE`, patterns refer to the rule by it
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn states(&self) -> u16 {
+        self.states
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    pub fn color(&self, state: u8) -> Option<[u8; 3]> {
+        self.colors
+            .iter()
+            .find(|(color_state, _)| *color_state == state)
+            .map(|&(_, color)| color)
+    }
+
+    /// `neighbours` are the states of the neighbours in the order of the neighbourhood
+    pub fn next_state(&self, state: u8, neighbours: &[u8]) -> u8 {
+        debug_assert_eq!(neighbours.len(), self.neighbourhood.size() as usize);
+        // Transitions are scanned 64 at a time, the first matching one wins
+        for word in 0..self.words {
+            let mut matches = self.accepts(0, state, word);
+            for (position, &neighbour) in neighbours.iter().enumerate() {
+                if matches == 0 {
+                    break;
+                }
+                matches &= self.accepts(position + 1, neighbour, word);
+            }
+            if matches != 0 {
+                return self.outputs[word * 64 + matches.trailing_zeros() as usize];
+            }
+        }
+        state
+    }
+
+    // Transitions `64 * word..` that accept the state at the position
+    fn accepts(&self, position: usize, state: u8, word: usize) -> u64 {
+        if state as u16 >= self.states {
+            return 0;
+        }
+        self.accepts[(position * self.states as usize + state as usize) * self.words + word]
+    }
+}
+
+/// Reads a .rule file
+pub fn load(path: &Path) -> anyhow::Result<RuleTable> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse(&text).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Table,
+    Colors,
+    // @NAMES, @ICONS and anything else
+    Other,
+}
+
+/// Parses the `@RULE`, `@TABLE` and `@COLORS` sections, other sections are skipped
+pub fn parse(text: &str) -> Result<RuleTable, RuleTableParseError> {
+    let mut name = None;
+    let mut section = None;
+    let mut table = TableParser::default();
+    let mut has_table = false;
+    let mut colors = Vec::new();
+    let mut gradient = None;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| RuleTableParseError::new(line_number, message);
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('@') {
+            let (keyword, rest) = header
+                .split_once(char::is_whitespace)
+                .unwrap_or((header, ""));
+            section = Some(match keyword {
+                "RULE" => {
+                    let rule_name = rest.trim();
+                    if rule_name.is_empty() {
+                        return Err(error("expected the rule name after @RULE".to_string()));
+                    }
+                    name = Some(rule_name.to_string());
+                    Section::Other
+                }
+                "TABLE" => {
+                    has_table = true;
+                    Section::Table
+                }
+                "COLORS" => Section::Colors,
+                "TREE" => return Err(error("@TREE rules are not supported".to_string())),
+                _ => Section::Other,
+            });
+            continue;
+        }
+
+        match section {
+            None => return Err(error("expected @RULE".to_string())),
+            Some(Section::Table) => table.parse_line(line, line_number).map_err(error)?,
+            Some(Section::Colors) => {
+                let numbers = line
+                    .split_whitespace()
+                    .map(|number| {
+                        number
+                            .parse::<u8>()
+                            .map_err(|_| error(format!("expected a number 0-255, not '{number}'")))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                match numbers[..] {
+                    [state, r, g, b] => colors.push((state, [r, g, b])),
+                    // Gradient over the live states
+                    [r1, g1, b1, r2, g2, b2] => gradient = Some(([r1, g1, b1], [r2, g2, b2])),
+                    _ => {
+                        return Err(error(
+                            "expected 'state r g b' or a gradient 'r1 g1 b1 r2 g2 b2'".to_string(),
+                        ));
+                    }
+                }
+            }
+            Some(Section::Other) => (),
+        }
+    }
+
+    let Some(name) = name else {
+        return Err(RuleTableParseError::new(1, "expected @RULE"));
+    };
+    if !has_table {
+        return Err(RuleTableParseError::new(
+            1,
+            "expected a @TABLE section, other kinds of rules are not supported",
+        ));
+    }
+    let Some(states) = table.states else {
+        return Err(RuleTableParseError::new(1, "n_states is missing"));
+    };
+
+    if let Some((symmetries, line)) = &table.symmetries
+        && symmetries != "permute"
+        && symmetry_group(table.neighbourhood, symmetries).is_none()
+    {
+        return Err(RuleTableParseError::new(
+            *line,
+            format!("unsupported symmetries '{symmetries}' for the neighborhood"),
+        ));
+    }
+
+    // Colors of single states come first, they win over the gradient
+    if let Some((first, last)) = gradient {
+        let live_states = states - 1;
+        for state in 1..states {
+            let t = if live_states > 1 {
+                (state - 1) as f32 / (live_states - 1) as f32
+            } else {
+                0.
+            };
+            let color = std::array::from_fn(|i| {
+                (first[i] as f32 + (last[i] as f32 - first[i] as f32) * t).round() as u8
+            });
+            colors.push((state as u8, color));
+        }
+    }
+
+    table.build(name, states, colors)
+}
+
+#[derive(Default)]
+struct TableParser {
+    states: Option<u16>,
+    neighbourhood: Neighbourhood,
+    // With the line it is on, checked against the neighbourhood at the end
+    symmetries: Option<(String, usize)>,
+    variables: HashMap<String, StateSet>,
+    // Inputs of the cell and its neighbours, the output and the line, before symmetries
+    transitions: Vec<(Vec<StateSet>, u8, usize)>,
+}
+
+impl TableParser {
+    fn parse_line(&mut self, line: &str, line_number: usize) -> Result<(), String> {
+        if let Some((key, value)) = line.split_once([':', '=']) {
+            let key = key.trim();
+            let value = value.trim();
+            if let Some(variable) = key.strip_prefix("var ") {
+                return self.parse_variable(variable.trim(), value);
+            }
+            if matches!(
+                key,
+                "n_states" | "num_states" | "neighborhood" | "symmetries"
+            ) {
+                if !self.transitions.is_empty() || !self.variables.is_empty() {
+                    return Err(format!("{key} must come before variables and transitions"));
+                }
+                match key {
+                    "neighborhood" => {
+                        self.neighbourhood = match value {
+                            "Moore" => Neighbourhood::Moore,
+                            "vonNeumann" => Neighbourhood::VonNeumann,
+                            "hexagonal" => Neighbourhood::Hexagonal,
+                            _ => return Err(format!("unsupported neighborhood '{value}'")),
+                        }
+                    }
+                    "symmetries" => self.symmetries = Some((value.to_string(), line_number)),
+                    _ => match value.parse() {
+                        Ok(states @ 2..=MAX_STATES) => self.states = Some(states),
+                        _ => return Err(format!("n_states must be in 2..={MAX_STATES}")),
+                    },
+                }
+                return Ok(());
+            }
+        }
+        self.parse_transition(line, line_number)
+    }
+
+    // `a={0,1,2}`, values are states or earlier variables
+    fn parse_variable(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let states = self.states()?;
+        if name.is_empty() || name.starts_with(|character: char| character.is_ascii_digit()) {
+            return Err(format!("invalid variable name '{name}'"));
+        }
+        let set = match value
+            .strip_prefix('{')
+            .and_then(|value| value.strip_suffix('}'))
+        {
+            Some(values) => self.parse_set(values, states)?,
+            None => self.parse_value(value, states)?,
+        };
+        self.variables.insert(name.to_string(), set);
+        Ok(())
+    }
+
+    fn parse_set(&self, values: &str, states: u16) -> Result<StateSet, String> {
+        let mut set = [0; 4];
+        for value in values.split(',') {
+            let value_set = self.parse_value(value.trim(), states)?;
+            for (word, value_word) in set.iter_mut().zip(value_set) {
+                *word |= value_word;
+            }
+        }
+        Ok(set)
+    }
+
+    // State or variable
+    fn parse_value(&self, value: &str, states: u16) -> Result<StateSet, String> {
+        if value.starts_with(|character: char| character.is_ascii_digit()) {
+            return match value.parse::<u16>() {
+                Ok(state) if state < states => Ok(single_state(state as u8)),
+                _ => Err(format!("expected a state 0-{}, not '{value}'", states - 1)),
+            };
+        }
+        self.variables
+            .get(value)
+            .copied()
+            .ok_or_else(|| format!("unknown variable '{value}'"))
+    }
+
+    // `0,1,2,..` or `012..` without commas if there are at most 10 states
+    fn parse_transition(&mut self, line: &str, line_number: usize) -> Result<(), String> {
+        let states = self.states()?;
+        let tokens: Vec<&str> = if states <= 10 && line.chars().all(|c| c.is_ascii_digit()) {
+            (0..line.len())
+                .map(|index| &line[index..index + 1])
+                .collect()
+        } else {
+            split_transition(line)?
+        };
+        let expected = self.neighbourhood.size() as usize + 2;
+        if tokens.len() != expected {
+            return Err(format!(
+                "expected {expected} states in a transition, found {}",
+                tokens.len()
+            ));
+        }
+
+        // Variables that appear more than once are bound, they take the same state everywhere
+        let mut occurrences: HashMap<&str, usize> = HashMap::new();
+        for &token in &tokens {
+            if self.variables.contains_key(token) {
+                *occurrences.entry(token).or_default() += 1;
+            }
+        }
+        let mut bound: Vec<&str> = Vec::new();
+        for &token in &tokens {
+            if occurrences.get(token).is_some_and(|&count| count > 1) && !bound.contains(&token) {
+                bound.push(token);
+            }
+        }
+        let output_token = tokens[tokens.len() - 1];
+        if self.variables.contains_key(output_token) && !bound.contains(&output_token) {
+            return Err(format!(
+                "output variable '{output_token}' doesn't appear in the inputs"
+            ));
+        }
+
+        let bound_values: Vec<Vec<u8>> = bound
+            .iter()
+            .map(|name| states_of(&self.variables[*name]).collect())
+            .collect();
+        if bound_values.iter().any(Vec::is_empty) {
+            return Ok(());
+        }
+        // Every combination of states of the bound variables
+        let mut indices = vec![0; bound.len()];
+        loop {
+            let mut sets = Vec::with_capacity(tokens.len());
+            for &token in &tokens {
+                let set = match bound.iter().position(|&name| name == token) {
+                    Some(variable) => single_state(bound_values[variable][indices[variable]]),
+                    None if token.starts_with('{') => {
+                        self.parse_set(&token[1..token.len() - 1], states)?
+                    }
+                    None => self.parse_value(token, states)?,
+                };
+                sets.push(set);
+            }
+            let output = sets.pop().expect("transition without an output");
+            let mut output_states = states_of(&output);
+            let (Some(output), None) = (output_states.next(), output_states.next()) else {
+                return Err("output must be a single state".to_string());
+            };
+            if self.transitions.len() == MAX_TRANSITIONS {
+                return Err(format!("more than {MAX_TRANSITIONS} transitions"));
+            }
+            self.transitions.push((sets, output, line_number));
+
+            // Next combination, the last variable changes fastest
+            let Some(variable) = (0..bound.len())
+                .rev()
+                .find(|&variable| indices[variable] + 1 < bound_values[variable].len())
+            else {
+                return Ok(());
+            };
+            indices[variable] += 1;
+            indices[variable + 1..].fill(0);
+        }
+    }
+
+    fn states(&self) -> Result<u16, String> {
+        self.states
+            .ok_or_else(|| "n_states must come before variables and transitions".to_string())
+    }
+
+    // Transitions of every symmetry as bitsets
+    fn build(
+        self,
+        name: String,
+        states: u16,
+        colors: Vec<(u8, [u8; 3])>,
+    ) -> Result<RuleTable, RuleTableParseError> {
+        let neighbours = self.neighbourhood.size() as usize;
+        let symmetries = match self
+            .symmetries
+            .as_ref()
+            .map(|(symmetries, _)| symmetries.as_str())
+        {
+            Some("permute") => None,
+            symmetries => Some(
+                symmetry_group(self.neighbourhood, symmetries.unwrap_or("none"))
+                    .expect("symmetries are checked while parsing"),
+            ),
+        };
+
+        let mut transitions = Vec::new();
+        for (inputs, output, line) in self.transitions {
+            let mut seen = HashSet::new();
+            let mut push = |variant: Vec<StateSet>| {
+                if seen.insert(variant.clone()) {
+                    transitions.push((variant, output));
+                }
+            };
+            match &symmetries {
+                Some(permutations) => {
+                    for permutation in permutations {
+                        let mut variant = vec![inputs[0]];
+                        variant.extend(permutation.iter().map(|&from| inputs[from + 1]));
+                        push(variant);
+                    }
+                }
+                // Every distinct order of the neighbours
+                None => {
+                    let mut order = inputs[1..].to_vec();
+                    order.sort();
+                    loop {
+                        let mut variant = vec![inputs[0]];
+                        variant.extend_from_slice(&order);
+                        push(variant);
+                        if !next_permutation(&mut order) {
+                            break;
+                        }
+                    }
+                }
+            }
+            if transitions.len() > MAX_TRANSITIONS {
+                return Err(RuleTableParseError::new(
+                    line,
+                    format!("more than {MAX_TRANSITIONS} transitions with the symmetries"),
+                ));
+            }
+        }
+
+        let words = transitions.len().div_ceil(64).max(1);
+        let mut accepts = vec![0; (neighbours + 1) * states as usize * words];
+        for (transition, (inputs, _)) in transitions.iter().enumerate() {
+            for (position, set) in inputs.iter().enumerate() {
+                for state in states_of(set).take_while(|&state| (state as u16) < states) {
+                    let index = (position * states as usize + state as usize) * words;
+                    accepts[index + transition / 64] |= 1 << (transition % 64);
+                }
+            }
+        }
+
+        Ok(RuleTable {
+            name,
+            states,
+            neighbourhood: self.neighbourhood,
+            outputs: transitions.iter().map(|&(_, output)| output).collect(),
+            accepts,
+            words,
+            colors,
+        })
+    }
+}
+
+// Splits at commas outside of `{..}`
+fn split_transition(line: &str) -> Result<Vec<&str>, String> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_braces = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '{' if !in_braces => in_braces = true,
+            '}' if in_braces => in_braces = false,
+            '{' | '}' => return Err(format!("unexpected '{character}'")),
+            ',' if !in_braces => {
+                tokens.push(line[start..index].trim());
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    if in_braces {
+        return Err("expected '}'".to_string());
+    }
+    tokens.push(line[start..].trim());
+    Ok(tokens)
+}
+
+// Permutations of the neighbours, `permutation[i]` is the neighbour that moves to i
+// `None` for symmetries that are unknown or don't fit the neighbourhood
+fn symmetry_group(neighbourhood: Neighbourhood, symmetries: &str) -> Option<Vec<Vec<usize>>> {
+    let size = neighbourhood.size() as usize;
+    let rotate = |by: usize| -> Vec<usize> { (0..size).map(|i| (i + by) % size).collect() };
+    let reflect: Vec<usize> = (0..size).map(|i| (size - i) % size).collect();
+    let generators = match (neighbourhood, symmetries) {
+        (_, "none") => vec![],
+        (Neighbourhood::Moore, "rotate4") => vec![rotate(2)],
+        (Neighbourhood::Moore, "rotate4reflect") => vec![rotate(2), reflect],
+        (Neighbourhood::Moore, "rotate8") => vec![rotate(1)],
+        (Neighbourhood::Moore, "rotate8reflect") => vec![rotate(1), reflect],
+        (Neighbourhood::VonNeumann, "rotate4") => vec![rotate(1)],
+        (Neighbourhood::VonNeumann, "rotate4reflect") => vec![rotate(1), reflect],
+        (Neighbourhood::Moore | Neighbourhood::VonNeumann, "reflect_horizontal") => vec![reflect],
+        (Neighbourhood::Hexagonal, "rotate2") => vec![rotate(3)],
+        (Neighbourhood::Hexagonal, "rotate3") => vec![rotate(2)],
+        (Neighbourhood::Hexagonal, "rotate6") => vec![rotate(1)],
+        (Neighbourhood::Hexagonal, "rotate6reflect") => vec![rotate(1), reflect],
+        _ => return None,
+    };
+
+    // Closure under composition, starting from the identity
+    let mut group = vec![(0..size).collect::<Vec<_>>()];
+    let mut next = 0;
+    while next < group.len() {
+        for generator in &generators {
+            let composed: Vec<usize> = generator.iter().map(|&i| group[next][i]).collect();
+            if !group.contains(&composed) {
+                group.push(composed);
+            }
+        }
+        next += 1;
+    }
+    Some(group)
+}
+
+// Lexicographically next permutation, false after the last one
+fn next_permutation<T: Ord>(items: &mut [T]) -> bool {
+    let Some(pivot) = (1..items.len()).rev().find(|&i| items[i - 1] < items[i]) else {
+        return false;
+    };
+    let successor = (pivot..items.len())
+        .rev()
+        .find(|&i| items[i] > items[pivot - 1])
+        .expect("pivot without a successor");
+    items.swap(pivot - 1, successor);
+    items[pivot..].reverse();
+    true
+}
+
+/// Malformed .rule file, the line is 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleTableParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl RuleTableParseError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RuleTableParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for RuleTableParseError {}
+
+#[cfg(test)]
+pub(in crate::simulation) mod tests {
+    use super::*;
+
+    pub(in crate::simulation) const WIREWORLD: &str = "\
+@RULE WireWorld
+# 0 empty, 1 electron head, 2 electron tail, 3 wire
+
+@TABLE
+n_states:4
+neighborhood:Moore
+symmetries:permute
+var a={0,1,2,3}
+var b=a
+var c=a
+var d=a
+var e=a
+var f=a
+var g=a
+var h=a
+var i={0,2,3}
+var j=i
+var k=i
+var l=i
+var m=i
+var n=i
+var o=i
+1,a,b,c,d,e,f,g,h,2
+2,a,b,c,d,e,f,g,h,3
+# Wire with one or two heads around it
+3,1,i,j,k,l,m,n,o,1
+3,1,1,j,k,l,m,n,o,1
+
+@COLORS
+1 255 255 255
+2 0 128 255
+3 255 128 0
+";
+
+    #[test]
+    fn parses_wireworld() {
+        let rule_table = parse(WIREWORLD).unwrap();
+        assert_eq!(rule_table.name(), "WireWorld");
+        assert_eq!(rule_table.states(), 4);
+        assert_eq!(rule_table.neighbourhood(), Neighbourhood::Moore);
+        // Distinct orders of the neighbours, 1 + 1 + 8 + 28
+        assert_eq!(rule_table.outputs.len(), 38);
+        assert_eq!(rule_table.color(2), Some([0, 128, 255]));
+        assert_eq!(rule_table.color(0), None);
+
+        assert_eq!(rule_table.next_state(1, &[3, 3, 0, 0, 0, 0, 2, 0]), 2);
+        assert_eq!(rule_table.next_state(2, &[0; 8]), 3);
+        assert_eq!(rule_table.next_state(3, &[0, 0, 0, 1, 0, 0, 0, 0]), 1);
+        assert_eq!(rule_table.next_state(3, &[2, 1, 0, 0, 0, 0, 1, 0]), 1);
+        // Three heads, no transition matches
+        assert_eq!(rule_table.next_state(3, &[1, 1, 1, 0, 0, 0, 0, 0]), 3);
+        assert_eq!(rule_table.next_state(0, &[1; 8]), 0);
+    }
+
+    #[test]
+    fn applies_symmetries_and_bound_variables() {
+        let text = "\
+@RULE Test
+@TABLE
+n_states:3
+neighborhood:vonNeumann
+symmetries:rotate4
+var a={1,2}
+var b={0,1,2}
+# The same state to the north and east, anything to the south
+0,a,a,b,0,a
+020001
+";
+        let rule_table = parse(text).unwrap();
+        // Two values of the bound variable, four rotations each, and the compact transition
+        assert_eq!(rule_table.outputs.len(), 2 * 4 + 4);
+        assert_eq!(rule_table.next_state(0, &[2, 2, 1, 0]), 2);
+        assert_eq!(rule_table.next_state(0, &[0, 1, 1, 2]), 1);
+        assert_eq!(rule_table.next_state(0, &[1, 2, 0, 0]), 0);
+        assert_eq!(rule_table.next_state(0, &[0, 0, 0, 2]), 1);
+        assert_eq!(rule_table.next_state(1, &[1, 1, 0, 0]), 1);
+
+        let hexagonal = parse(
+            "@RULE Hex\n@TABLE\nn_states:2\nneighborhood:hexagonal\nsymmetries:rotate3\n0,1,0,0,0,0,0,1",
+        )
+        .unwrap();
+        assert_eq!(hexagonal.outputs.len(), 3);
+        assert_eq!(hexagonal.next_state(0, &[0, 0, 1, 0, 0, 0]), 1);
+        assert_eq!(hexagonal.next_state(0, &[0, 1, 0, 0, 0, 0]), 0);
+    }
+
+    #[test]
+    fn reports_lines_of_errors() {
+        let cases = [
+            ("@TABLE\nn_states:2", 1, "expected @RULE"),
+            (
+                "@RULE A\n@TABLE\n0,0,0,0,0,0,0,0,0,1",
+                3,
+                "n_states must come",
+            ),
+            (
+                "@RULE A\n@TABLE\nn_states:2\n0,0,0,0,0,0,0,0,1",
+                4,
+                "expected 10 states",
+            ),
+            (
+                "@RULE A\n@TABLE\nn_states:2\n0,x,0,0,0,0,0,0,0,1",
+                4,
+                "unknown variable 'x'",
+            ),
+            (
+                "@RULE A\n@TABLE\nn_states:2\n0,0,0,0,0,0,0,0,0,2",
+                4,
+                "expected a state 0-1",
+            ),
+            (
+                "@RULE A\n@TABLE\nn_states:2\nvar a={0,1}\n0,0,0,0,0,0,0,0,0,a",
+                5,
+                "doesn't appear",
+            ),
+            (
+                "@RULE A\n@TABLE\nn_states:2\nsymmetries:rotate6\n",
+                4,
+                "unsupported symmetries",
+            ),
+            (
+                "@RULE A\n@TREE\nnum_states=2",
+                2,
+                "@TREE rules are not supported",
+            ),
+            ("@RULE A\n@COLORS\n1 255 0", 3, "expected 'state r g b'"),
+        ];
+        for (text, line, message) in cases {
+            let error = parse(text).unwrap_err();
+            assert_eq!(error.line, line, "{text}: {error}");
+            assert!(error.message.contains(message), "{text}: {error}");
+        }
+    }
+}
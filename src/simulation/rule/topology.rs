@@ -0,0 +1,261 @@
+// Bounded grids of Golly's rule suffixes, `:T100,50` is a 100x50 torus
+
+use crate::simulation::rule::{Chars, RuleParseError, expect, parse_number};
+use std::fmt;
+use std::str::FromStr;
+
+/// Largest side of a bounded grid
+pub const MAX_SIZE: u32 = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TopologyKind {
+    // `P`, cells outside of the grid are dead
+    Plane,
+    // `T`, opposite edges are joined
+    Torus,
+    // `K`, like a torus but one pair of edges is joined with a twist
+    // `K100*,50` twists the top and bottom edges, crossing them mirrors the column
+    // `K100,50*` twists the left and right edges, crossing them mirrors the row
+    KleinBottle { twisted_columns: bool },
+    // `C`, both pairs of edges are joined with a twist
+    CrossSurface,
+    // `S`, square, the top edge is joined to the left one and the bottom edge to the right one
+    Sphere,
+}
+
+/// Size and edges of a bounded grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Topology {
+    kind: TopologyKind,
+    width: u32,
+    height: u32,
+}
+
+impl Topology {
+    pub fn kind(&self) -> TopologyKind {
+        self.kind
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Cell of the grid that the position stands for, `None` if it is always dead
+    pub fn wrap(&self, x: i64, y: i64) -> Option<(i64, i64)> {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let contains = |(x, y): (i64, i64)| (0..width).contains(&x) && (0..height).contains(&y);
+        if contains((x, y)) {
+            return Some((x, y));
+        }
+
+        // Times the left or right and the top or bottom edges are crossed
+        let (crossings_x, crossings_y) = (x.div_euclid(width), y.div_euclid(height));
+        let (wrapped_x, wrapped_y) = (x.rem_euclid(width), y.rem_euclid(height));
+        let mirrored_x = width - 1 - wrapped_x;
+        let mirrored_y = height - 1 - wrapped_y;
+        let (odd_x, odd_y) = (crossings_x % 2 != 0, crossings_y % 2 != 0);
+        let wrapped = match self.kind {
+            TopologyKind::Plane => return None,
+            TopologyKind::Torus => (wrapped_x, wrapped_y),
+            TopologyKind::KleinBottle {
+                twisted_columns: true,
+            } => (if odd_y { mirrored_x } else { wrapped_x }, wrapped_y),
+            TopologyKind::KleinBottle {
+                twisted_columns: false,
+            } => (wrapped_x, if odd_x { mirrored_y } else { wrapped_y }),
+            TopologyKind::CrossSurface => (
+                if odd_y { mirrored_x } else { wrapped_x },
+                if odd_x { mirrored_y } else { wrapped_y },
+            ),
+            // Rows above the grid are the columns left of it, rows below are right of it
+            TopologyKind::Sphere => match (x, y) {
+                _ if (0..width).contains(&x) && y < 0 => (-y - 1, x),
+                _ if (0..width).contains(&x) => (width - 1 - (y - height), x),
+                _ if (0..height).contains(&y) && x < 0 => (y, -x - 1),
+                _ if (0..height).contains(&y) => (y, height - 1 - (x - width)),
+                // Beyond the corners
+                _ => return None,
+            },
+        };
+        Some(wrapped).filter(|&wrapped| contains(wrapped))
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (width, height) = (self.width, self.height);
+        match self.kind {
+            TopologyKind::Plane => write!(f, ":P{width},{height}"),
+            TopologyKind::Torus => write!(f, ":T{width},{height}"),
+            TopologyKind::KleinBottle {
+                twisted_columns: true,
+            } => write!(f, ":K{width}*,{height}"),
+            TopologyKind::KleinBottle {
+                twisted_columns: false,
+            } => write!(f, ":K{width},{height}*"),
+            TopologyKind::CrossSurface => write!(f, ":C{width},{height}"),
+            TopologyKind::Sphere => write!(f, ":S{width}"),
+        }
+    }
+}
+
+impl FromStr for Topology {
+    type Err = RuleParseError;
+
+    /// `:T100,50`, the suffix without the rule
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<(usize, char)> = text
+            .chars()
+            .enumerate()
+            .map(|(index, character)| (index + 1, character))
+            .collect();
+        parse(text, &chars)
+    }
+}
+
+/// Splits the topology suffix off a rule table name or path, `Wireworld:T100,50`
+pub fn split_suffix(text: &str) -> Result<(&str, Option<Topology>), RuleParseError> {
+    match text.rfind(':') {
+        Some(colon) => Ok((&text[..colon], Some(text[colon..].parse()?))),
+        None => Ok((text, None)),
+    }
+}
+
+/// `:T100,50` after the rule, `chars` start at the colon
+///
+/// Klein bottles mark the twisted pair of edges with `*`, spheres only have one side, `:S100`.
+pub(super) fn parse(text: &str, chars: &[(usize, char)]) -> Result<Topology, RuleParseError> {
+    let end_column = chars.last().map_or(1, |&(column, _)| column + 1);
+    let mut chars = chars.iter().copied().peekable();
+
+    expect(text, &mut chars, end_column, ':')?;
+    let kind = match chars.next() {
+        Some((_, 'P' | 'p')) => TopologyKind::Plane,
+        Some((_, 'T' | 't')) => TopologyKind::Torus,
+        Some((_, 'K' | 'k')) => TopologyKind::KleinBottle {
+            twisted_columns: true,
+        },
+        Some((_, 'C' | 'c')) => TopologyKind::CrossSurface,
+        Some((_, 'S' | 's')) => TopologyKind::Sphere,
+        Some((column, character)) => {
+            return Err(RuleParseError::unexpected(
+                text,
+                column,
+                character,
+                "expected 'P', 'T', 'K', 'C' or 'S'",
+            ));
+        }
+        None => {
+            return Err(RuleParseError::new(
+                text,
+                end_column,
+                "expected 'P', 'T', 'K', 'C' or 'S'",
+            ));
+        }
+    };
+
+    let width = parse_size(text, &mut chars, end_column)?;
+    let width_twisted = chars.next_if(|&(_, character)| character == '*').is_some();
+    let (height, height_twisted) = if kind == TopologyKind::Sphere {
+        (width, false)
+    } else {
+        expect(text, &mut chars, end_column, ',')?;
+        let height = parse_size(text, &mut chars, end_column)?;
+        (
+            height,
+            chars.next_if(|&(_, character)| character == '*').is_some(),
+        )
+    };
+    if let Some((column, character)) = chars.next() {
+        return Err(RuleParseError::unexpected(
+            text,
+            column,
+            character,
+            "expected the end of the rule",
+        ));
+    }
+
+    let kind = match kind {
+        TopologyKind::KleinBottle { .. } if width_twisted != height_twisted => {
+            TopologyKind::KleinBottle {
+                twisted_columns: width_twisted,
+            }
+        }
+        TopologyKind::KleinBottle { .. } => {
+            return Err(RuleParseError::new(
+                text,
+                end_column,
+                "a Klein bottle needs '*' after either the width or the height",
+            ));
+        }
+        _ if width_twisted || height_twisted => {
+            return Err(RuleParseError::new(
+                text,
+                end_column,
+                "only Klein bottles have twisted edges",
+            ));
+        }
+        kind => kind,
+    };
+    Ok(Topology {
+        kind,
+        width,
+        height,
+    })
+}
+
+fn parse_size(text: &str, chars: &mut Chars, end_column: usize) -> Result<u32, RuleParseError> {
+    let (size, column) = parse_number(text, chars, end_column)?;
+    if !(1..=MAX_SIZE).contains(&size) {
+        return Err(RuleParseError::new(
+            text,
+            column,
+            format!("size must be in 1..={MAX_SIZE}"),
+        ));
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_positions_around_the_edges() {
+        let topology = |text: &str| text.parse::<Topology>().unwrap();
+
+        let plane = topology(":P4,3");
+        assert_eq!(plane.wrap(3, 2), Some((3, 2)));
+        assert_eq!(plane.wrap(-1, 0), None);
+
+        let torus = topology(":T4,3");
+        assert_eq!(torus.wrap(-1, 0), Some((3, 0)));
+        assert_eq!(torus.wrap(4, 3), Some((0, 0)));
+        assert_eq!(torus.wrap(-5, -4), Some((3, 2)));
+
+        // Crossing the top or bottom edge mirrors the column
+        let klein_bottle = topology(":K4*,3");
+        assert_eq!(klein_bottle.wrap(0, -1), Some((3, 2)));
+        assert_eq!(klein_bottle.wrap(-1, 1), Some((3, 1)));
+        assert_eq!(klein_bottle.wrap(1, 6), Some((1, 0)));
+        // Crossing the left or right edge mirrors the row
+        let klein_bottle = topology(":K4,3*");
+        assert_eq!(klein_bottle.wrap(-1, 0), Some((3, 2)));
+        assert_eq!(klein_bottle.wrap(1, 3), Some((1, 0)));
+
+        let cross_surface = topology(":C4,3");
+        assert_eq!(cross_surface.wrap(-1, -1), Some((0, 0)));
+        assert_eq!(cross_surface.wrap(4, 0), Some((0, 2)));
+
+        let sphere = topology(":S4");
+        assert_eq!(sphere.wrap(2, -1), Some((0, 2)));
+        assert_eq!(sphere.wrap(-1, 2), Some((2, 0)));
+        assert_eq!(sphere.wrap(1, 4), Some((3, 1)));
+        assert_eq!(sphere.wrap(4, 1), Some((1, 3)));
+        assert_eq!(sphere.wrap(-1, -1), None);
+    }
+}
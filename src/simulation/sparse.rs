@@ -0,0 +1,388 @@
+// Unbounded universe of 64x64 tiles in a hash map
+// Tiles are created where live cells can spill over their edges and freed once all their cells
+// are dead, so the universe grows and shrinks with the pattern in any direction
+
+use crate::simulation::hashlife::HashLife;
+use crate::simulation::rule::{NEIGHBOURS, Rule};
+use crate::simulation::{ALIVE, DEAD, Universe};
+use std::collections::{HashMap, HashSet};
+
+/// Side of a tile in cells
+pub const TILE_SIZE: usize = 64;
+const TILE_SHIFT: u32 = TILE_SIZE.trailing_zeros();
+// Tile with a border of the cells of its neighbours
+const PADDED_SIZE: usize = TILE_SIZE + 2;
+
+// Row-major cells of a tile
+type Tile = Box<[u8; TILE_SIZE * TILE_SIZE]>;
+
+#[derive(Debug, Clone)]
+pub struct SparseUniverse {
+    // Tile (tx, ty) covers cells tx * 64..(tx + 1) * 64, ty * 64..(ty + 1) * 64
+    // Tile coordinates wrap around at the i64 limits
+    tiles: HashMap<(i64, i64), Tile>,
+    // Non-dead cells
+    population: u64,
+    generation: u64,
+    rule: Rule,
+}
+
+impl Default for SparseUniverse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SparseUniverse {
+    pub fn new() -> Self {
+        Self {
+            tiles: HashMap::new(),
+            population: 0,
+            generation: 0,
+            rule: Rule::LIFE,
+        }
+    }
+
+    pub fn from_universe(universe: &Universe) -> Self {
+        let mut sparse_universe = Self::new();
+        for (x, y, state) in universe.live_cells() {
+            sparse_universe.set_cell(x, y, state);
+        }
+        sparse_universe.generation = universe.generation();
+        sparse_universe.set_rule(universe.rule().clone());
+        sparse_universe
+    }
+
+    /// Copies the whole plane, HashLife keeps cells outside of the universe
+    pub fn from_hashlife(hashlife: &HashLife) -> Self {
+        let mut sparse_universe = Self::new();
+        hashlife.for_each_live_cell_in(
+            (i64::MIN, i64::MIN),
+            (i64::MAX, i64::MAX),
+            &mut |x, y, state| sparse_universe.set_cell(x, y, state),
+        );
+        sparse_universe.generation = hashlife.generation();
+        sparse_universe.set_rule(hashlife.rule().clone());
+        sparse_universe
+    }
+
+    /// Replaces the universe contents, cells outside of it are dropped
+    pub fn write_to(&self, universe: &mut Universe) {
+        universe.clear();
+        let (width, height) = (universe.width() as i64, universe.height() as i64);
+        self.for_each_live_cell_in((0, 0), (width, height), &mut |x, y, state| {
+            universe.set_cell(x, y, state)
+        });
+        universe.set_generation(self.generation);
+        universe.set_rule(self.rule.clone());
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    pub fn population(&self) -> u64 {
+        self.population
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// B0 rules are not supported, empty space must stay empty
+    /// Neither are Larger than Life rules, rule tables and bounded grids
+    pub fn supports(rule: &Rule) -> bool {
+        !rule.has_b0()
+            && rule.larger_than_life().is_none()
+            && rule.rule_table().is_none()
+            && rule.topology().is_none()
+    }
+
+    /// See `supports`
+    pub fn set_rule(&mut self, rule: Rule) {
+        assert!(
+            Self::supports(&rule),
+            "The sparse universe can't run rule {rule}"
+        );
+        self.rule = rule;
+    }
+
+    pub fn get_cell(&self, x: i64, y: i64) -> u8 {
+        let (tile_position, index) = split_position(x, y);
+        self.tiles
+            .get(&tile_position)
+            .map_or(DEAD, |tile| tile[index])
+    }
+
+    /// Tiles are created on demand and freed once they only have dead cells
+    pub fn set_cell(&mut self, x: i64, y: i64, state: u8) {
+        let (tile_position, index) = split_position(x, y);
+        if state != DEAD {
+            let tile = self
+                .tiles
+                .entry(tile_position)
+                .or_insert_with(|| Box::new([DEAD; TILE_SIZE * TILE_SIZE]));
+            self.population += (tile[index] == DEAD) as u64;
+            tile[index] = state;
+        } else if let Some(tile) = self.tiles.get_mut(&tile_position) {
+            self.population -= (tile[index] != DEAD) as u64;
+            tile[index] = DEAD;
+            if tile.iter().all(|&state| state == DEAD) {
+                self.tiles.remove(&tile_position);
+            }
+        }
+    }
+
+    /// Calls `f(x, y, state)` for every non-dead cell within [min, max)
+    pub fn for_each_live_cell_in(
+        &self,
+        min: (i64, i64),
+        max: (i64, i64),
+        f: &mut impl FnMut(i64, i64, u8),
+    ) {
+        if min.0 >= max.0 || min.1 >= max.1 {
+            return;
+        }
+        let (min_tile, _) = split_position(min.0, min.1);
+        let (max_tile, _) = split_position(max.0 - 1, max.1 - 1);
+        let tile_columns = max_tile.0.abs_diff(min_tile.0).saturating_add(1);
+        let tile_rows = max_tile.1.abs_diff(min_tile.1).saturating_add(1);
+
+        let mut visit = |&(tile_x, tile_y): &(i64, i64), tile: &Tile| {
+            let (left, top) = (tile_x << TILE_SHIFT, tile_y << TILE_SHIFT);
+            for (index, &state) in tile.iter().enumerate() {
+                let x = left + (index % TILE_SIZE) as i64;
+                let y = top + (index / TILE_SIZE) as i64;
+                if state != DEAD && (min.0..max.0).contains(&x) && (min.1..max.1).contains(&y) {
+                    f(x, y, state);
+                }
+            }
+        };
+        // Small regions look their tiles up, large ones go through all tiles
+        if tile_columns.saturating_mul(tile_rows) < self.tiles.len() as u64 {
+            for tile_y in min_tile.1..=max_tile.1 {
+                for tile_x in min_tile.0..=max_tile.0 {
+                    if let Some(tile) = self.tiles.get(&(tile_x, tile_y)) {
+                        visit(&(tile_x, tile_y), tile);
+                    }
+                }
+            }
+        } else {
+            for (tile_position, tile) in &self.tiles {
+                let (tile_x, tile_y) = *tile_position;
+                if (min_tile.0..=max_tile.0).contains(&tile_x)
+                    && (min_tile.1..=max_tile.1).contains(&tile_y)
+                {
+                    visit(tile_position, tile);
+                }
+            }
+        }
+    }
+
+    /// Live cells of the whole plane, for small patterns
+    pub fn live_cells(&self) -> Vec<(i64, i64, u8)> {
+        let mut cells = Vec::new();
+        self.for_each_live_cell_in(
+            (i64::MIN, i64::MIN),
+            (i64::MAX, i64::MAX),
+            &mut |x, y, state| cells.push((x, y, state)),
+        );
+        cells.sort_unstable();
+        cells
+    }
+
+    /// Advances the universe by one generation
+    pub fn step(&mut self) {
+        // Stored tiles and the neighbours that their live edge cells can give birth in
+        let mut active_tiles = HashSet::with_capacity(self.tiles.len() * 2);
+        for (&(tile_x, tile_y), tile) in &self.tiles {
+            active_tiles.insert((tile_x, tile_y));
+            for (dx, dy) in NEIGHBOURS {
+                if has_live_cells_at_edge(tile, dx, dy) {
+                    active_tiles.insert((tile_x.wrapping_add(dx), tile_y.wrapping_add(dy)));
+                }
+            }
+        }
+
+        let mut next_tiles = HashMap::with_capacity(active_tiles.len());
+        let mut population = 0;
+        let mut padded = [DEAD; PADDED_SIZE * PADDED_SIZE];
+        for tile_position in active_tiles {
+            self.fill_padded(tile_position, &mut padded);
+            let mut next_tile: Tile = Box::new([DEAD; TILE_SIZE * TILE_SIZE]);
+            let mut tile_population = 0;
+            for y in 0..TILE_SIZE {
+                for x in 0..TILE_SIZE {
+                    let padded_index = (y + 1) * PADDED_SIZE + x + 1;
+                    let mut neighbours = 0;
+                    for (bit, (dx, dy)) in NEIGHBOURS.into_iter().enumerate() {
+                        let neighbour_index = padded_index
+                            .wrapping_add_signed(dy as isize * PADDED_SIZE as isize + dx as isize);
+                        // Decaying cells of Generations rules are not live neighbours
+                        if padded[neighbour_index] == ALIVE {
+                            neighbours |= 1 << bit;
+                        }
+                    }
+                    let state = self.rule.next_state(padded[padded_index], neighbours);
+                    next_tile[y * TILE_SIZE + x] = state;
+                    tile_population += (state != DEAD) as u64;
+                }
+            }
+            if tile_population > 0 {
+                next_tiles.insert(tile_position, next_tile);
+                population += tile_population;
+            }
+        }
+        self.tiles = next_tiles;
+        self.population = population;
+        self.generation += 1;
+    }
+
+    // Copies the tile and the adjacent cells of its neighbours into `padded`
+    fn fill_padded(
+        &self,
+        (tile_x, tile_y): (i64, i64),
+        padded: &mut [u8; PADDED_SIZE * PADDED_SIZE],
+    ) {
+        padded.fill(DEAD);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let Some(tile) = self
+                    .tiles
+                    .get(&(tile_x.wrapping_add(dx), tile_y.wrapping_add(dy)))
+                else {
+                    continue;
+                };
+                // Cells of the tile that fall into the padded area, in padded coordinates
+                let columns = padded_range(dx);
+                let rows = padded_range(dy);
+                for padded_y in rows {
+                    let y = (padded_y as i64 - 1 - dy * TILE_SIZE as i64) as usize;
+                    for padded_x in columns.clone() {
+                        let x = (padded_x as i64 - 1 - dx * TILE_SIZE as i64) as usize;
+                        padded[padded_y * PADDED_SIZE + padded_x] = tile[y * TILE_SIZE + x];
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Tile position and the index of the cell in it
+fn split_position(x: i64, y: i64) -> ((i64, i64), usize) {
+    let mask = TILE_SIZE as i64 - 1;
+    (
+        (x >> TILE_SHIFT, y >> TILE_SHIFT),
+        (y & mask) as usize * TILE_SIZE + (x & mask) as usize,
+    )
+}
+
+// Padded coordinates covered by the neighbour tile at offset `d` along one axis
+fn padded_range(d: i64) -> std::ops::Range<usize> {
+    match d {
+        -1 => 0..1,
+        0 => 1..TILE_SIZE + 1,
+        _ => TILE_SIZE + 1..PADDED_SIZE,
+    }
+}
+
+// Live cells on the edge or corner of the tile facing the neighbour at (dx, dy)
+fn has_live_cells_at_edge(tile: &Tile, dx: i64, dy: i64) -> bool {
+    let edge = |d: i64| match d {
+        -1 => 0..1,
+        0 => 0..TILE_SIZE,
+        _ => TILE_SIZE - 1..TILE_SIZE,
+    };
+    edge(dy).any(|y| edge(dx).any(|x| tile[y * TILE_SIZE + x] == ALIVE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supports_unbounded_range_one_rules() {
+        for rule in ["B3/S23", "B3/S2-i34q", "B2/S/C3", "B2/S34H"] {
+            assert!(SparseUniverse::supports(&rule.parse().unwrap()), "{rule}");
+        }
+        for rule in [
+            "B0/S8",
+            "R5,C0,M1,S34..58,B34..45,NM",
+            "B3/S23:T10,10",
+            "B3/S23:P10,10",
+        ] {
+            assert!(!SparseUniverse::supports(&rule.parse().unwrap()), "{rule}");
+        }
+    }
+
+    #[test]
+    fn matches_bounded_universe() {
+        // R-pentomino, it stays within the bounded universe for 200 generations
+        let r_pentomino = [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)];
+        for rule in ["B3/S23", "B2/S/C3", "B2/S34H", "B2/S013V"] {
+            let rule: Rule = rule.parse().unwrap();
+            let mut universe = Universe::new(256, 256);
+            universe.set_rule(rule.clone());
+            for (x, y) in r_pentomino {
+                // Across the corner of four tiles
+                universe.set_cell(x + 126, y + 126, ALIVE);
+            }
+            let mut sparse_universe = SparseUniverse::from_universe(&universe);
+            for _ in 0..60 {
+                universe.step();
+                sparse_universe.step();
+            }
+            let mut cells: Vec<_> = universe.live_cells().collect();
+            cells.sort_unstable();
+            assert_eq!(sparse_universe.live_cells(), cells, "{rule}");
+            assert_eq!(sparse_universe.population(), universe.population() as u64);
+        }
+    }
+
+    #[test]
+    fn glider_travels_far_from_the_origin() {
+        let mut sparse_universe = SparseUniverse::new();
+        let far = 1_000_000_000_000;
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        for (x, y) in glider {
+            sparse_universe.set_cell(x - far, y - far, ALIVE);
+        }
+
+        // A glider moves one cell diagonally every 4 generations
+        for _ in 0..4 * 200 {
+            sparse_universe.step();
+        }
+        let mut moved: Vec<_> = glider
+            .iter()
+            .map(|&(x, y)| (x - far + 200, y - far + 200, ALIVE))
+            .collect();
+        moved.sort_unstable();
+        assert_eq!(sparse_universe.live_cells(), moved);
+        // Tiles behind the glider are freed
+        assert!(sparse_universe.tile_count() <= 4);
+    }
+
+    #[test]
+    fn empty_tiles_are_freed() {
+        let mut sparse_universe = SparseUniverse::new();
+        sparse_universe.set_cell(-1, -1, ALIVE);
+        sparse_universe.set_cell(64, 64, ALIVE);
+        assert_eq!(sparse_universe.tile_count(), 2);
+        assert_eq!(sparse_universe.get_cell(-1, -1), ALIVE);
+        sparse_universe.set_cell(-1, -1, DEAD);
+        assert_eq!(sparse_universe.tile_count(), 1);
+
+        // A lone cell dies and takes its tile with it
+        sparse_universe.step();
+        assert_eq!(sparse_universe.tile_count(), 0);
+        assert_eq!(sparse_universe.population(), 0);
+    }
+}